@@ -3,6 +3,7 @@ use std::collections::vec_deque::VecDeque;
 use specs::{self, Entity};
 
 use pk::AutoResource;
+use pk::globe::Spec;
 
 use ::player::{Player, PlayerId};
 
@@ -11,6 +12,9 @@ use ::player::{Player, PlayerId};
 /// by clients as they are informed about the state of the world.
 pub struct GameState {
     pub globe_entity: Option<Entity>,
+    // What the globe looks like, so the server can
+    // tell new clients about it.
+    pub globe_spec: Option<Spec>,
     // TODO: this should probably not be a Vec;
     // in practice we can be pretty sure clients will
     // hear about new players in order, but it's still not
@@ -27,6 +31,7 @@ impl AutoResource for GameState {
     fn new(_world: &mut specs::World) -> GameState {
         GameState {
             globe_entity: None,
+            globe_spec: None,
             players: Vec::<Player>::new(),
             new_players: VecDeque::<PlayerId>::new(),
         }
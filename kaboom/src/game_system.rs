@@ -11,7 +11,7 @@ use pk::net::{PeerId, NetworkPeers, Destination, Transport, SendMessageQueue, Se
 use ::player::{self, Player, PlayerId, PlayerMessage};
 use ::game_state::GameState;
 use ::client_state::ClientState;
-use ::planet;
+use ::planet::{self, PlanetMessage};
 use ::fighter;
 use ::message::Message;
 
@@ -30,6 +30,7 @@ impl GameSystem {
         GameState::ensure(world);
         ClientState::ensure(world);
         player::RecvMessageQueue::ensure(world);
+        planet::RecvMessageQueue::ensure(world);
 
         GameSystem {
             log: parent_log.new(o!("system" => "game"))
@@ -89,6 +90,7 @@ impl<'a> specs::System<'a> for GameSystem {
         FetchMut<'a, NetworkPeers<Message>>,
         FetchMut<'a, SendMessageQueue<Message>>,
         FetchMut<'a, player::RecvMessageQueue>,
+        FetchMut<'a, planet::RecvMessageQueue>,
     );
 
     fn run(&mut self, data: Self::SystemData) {
@@ -104,8 +106,31 @@ impl<'a> specs::System<'a> for GameSystem {
             mut network_peers,
             mut send_message_queue,
             mut player_recv_message_queue,
+            mut planet_recv_message_queue,
         ) = data;
 
+        while let Some(message) = planet_recv_message_queue.queue.pop_front() {
+            match message.game_message {
+                PlanetMessage::Spec(spec) => {
+                    // Master decides what the planet looks like;
+                    // it should never be told.
+                    // TODO: don't explode; see `PlayerMessage::NewPlayer` below.
+                    assert!(!client_state.is_master);
+
+                    if game_state.globe_entity.is_some() {
+                        info!(self.log, "Heard about the planet again; ignoring it");
+                        continue;
+                    }
+
+                    info!(self.log, "Heard about the planet; making our own copy");
+                    game_state.globe_entity = Some(
+                        planet::create(&entities, &updater, spec)
+                    );
+                    game_state.globe_spec = Some(spec);
+                },
+            }
+        }
+
         while let Some(message) = player_recv_message_queue.queue.pop_front() {
             match message.game_message {
                 PlayerMessage::NewPlayer(player_id) => {
@@ -156,11 +181,35 @@ impl<'a> specs::System<'a> for GameSystem {
             self.create_and_broadcast_player(&mut game_state, &mut send_message_queue, PeerId(0));
         }
 
+        // Only the master makes up the planet; clients wait to hear about it.
+        if client_state.is_master && game_state.globe_entity.is_none() {
+            // Create the globe first, because we'll need it to figure out where
+            // to place the player character.
+            let spec = planet::default_spec();
+            game_state.globe_entity = Some(
+                planet::create(&entities, &updater, spec)
+            );
+            game_state.globe_spec = Some(spec);
+        }
+
         // If there are any new network peers, then pop them off
         // and maybe do something with them.
         while let Some(new_peer_id) = network_peers.new_peers.pop_front() {
             // As a client, we don't care; we just want to clean out the list.
             if client_state.is_master {
+                // Tell the new peer what the planet looks like,
+                // so that it can make its own copy.
+                let globe_spec = game_state.globe_spec.expect("Master should have made the planet by now");
+                send_message_queue.queue.push_back(
+                    SendMessage {
+                        destination: Destination::One(new_peer_id),
+                        game_message: Message::Planet(
+                            PlanetMessage::Spec(globe_spec)
+                        ),
+                        transport: Transport::TCP,
+                    }
+                );
+
                 // Tell the new peer about all existing players.
                 for player in &game_state.players {
                     send_message_queue.queue.push_back(
@@ -183,16 +232,6 @@ impl<'a> specs::System<'a> for GameSystem {
             // and then which player is theirs.
         }
 
-        // TODO: eventually only the server should create this,
-        // and then describe it to clients.
-        if game_state.globe_entity.is_none() {
-            // Create the globe first, because we'll need it to figure out where
-            // to place the player character.
-            game_state.globe_entity = Some(
-                planet::create(&entities, &updater)
-            );
-        }
-
         // Create a new character for each new player.
         if client_state.is_master {
             if let Some(globe_entity) = game_state.globe_entity {
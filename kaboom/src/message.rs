@@ -3,10 +3,12 @@ use pk::net::GameMessage;
 use pk::cell_dweller::CellDwellerMessage;
 
 use ::player::PlayerMessage;
+use ::planet::PlanetMessage;
 
-#[derive(Serialize, Deserialize, Debug, Eq, PartialEq, Clone)]
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
 pub enum Message {
     CellDweller(CellDwellerMessage),
     Player(PlayerMessage),
+    Planet(PlanetMessage),
 }
 impl GameMessage for Message{}
@@ -1,36 +1,67 @@
+use std::collections::vec_deque::VecDeque;
+
 use specs;
 use specs::{Fetch, LazyUpdate, Entities};
 
 use pk;
-use pk::globe::{Globe, Spec};
+use pk::AutoResource;
+use pk::globe::{Globe, Spec, SpecBuilder, Gen};
+use pk::net::RecvMessage;
 
-// Create a planet to fight on.
-pub fn create(
-    entities: &Entities,
-    updater: &Fetch<LazyUpdate>,
-) -> specs::Entity {
+// What the server's planet looks like. Clients find out from the server
+// rather than calling this, so they're sure to agree with it.
+pub fn default_spec() -> Spec {
     // Make it small enough that you can find another person easily enough.
     // TODO: eventually make it scale to the number of players present at the start of each round.
     // TODO: special generator for this; you want to have lava beneath the land
     let ocean_radius = 30.0;
     let crust_depth = 25.0;
     let floor_radius = ocean_radius - crust_depth;
-    let spec = Spec {
+    SpecBuilder::new()
         // TODO: random seed every time.
-        seed: 14,
-        floor_radius: floor_radius,
-        ocean_radius: ocean_radius,
-        block_height: 0.65,
+        .seed(14)
+        .floor_radius(floor_radius)
+        .ocean_radius(ocean_radius)
+        .block_height(0.65)
         // TODO: calculate this (experimentally if necessary) based on the size of the blocks you want
-        root_resolution: [64, 128],
+        .root_resolution([64, 128])
         // Chunks should probably be taller, but short chunks are a bit
         // better for now in exposing bugs visually.
-        chunk_resolution: [16, 16, 4],
-    };
-    let globe = Globe::new(spec);
+        .chunk_resolution([16, 16, 4])
+        .build()
+        .expect("Planet spec should be valid")
+}
+
+// Create a planet to fight on.
+pub fn create(
+    entities: &Entities,
+    updater: &Fetch<LazyUpdate>,
+    spec: Spec,
+) -> specs::Entity {
+    let globe = Globe::new(spec, Box::new(Gen::new(spec)));
 
     let entity = entities.create();
     updater.insert(entity, globe);
     updater.insert(entity, pk::Spatial::new_root());
     entity
 }
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+pub enum PlanetMessage {
+    // Tell a client what the planet looks like,
+    // so it can make its own copy of it.
+    Spec(Spec),
+}
+
+/// `World`-global resource for inbound planet-related network messages.
+pub struct RecvMessageQueue {
+    pub queue: VecDeque<RecvMessage<PlanetMessage>>,
+}
+
+impl AutoResource for RecvMessageQueue {
+    fn new(_world: &mut specs::World) -> RecvMessageQueue {
+        RecvMessageQueue {
+            queue: VecDeque::new(),
+        }
+    }
+}
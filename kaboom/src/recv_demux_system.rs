@@ -10,6 +10,7 @@ use pk::net::{
 
 use ::message::Message;
 use ::player;
+use ::planet;
 
 pub struct RecvDemuxSystem{
     log: Logger,
@@ -28,6 +29,7 @@ impl<'a> specs::System<'a> for RecvDemuxSystem {
         FetchMut<'a, RecvMessageQueue<Message>>,
         FetchMut<'a, cell_dweller::RecvMessageQueue>,
         FetchMut<'a, player::RecvMessageQueue>,
+        FetchMut<'a, planet::RecvMessageQueue>,
     );
 
     fn run(&mut self, data: Self::SystemData) {
@@ -35,6 +37,7 @@ impl<'a> specs::System<'a> for RecvDemuxSystem {
             mut recv_message_queue,
             mut cell_dweller_recv_queue,
             mut player_recv_queue,
+            mut planet_recv_queue,
         ) = data;
 
         // Drain the recv message queue, and dispatch to system-specific queues.
@@ -56,6 +59,14 @@ impl<'a> specs::System<'a> for RecvDemuxSystem {
                         }
                     );
                 },
+                Message::Planet(planet_message) => {
+                    trace!(self.log, "Forwarding planet message to its recv message queue"; "message" => format!("{:?}", planet_message));
+                    planet_recv_queue.queue.push_back(
+                        RecvMessage {
+                            game_message: planet_message,
+                        }
+                    );
+                },
             }
         }
     }
@@ -6,9 +6,8 @@ use piston::input::Input;
 
 use super::{CellDweller, ActiveCellDweller};
 use movement::*;
-use grid::PosInOwningRoot;
 use globe::Globe;
-use globe::chunk::Material;
+use globe::MaterialId;
 use input_adapter;
 
 // TODO: own file?
@@ -80,11 +79,7 @@ impl MiningSystem {
     }
 
     fn pick_up_if_possible(&self, cd: &mut CellDweller, globe: &mut Globe) {
-        use globe::is_point_on_chunk_edge;
-
         // Only allow picking stuff up if you're sitting above solid ground.
-        // (Or, rather, the stuff we consider to be solid for now,
-        // which is anything other than air.)
         //
         // TODO: abstract this whole thing... you need some kind of
         // utilities for a globe.
@@ -95,9 +90,10 @@ impl MiningSystem {
         let under_pos = cd.pos.with_z(cd.pos.z - 1);
         {
             // Inner scope to fight borrowck.
-            let under_cell = globe.maybe_non_authoritative_cell(under_pos);
-            if under_cell.material != Material::Dirt {
-                return;
+            match globe.maybe_non_authoritative_cell_if_ready(under_pos) {
+                Ok(under_cell) if globe.materials().is_solid(under_cell.material) => (),
+                // Either not solid, or we can't tell yet.
+                _ => return,
             }
         }
 
@@ -106,17 +102,21 @@ impl MiningSystem {
         let mut new_dir = cd.dir;
         move_forward(&mut new_pos, &mut new_dir, globe.spec().root_resolution)
             .expect("CellDweller should have been in good state.");
-        let anything_to_pick_up = {
-            let cell = globe.maybe_non_authoritative_cell(new_pos);
-            cell.material == Material::Dirt
+        // If any of the chunks involved aren't loaded yet, then
+        // we can't pick anything up yet.
+        let anything_to_pick_up = match globe.maybe_non_authoritative_cell_if_ready(new_pos) {
+            Ok(cell) => globe.materials().is_mineable(cell.material),
+            Err(_) => false,
         };
         // Also require that there's air above the block;
         // in my initial use case I don't want to allow mining below
         // the surface.
         let air_above_target = {
             let above_new_pos = new_pos.with_z(new_pos.z + 1);
-            let cell = globe.maybe_non_authoritative_cell(above_new_pos);
-            cell.material == Material::Air
+            match globe.maybe_non_authoritative_cell_if_ready(above_new_pos) {
+                Ok(cell) => globe.materials().is_empty(cell.material),
+                Err(_) => false,
+            }
         };
         let can_pick_up = anything_to_pick_up && air_above_target;
         if can_pick_up {
@@ -125,29 +125,13 @@ impl MiningSystem {
             // that make it super-easy to configure.
             // The goal here should be that the "block dude" game
             // ends up both concise and legible.
-            let new_pos_in_owning_root =
-                PosInOwningRoot::new(new_pos, globe.spec().root_resolution);
-            globe
-                .authoritative_cell_mut(new_pos_in_owning_root)
-                .material = Material::Air;
-            // Some extra stuff is only relevant if the cell is on the edge of its chunk.
-            if is_point_on_chunk_edge(
-                *new_pos_in_owning_root.pos(),
-                globe.spec().chunk_resolution,
-            )
-            {
-                // Bump version of owned shared cells.
-                globe.increment_chunk_owned_edge_version_for_cell(new_pos_in_owning_root);
-                // Propagate change to neighbouring chunks.
-                let chunk_origin = globe.origin_of_chunk_owning(new_pos_in_owning_root);
-                globe.push_shared_cells_for_chunk(chunk_origin);
+            let mut edit = globe.edit();
+            if edit.set_material(new_pos, MaterialId::AIR).is_err() {
+                // The chunk that owns the cell might be a different one
+                // to the one we just looked at, and it isn't loaded yet.
+                return;
             }
-            // Mark the view for the containing chunk and those containing each cell surrounding
-            // it as being dirty. (This cell might affect the visibility of cells in those chunks.)
-            // TODO: different API where you commit to changing a cell
-            // in a closure you get back that has a reference to it?
-            // Or contains a _wrapper_ around it so it knows if you mutated it? Ooooh.
-            globe.mark_chunk_views_affected_by_cell_as_dirty(new_pos);
+            edit.commit();
             // TODO: remember on the cell-dweller that it's carrying something?
             // Or should that be a different kind of component?
             debug!(self.log, "Picked up block"; "pos" => format!("{:?}", new_pos));
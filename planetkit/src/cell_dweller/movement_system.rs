@@ -15,7 +15,6 @@ use super::{
 use Spatial;
 use movement::*;
 use globe::Globe;
-use globe::chunk::Material;
 use input_adapter;
 use ::net::{
     SendMessage,
@@ -138,8 +137,6 @@ impl MovementSystem {
         forward_or_backward: ForwardOrBackward,
     ) {
         // Only allow movement if you're sitting above solid ground.
-        // (Or, rather, the stuff we consider to be solid for now,
-        // which is anything other than air.)
         //
         // TODO: Fix to be <= 0 and log error.
         if cd.pos.z < 0 {
@@ -147,9 +144,10 @@ impl MovementSystem {
             return;
         }
         let under_pos = cd.pos.with_z(cd.pos.z - 1);
-        let under_cell = globe.maybe_non_authoritative_cell(under_pos);
-        if under_cell.material != Material::Dirt {
-            return;
+        match globe.maybe_non_authoritative_cell_if_ready(under_pos) {
+            Ok(under_cell) if globe.materials().is_solid(under_cell.material) => (),
+            // Either not solid, or we can't tell yet.
+            _ => return,
         }
 
         // Find out whether we're actually allowed to step there.
@@ -180,8 +178,16 @@ impl MovementSystem {
         // Usually we'll allow climbing a maximum of one block, but especially in certain tests
         // we want to let you climb higher!
         for _ in 0..(self.max_step_height + 1) {
-            let cell = globe.maybe_non_authoritative_cell(new_pos);
-            let can_move_to_cell = cell.material != Material::Dirt;
+            let cell = match globe.maybe_non_authoritative_cell_if_ready(new_pos) {
+                Ok(cell) => cell,
+                Err(_) => {
+                    // The chunk isn't ready yet; stay put, and we'll
+                    // try again next time.
+                    trace!(self.log, "Waiting for chunk to be ready before stepping"; "pos" => format!("{:?}", new_pos));
+                    break;
+                }
+            };
+            let can_move_to_cell = !globe.materials().is_solid(cell.material);
 
             if !can_move_to_cell {
                 // Try again one higher.
@@ -6,7 +6,6 @@ use types::*;
 use super::CellDweller;
 use Spatial;
 use globe::Globe;
-use globe::chunk::Material;
 
 pub struct PhysicsSystem {
     log: Logger,
@@ -25,14 +24,19 @@ impl PhysicsSystem {
     // Note that "gravity" moves you down at a constant speed;
     // i.e. it doesn't accelerate you like in the real world.
     fn maybe_fall(&self, cd: &mut CellDweller, globe: &Globe, dt: TimeDelta) {
-        // Only make you fall if there's air below you.
+        // Only make you fall if there's nothing solid below you.
         if cd.pos.z <= 0 {
             // There's nothing below; someone built a silly globe.
             return;
         }
         let under_pos = cd.pos.with_z(cd.pos.z - 1);
-        let under_cell = globe.maybe_non_authoritative_cell(under_pos);
-        if under_cell.material == Material::Dirt {
+        let under_cell = match globe.maybe_non_authoritative_cell_if_ready(under_pos) {
+            Ok(cell) => cell,
+            // Don't fall into the unknown; hang in there until
+            // the chunk below has been loaded.
+            Err(_) => return,
+        };
+        if globe.materials().is_solid(under_cell.material) {
             // Reset time until we can fall to the time
             // between falls; we don't want to instantly
             // fall down every step of size 1.
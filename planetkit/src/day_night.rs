@@ -0,0 +1,216 @@
+//! Time of day, and where the sun is in the sky over each globe.
+
+use std::f64::consts::PI;
+
+use specs;
+use specs::{Fetch, FetchMut};
+use slog::Logger;
+
+use types::*;
+use grid::GridPoint2;
+use globe::Spec;
+use globe::icosahedron::{north_pole, prime_meridian};
+use AutoResource;
+
+/// How far through the day it is, shared by every globe.
+///
+/// Globes don't actually spin; instead, the sun circles around them once
+/// per day, in the plane of the equator. So the direction to the sun
+/// from the center of a globe changes over the course of a day, and is
+/// the same for every globe. Add a `DayNightSystem` to keep time moving.
+/// (This ignores any `Orbit` a globe might have, and where any actual
+/// sun entity is.)
+///
+/// Midnight and noon are as seen from the prime meridian, which runs from the
+/// north pole (the corner shared by all the root quads at `(0, 0)`) through
+/// the neighboring corner of root 0 at `(0, root_resolution[1])`.
+pub struct TimeOfDay {
+    /// How many seconds there are in a day.
+    pub day_length: TimeDelta,
+    // Seconds since midnight, always in `[0, day_length)`.
+    seconds_since_midnight: TimeDelta,
+}
+
+impl TimeOfDay {
+    /// Start at `fraction_of_day` through the day; see `set_fraction_of_day`.
+    pub fn new(day_length: TimeDelta, fraction_of_day: f64) -> TimeOfDay {
+        let mut time_of_day = TimeOfDay {
+            day_length: day_length,
+            seconds_since_midnight: 0.0,
+        };
+        time_of_day.set_fraction_of_day(fraction_of_day);
+        time_of_day
+    }
+
+    /// How far through the day it is, from 0 at midnight,
+    /// through 0.5 at noon, to just below 1 right before midnight again.
+    pub fn fraction_of_day(&self) -> f64 {
+        self.seconds_since_midnight / self.day_length
+    }
+
+    /// Skip straight to a given time of day; see `fraction_of_day`.
+    /// Values outside of `[0, 1)` wrap around into the previous or next day.
+    pub fn set_fraction_of_day(&mut self, fraction_of_day: f64) {
+        let seconds_since_midnight = fraction_of_day * self.day_length;
+        self.seconds_since_midnight = 0.0;
+        self.advance(seconds_since_midnight);
+    }
+
+    /// Move time forward by `dt` seconds.
+    pub fn advance(&mut self, dt: TimeDelta) {
+        let seconds = (self.seconds_since_midnight + dt) % self.day_length;
+        // Remainder keeps the sign of the dividend.
+        self.seconds_since_midnight = if seconds < 0.0 {
+            seconds + self.day_length
+        } else {
+            seconds
+        };
+    }
+
+    /// Unit vector pointing from the center of any globe towards the sun,
+    /// in the globe's own `Spatial` frame.
+    pub fn sun_direction(&self) -> Vec3 {
+        let north = north_pole();
+        // The sun moves from east to west, i.e. clockwise looking down
+        // on the north pole, and is right above the prime meridian at noon.
+        let angle = -2.0 * PI * (self.fraction_of_day() - 0.5);
+        Rot3::new(north * angle) * prime_meridian()
+    }
+
+    /// Sine of the angle of the sun above the horizon as seen from `column`
+    /// of a globe with the given `spec`. This is positive during the day,
+    /// and negative at night.
+    pub fn sun_height(&self, spec: &Spec, column: GridPoint2) -> f64 {
+        spec.cell_center_on_unit_sphere(column).coords.dot(
+            &self.sun_direction(),
+        )
+    }
+
+    /// Whether the sun is currently above the horizon as seen from `column`
+    /// of a globe with the given `spec`.
+    pub fn is_daylight(&self, spec: &Spec, column: GridPoint2) -> bool {
+        self.sun_height(spec, column) > 0.0
+    }
+}
+
+impl Default for TimeOfDay {
+    fn default() -> TimeOfDay {
+        // Ten minute days, starting in the morning.
+        TimeOfDay::new(600.0, 0.3)
+    }
+}
+
+impl AutoResource for TimeOfDay {
+    fn new(_world: &mut specs::World) -> TimeOfDay {
+        TimeOfDay::default()
+    }
+}
+
+/// Moves `TimeOfDay` forward every tick.
+pub struct DayNightSystem {
+    log: Logger,
+}
+
+impl DayNightSystem {
+    pub fn new(world: &mut specs::World, parent_log: &Logger) -> DayNightSystem {
+        TimeOfDay::ensure(world);
+        DayNightSystem { log: parent_log.new(o!("system" => "day_night")) }
+    }
+}
+
+impl<'a> specs::System<'a> for DayNightSystem {
+    type SystemData = (Fetch<'a, TimeDeltaResource>, FetchMut<'a, TimeOfDay>);
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (dt, mut time_of_day) = data;
+        let was_daytime = time_of_day.fraction_of_day() >= 0.25 &&
+            time_of_day.fraction_of_day() < 0.75;
+        time_of_day.advance(dt.0);
+        let is_daytime = time_of_day.fraction_of_day() >= 0.25 &&
+            time_of_day.fraction_of_day() < 0.75;
+        if was_daytime != is_daytime {
+            debug!(
+                self.log,
+                "Sun crossed the horizon at the prime meridian";
+                "is_daytime" => is_daytime
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use globe::Globe;
+    use super::*;
+
+    #[test]
+    fn time_wraps_around_each_day() {
+        let mut time_of_day = TimeOfDay::new(100.0, 0.25);
+        time_of_day.advance(150.0);
+        assert_relative_eq!(0.75, time_of_day.fraction_of_day());
+        time_of_day.advance(-100.0);
+        assert_relative_eq!(0.75, time_of_day.fraction_of_day());
+        time_of_day.set_fraction_of_day(-0.25);
+        assert_relative_eq!(0.75, time_of_day.fraction_of_day());
+    }
+
+    #[test]
+    fn sun_circles_the_equator_once_a_day() {
+        let mut time_of_day = TimeOfDay::new(100.0, 0.0);
+        let midnight_sun = time_of_day.sun_direction();
+        for _ in 0..10 {
+            let sun = time_of_day.sun_direction();
+            assert_relative_eq!(1.0, sun.norm(), epsilon = 1e-9);
+            assert_relative_eq!(0.0, sun.dot(&north_pole()), epsilon = 1e-9);
+            time_of_day.advance(10.0);
+        }
+        assert_relative_eq!(midnight_sun, time_of_day.sun_direction(), epsilon = 1e-9);
+
+        // Right overhead the prime meridian at noon, and opposite at midnight.
+        time_of_day.set_fraction_of_day(0.5);
+        assert_relative_eq!(prime_meridian(), time_of_day.sun_direction(), epsilon = 1e-9);
+        assert_relative_eq!(-prime_meridian(), midnight_sun, epsilon = 1e-9);
+    }
+
+    #[test]
+    fn half_the_globe_is_in_daylight() {
+        let spec = Globe::new_example().spec();
+        let mut time_of_day = TimeOfDay::new(100.0, 0.3);
+        let columns: Vec<GridPoint2> = (0..5)
+            .flat_map(|root| {
+                (0..8).flat_map(move |i| {
+                    (0..16).map(move |j| GridPoint2::new(root.into(), i * 4, j * 4))
+                })
+            })
+            .collect();
+        let daylit = |time_of_day: &TimeOfDay| {
+            columns
+                .iter()
+                .filter(|column| time_of_day.is_daylight(&spec, **column))
+                .count()
+        };
+        let daylit_in_morning = daylit(&time_of_day);
+        assert!(daylit_in_morning > columns.len() * 2 / 5);
+        assert!(daylit_in_morning < columns.len() * 3 / 5);
+
+        // Twelve hours later, it should be night wherever it was day.
+        let was_daylight: Vec<bool> = columns
+            .iter()
+            .map(|column| time_of_day.is_daylight(&spec, *column))
+            .collect();
+        time_of_day.advance(50.0);
+        for (column, was_daylight) in columns.iter().zip(was_daylight) {
+            if time_of_day.sun_height(&spec, *column).abs() > 1e-6 {
+                assert!(time_of_day.is_daylight(&spec, *column) != was_daylight);
+            }
+        }
+
+        // The sun rises in the east, so the north pole's neighbor
+        // along the prime meridian should see it rise at 6 AM.
+        let meridian_column = GridPoint2::new(0.into(), 0, 8);
+        time_of_day.set_fraction_of_day(0.24);
+        assert!(!time_of_day.is_daylight(&spec, meridian_column));
+        time_of_day.set_fraction_of_day(0.26);
+        assert!(time_of_day.is_daylight(&spec, meridian_column));
+    }
+}
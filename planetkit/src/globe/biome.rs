@@ -0,0 +1,95 @@
+use super::MaterialId;
+
+/// Climate of a column of the globe, used to choose its `Biome`.
+///
+/// Both values are roughly in the range [0, 1], but may stray
+/// slightly outside it.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Climate {
+    /// Hottest at the equator at sea level; coldest at the poles
+    /// and on top of mountains.
+    pub temperature: f64,
+    pub moisture: f64,
+}
+
+/// Broad kind of terrain in a column of the globe.
+///
+/// Determines what the surface is made of, how deep the soil
+/// goes before hitting stone, and how rough the terrain is.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Hash)]
+pub enum Biome {
+    /// Land below sea level.
+    Ocean,
+    Grassland,
+    Desert,
+    Tundra,
+    Mountains,
+}
+
+// Climate and elevation thresholds for choosing biomes.
+//
+// TODO: make these part of the spec once we know what sort
+// of knobs people actually want to turn.
+const MOUNTAIN_MIN_ELEVATION: f64 = 0.2;
+const TUNDRA_MAX_TEMPERATURE: f64 = 0.3;
+const DESERT_MIN_TEMPERATURE: f64 = 0.5;
+const DESERT_MAX_MOISTURE: f64 = 0.35;
+
+impl Biome {
+    /// Choose the biome for a column with the given climate.
+    ///
+    /// `elevation` is the column's height above (positive) or below (negative)
+    /// sea level, as a proportion of the maximum; i.e. in the range [-1, 1].
+    pub fn choose(climate: Climate, elevation: f64) -> Biome {
+        if elevation < 0.0 {
+            Biome::Ocean
+        } else if elevation > MOUNTAIN_MIN_ELEVATION {
+            Biome::Mountains
+        } else if climate.temperature < TUNDRA_MAX_TEMPERATURE {
+            Biome::Tundra
+        } else if climate.temperature > DESERT_MIN_TEMPERATURE &&
+                   climate.moisture < DESERT_MAX_MOISTURE
+        {
+            Biome::Desert
+        } else {
+            Biome::Grassland
+        }
+    }
+
+    /// What the top layer of the land is made of.
+    pub fn surface_material(&self) -> MaterialId {
+        match *self {
+            Biome::Ocean => MaterialId::SAND,
+            Biome::Grassland => MaterialId::DIRT,
+            Biome::Desert => MaterialId::SAND,
+            Biome::Tundra => MaterialId::SNOW,
+            Biome::Mountains => MaterialId::STONE,
+        }
+    }
+
+    /// How many cells deep the surface material goes before
+    /// we hit the stone beneath.
+    pub fn soil_depth(&self) -> f64 {
+        match *self {
+            Biome::Ocean => 2.0,
+            Biome::Grassland => 3.0,
+            Biome::Desert => 5.0,
+            Biome::Tundra => 2.0,
+            Biome::Mountains => 1.0,
+        }
+    }
+
+    /// Amplitude of small-scale bumps on the terrain, in cells.
+    ///
+    /// Mountains get rougher the higher they are, so that there's no cliff
+    /// where they meet their neighbors; `elevation` is as for `choose`.
+    pub fn roughness(&self, elevation: f64) -> f64 {
+        match *self {
+            Biome::Ocean => 1.0,
+            Biome::Grassland => 2.0,
+            Biome::Desert => 1.0,
+            Biome::Tundra => 2.0,
+            Biome::Mountains => 2.0 + (elevation - MOUNTAIN_MIN_ELEVATION) * 30.0,
+        }
+    }
+}
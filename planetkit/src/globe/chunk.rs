@@ -3,13 +3,7 @@ use grid::{GridCoord, GridPoint3, PosInOwningRoot};
 use globe::ChunkOrigin;
 use globe::origin_of_chunk_owning;
 use globe::chunk_pair::PointPair;
-
-#[derive(PartialEq, Eq, Copy, Clone)]
-pub enum Material {
-    Air,
-    Dirt,
-    Water,
-}
+use globe::MaterialId;
 
 // TODO: we should actually have multiple different
 // kinds of Voxmaps. "Chunk" should refer to the coarse
@@ -17,10 +11,21 @@ pub enum Material {
 // sized partition of the world that would be loaded and
 // unloaded into the world as a unit.
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, PartialEq, Debug)]
 pub struct Cell {
-    pub material: Material,
-    pub shade: f32,
+    pub material: MaterialId,
+    /// How much sunlight reaches the cell, up to `MAX_LIGHT`.
+    /// See `Globe::update_light`.
+    pub sky_light: u8,
+    /// How much light reaches the cell from materials that glow, up to `MAX_LIGHT`.
+    pub block_light: u8,
+}
+
+impl Cell {
+    /// How brightly lit the cell is, from either sunlight or anything glowing nearby.
+    pub fn light(&self) -> u8 {
+        self.sky_light.max(self.block_light)
+    }
 }
 
 // Stores from (0, 0) to (chunk_resolution, chunk_resolution) _inclusive_.
@@ -28,6 +33,7 @@ pub struct Cell {
 // Storage layout and cell ownership rules are mostly following:
 // <http://kiwi.atmos.colostate.edu/BUGS/geodesic/text.html>.
 // They seem to have a pretty good grasp on these things. :)
+#[derive(Clone)]
 pub struct Chunk {
     pub origin: ChunkOrigin,
     pub chunk_resolution: [GridCoord; 3],
@@ -50,6 +56,9 @@ pub struct Chunk {
     // Neighbors that share some cells on the border of this chunk but don't own them.
     pub downstream_neighbors: Vec<DownstreamNeighbor>,
     pub is_view_dirty: bool,
+    // Whether any authoritative cells in this chunk have been modified
+    // since it was generated or last saved to the globe's chunk store.
+    pub has_unsaved_changes: bool,
     // Chunks that are directly accessible from the given chunk via a single,
     // step between cells, including this chunk itself.
     //
@@ -85,6 +94,7 @@ impl Chunk {
             upstream_neighbors: Vec::new(),
             downstream_neighbors: Vec::new(),
             is_view_dirty: true,
+            has_unsaved_changes: false,
             accessible_chunks: Self::list_accessible_chunks(
                 origin,
                 root_resolution,
@@ -204,6 +214,13 @@ impl Chunk {
         }
     }
 
+    /// Number of cells stored in a chunk of the given resolution,
+    /// including those on its far edges that it shares with its neighbors.
+    pub fn cell_count(chunk_resolution: [GridCoord; 3]) -> usize {
+        let r = chunk_resolution;
+        ((r[0] + 1) * (r[1] + 1) * r[2]) as usize
+    }
+
     // Panics or returns nonsense if given coordinates of a cell we don't have data for.
     //
     // TODO: _store_ more information to make lookups cheaper.
@@ -247,7 +264,20 @@ impl Chunk {
         self.is_view_dirty = false;
     }
 
-    fn list_accessible_chunks(
+    /// Indicate that authoritative cells in this chunk have been modified
+    /// since it was last saved, so it must not be thrown away when unloaded.
+    pub fn mark_as_changed(&mut self) {
+        self.has_unsaved_changes = true;
+    }
+
+    /// Indicate that the chunk has been saved, so it can safely be unloaded.
+    pub fn mark_as_saved(&mut self) {
+        self.has_unsaved_changes = false;
+    }
+
+    /// List the origins of all chunks accessible from the chunk at `origin`;
+    /// see `accessible_chunks`.
+    pub fn list_accessible_chunks(
         origin: ChunkOrigin,
         root_resolution: [GridCoord; 2],
         chunk_resolution: [GridCoord; 3],
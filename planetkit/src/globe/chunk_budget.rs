@@ -0,0 +1,85 @@
+use std::mem;
+
+use grid::GridCoord;
+use super::chunk::{Chunk, Cell};
+
+/// How many chunks `ChunkSystem` may keep loaded for a `Globe`.
+///
+/// Once a globe has `max_chunks_loaded` chunks, chunks are unloaded until
+/// there are only `cull_chunks_down_to` left. This is a kind of hysteresis,
+/// so that we don't unload a chunk every time we load one.
+///
+/// Chunks wanted by a `ChunkLoadAnchor`, or holding changes that can't be
+/// saved, are never unloaded, so a globe may end up with more chunks
+/// loaded than its budget allows.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct ChunkBudget {
+    pub max_chunks_loaded: usize,
+    pub cull_chunks_down_to: usize,
+}
+
+impl ChunkBudget {
+    /// # Panics
+    ///
+    /// Panics if `cull_chunks_down_to` is greater than `max_chunks_loaded`.
+    pub fn new(max_chunks_loaded: usize, cull_chunks_down_to: usize) -> ChunkBudget {
+        assert!(
+            cull_chunks_down_to <= max_chunks_loaded,
+            "Can't cull chunks down to more than the maximum"
+        );
+        ChunkBudget {
+            max_chunks_loaded: max_chunks_loaded,
+            cull_chunks_down_to: cull_chunks_down_to,
+        }
+    }
+
+    /// Make a budget that keeps the memory used by a globe's chunks
+    /// under roughly `max_bytes`.
+    ///
+    /// Always allows at least one chunk, however small `max_bytes` is.
+    pub fn from_max_bytes(max_bytes: usize, chunk_resolution: [GridCoord; 3]) -> ChunkBudget {
+        let max_chunks_loaded = (max_bytes / estimated_chunk_bytes(chunk_resolution)).max(1);
+        ChunkBudget::new(max_chunks_loaded, max_chunks_loaded * 3 / 4)
+    }
+
+    /// Roughly how much memory this budget allows for chunks.
+    pub fn max_bytes(&self, chunk_resolution: [GridCoord; 3]) -> usize {
+        self.max_chunks_loaded * estimated_chunk_bytes(chunk_resolution)
+    }
+}
+
+impl Default for ChunkBudget {
+    fn default() -> ChunkBudget {
+        // There appears to be at least ~110 loaded at a minimum the way
+        // I have it at the moment; have to be super careful to get these
+        // numbers right so we don't unnecessarily churn chunks.
+        ChunkBudget::new(200, 150)
+    }
+}
+
+/// Rough estimate of how much memory a loaded chunk of the
+/// given resolution uses.
+///
+/// Only counts the chunk's cells, which dwarf everything else it holds.
+pub fn estimated_chunk_bytes(chunk_resolution: [GridCoord; 3]) -> usize {
+    mem::size_of::<Chunk>() + Chunk::cell_count(chunk_resolution) * mem::size_of::<Cell>()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn budget_from_max_bytes() {
+        let chunk_resolution = [16, 16, 4];
+        let chunk_bytes = estimated_chunk_bytes(chunk_resolution);
+        let budget = ChunkBudget::from_max_bytes(chunk_bytes * 100 + 1, chunk_resolution);
+        assert_eq!(100, budget.max_chunks_loaded);
+        assert_eq!(75, budget.cull_chunks_down_to);
+        assert!(budget.max_bytes(chunk_resolution) <= chunk_bytes * 100 + 1);
+
+        // Always allow at least one chunk.
+        let budget = ChunkBudget::from_max_bytes(0, chunk_resolution);
+        assert_eq!(1, budget.max_chunks_loaded);
+    }
+}
@@ -0,0 +1,618 @@
+// Compact binary encoding for `Chunk`s, suitable both for saving
+// chunks to disk and for sending them to network peers.
+//
+// Layout (all integers little-endian):
+//
+//   - Magic bytes "PKCH", then a format version byte.
+//   - Chunk origin: root index (u8), then x, y, z (i64).
+//   - Chunk resolution: x, y, z (i64).
+//   - Owned edge version (u64).
+//   - Material palette: entry count (varint), then one `MaterialId` (u16) per entry.
+//   - Material runs: pairs of palette index (varint) and run length (varint),
+//     covering every cell in storage order.
+//
+// Light isn't stored; `Globe` works it out again when the chunk is loaded.
+//
+// Generated chunks are mostly long runs of the same few materials,
+// so the material runs tend to be tiny compared to the cells themselves.
+//
+// There is also a "delta" encoding, which only stores the cells whose material
+// differs from what the globe's `WorldGen` would make there. Decoding it means
+// generating the chunk again, but it's a lot smaller than even the compressed
+// full encoding for any chunk that hasn't been dug up beyond recognition:
+//
+//   - Magic bytes "PKCD", then a format version byte.
+//   - Chunk origin, resolution, and owned edge version as above.
+//   - Number of changed cells (varint).
+//   - For each changed cell, in storage order: how many cells since the
+//     previous changed cell (varint; for the first one, its index),
+//     then its `MaterialId` (u16).
+
+use std::error;
+use std::fmt;
+use std::io;
+
+use bytes::{Buf, BufMut, LittleEndian};
+
+use grid::{GridCoord, GridPoint3};
+use super::{ChunkOrigin, Spec, WorldGen};
+use super::chunk::{Chunk, Cell};
+use super::material::MaterialId;
+use super::globe::generate_chunk;
+
+const MAGIC: &[u8; 4] = b"PKCH";
+const DELTA_MAGIC: &[u8; 4] = b"PKCD";
+
+/// Version of the chunk encoding written by `encode_chunk`.
+pub const CHUNK_FORMAT_VERSION: u8 = 1;
+
+/// Version of the chunk delta encoding written by `encode_chunk_delta`.
+pub const CHUNK_DELTA_FORMAT_VERSION: u8 = 1;
+
+/// Reasons why `decode_chunk` might reject its input.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ChunkDecodeError {
+    /// The data doesn't start with the expected magic bytes.
+    NotAChunk,
+    /// The data was written by an incompatible version of the format.
+    UnsupportedVersion(u8),
+    /// The data ended before the whole chunk was read.
+    Truncated,
+    /// The chunk was encoded for a globe with a different chunk resolution.
+    WrongResolution,
+    /// The chunk origin doesn't make sense for this globe.
+    InvalidOrigin,
+    /// The material runs referred to a palette entry that doesn't exist.
+    InvalidMaterial,
+    /// The material runs didn't add up to the number of cells in a chunk,
+    /// or a delta referred to a cell beyond the end of the chunk.
+    WrongCellCount,
+    /// There was more data after the end of the chunk.
+    TrailingData,
+}
+
+impl fmt::Display for ChunkDecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use self::ChunkDecodeError::*;
+        match *self {
+            NotAChunk => write!(f, "data is not an encoded chunk"),
+            UnsupportedVersion(version) => {
+                write!(f, "unsupported chunk format version {}", version)
+            }
+            Truncated => write!(f, "encoded chunk is truncated"),
+            WrongResolution => write!(f, "encoded chunk has the wrong resolution"),
+            InvalidOrigin => write!(f, "encoded chunk has an invalid origin"),
+            InvalidMaterial => write!(f, "encoded chunk has an invalid material"),
+            WrongCellCount => write!(f, "encoded chunk has the wrong number of cells"),
+            TrailingData => write!(f, "encoded chunk is followed by unexpected data"),
+        }
+    }
+}
+
+impl error::Error for ChunkDecodeError {
+    fn description(&self) -> &str {
+        "invalid encoded chunk"
+    }
+}
+
+impl From<ChunkDecodeError> for io::Error {
+    fn from(err: ChunkDecodeError) -> io::Error {
+        io::Error::new(io::ErrorKind::InvalidData, err)
+    }
+}
+
+/// Append the binary encoding of `chunk` to `buf`.
+///
+/// This covers everything about the chunk that can't be recomputed
+/// from its origin and the globe's `Spec`; in particular, cells come
+/// back from `decode_chunk` unlit.
+pub fn encode_chunk(chunk: &Chunk, buf: &mut Vec<u8>) {
+    put_header(buf, MAGIC, CHUNK_FORMAT_VERSION, chunk);
+
+    // Build the palette.
+    let mut palette: Vec<MaterialId> = Vec::new();
+    for cell in &chunk.cells {
+        if !palette.contains(&cell.material) {
+            palette.push(cell.material);
+        }
+    }
+    put_varint(buf, palette.len() as u32);
+    for material in &palette {
+        buf.put_u16::<LittleEndian>(material.0);
+    }
+
+    // Run-length encode the materials.
+    let mut cells = chunk.cells.iter().peekable();
+    while let Some(cell) = cells.next() {
+        let mut run_length: u32 = 1;
+        while cells.peek().map_or(false, |next| next.material == cell.material) {
+            cells.next();
+            run_length += 1;
+        }
+        let palette_index = palette
+            .iter()
+            .position(|material| *material == cell.material)
+            .expect("Every material should be in the palette");
+        put_varint(buf, palette_index as u32);
+        put_varint(buf, run_length);
+    }
+}
+
+/// Decode a chunk previously encoded by `encode_chunk`.
+///
+/// The chunk must have been encoded for a globe with the given resolutions.
+pub fn decode_chunk(
+    data: &[u8],
+    root_resolution: [GridCoord; 2],
+    chunk_resolution: [GridCoord; 3],
+) -> Result<Chunk, ChunkDecodeError> {
+    let mut reader = Reader { cursor: io::Cursor::new(data) };
+    let header = reader.header(MAGIC, CHUNK_FORMAT_VERSION, root_resolution, chunk_resolution)?;
+
+    let cell_count = Chunk::cell_count(chunk_resolution);
+    let palette_len = reader.varint()? as usize;
+    if palette_len > cell_count {
+        // No point having more materials than there are cells to use them,
+        // and we don't want to be tricked into allocating a huge palette.
+        return Err(ChunkDecodeError::InvalidMaterial);
+    }
+    let mut palette: Vec<MaterialId> = Vec::with_capacity(palette_len);
+    for _ in 0..palette_len {
+        // Note that we don't check whether the material is actually registered;
+        // that's up to whoever ends up using the cells.
+        palette.push(MaterialId(reader.u16()?));
+    }
+
+    let mut materials: Vec<MaterialId> = Vec::with_capacity(cell_count);
+    while materials.len() < cell_count {
+        let palette_index = reader.varint()? as usize;
+        let material = *palette.get(palette_index).ok_or(
+            ChunkDecodeError::InvalidMaterial,
+        )?;
+        let run_length = reader.varint()? as usize;
+        if run_length == 0 || materials.len() + run_length > cell_count {
+            return Err(ChunkDecodeError::WrongCellCount);
+        }
+        for _ in 0..run_length {
+            materials.push(material);
+        }
+    }
+
+    let cells: Vec<Cell> = materials
+        .into_iter()
+        .map(|material| {
+            Cell {
+                material: material,
+                sky_light: 0,
+                block_light: 0,
+            }
+        })
+        .collect();
+
+    if reader.cursor.has_remaining() {
+        return Err(ChunkDecodeError::TrailingData);
+    }
+
+    let mut chunk = Chunk::new(header.origin, cells, root_resolution, chunk_resolution);
+    chunk.owned_edge_version = header.owned_edge_version;
+    Ok(chunk)
+}
+
+/// Append the delta encoding of `chunk` to `buf`; this only includes the cells
+/// whose material differs from what `gen` would make there.
+///
+/// This generates the chunk again to compare against, so it's slow;
+/// `ChunkSystem` does it on its background threads.
+///
+/// `gen` must be the same generator that will be used to decode the chunk.
+pub fn encode_chunk_delta(chunk: &Chunk, spec: Spec, gen: &WorldGen, buf: &mut Vec<u8>) {
+    put_header(buf, DELTA_MAGIC, CHUNK_DELTA_FORMAT_VERSION, chunk);
+
+    let generated_chunk = generate_chunk(chunk.origin, spec, gen);
+    let changed_cells: Vec<(usize, MaterialId)> = chunk
+        .cells
+        .iter()
+        .zip(generated_chunk.cells.iter())
+        .enumerate()
+        .filter(|&(_, (cell, generated_cell))| cell.material != generated_cell.material)
+        .map(|(cell_i, (cell, _))| (cell_i, cell.material))
+        .collect();
+
+    put_varint(buf, changed_cells.len() as u32);
+    let mut next_cell_i = 0;
+    for (cell_i, material) in changed_cells {
+        put_varint(buf, (cell_i - next_cell_i) as u32);
+        buf.put_u16::<LittleEndian>(material.0);
+        next_cell_i = cell_i + 1;
+    }
+}
+
+/// Decode a chunk previously encoded by `encode_chunk_delta`,
+/// generating everything that wasn't stored.
+///
+/// The chunk must have been encoded for a globe with the given `Spec`,
+/// using the same generator.
+pub fn decode_chunk_delta(
+    data: &[u8],
+    spec: Spec,
+    gen: &WorldGen,
+) -> Result<Chunk, ChunkDecodeError> {
+    let mut reader = Reader { cursor: io::Cursor::new(data) };
+    let header = reader.header(
+        DELTA_MAGIC,
+        CHUNK_DELTA_FORMAT_VERSION,
+        spec.root_resolution,
+        spec.chunk_resolution,
+    )?;
+
+    let mut changed_cells: Vec<(usize, MaterialId)> = Vec::new();
+    let cell_count = Chunk::cell_count(spec.chunk_resolution);
+    let changed_cell_count = reader.varint()? as usize;
+    let mut next_cell_i = 0;
+    for _ in 0..changed_cell_count {
+        let cell_i = next_cell_i + reader.varint()? as usize;
+        if cell_i >= cell_count {
+            return Err(ChunkDecodeError::WrongCellCount);
+        }
+        changed_cells.push((cell_i, MaterialId(reader.u16()?)));
+        next_cell_i = cell_i + 1;
+    }
+
+    if reader.cursor.has_remaining() {
+        return Err(ChunkDecodeError::TrailingData);
+    }
+
+    // Only generate the chunk once we know the data is good; it's slow.
+    let mut chunk = generate_chunk(header.origin, spec, gen);
+    for (cell_i, material) in changed_cells {
+        chunk.cells[cell_i].material = material;
+    }
+    chunk.owned_edge_version = header.owned_edge_version;
+    Ok(chunk)
+}
+
+/// Decode a chunk encoded by either `encode_chunk` or `encode_chunk_delta`.
+pub fn decode_chunk_or_delta(
+    data: &[u8],
+    spec: Spec,
+    gen: &WorldGen,
+) -> Result<Chunk, ChunkDecodeError> {
+    if data.starts_with(DELTA_MAGIC) {
+        decode_chunk_delta(data, spec, gen)
+    } else {
+        decode_chunk(data, spec.root_resolution, spec.chunk_resolution)
+    }
+}
+
+fn put_header(buf: &mut Vec<u8>, magic: &[u8; 4], version: u8, chunk: &Chunk) {
+    buf.put_slice(magic);
+    buf.put_u8(version);
+
+    let origin = chunk.origin.pos();
+    buf.put_u8(origin.root.index);
+    buf.put_i64::<LittleEndian>(origin.x);
+    buf.put_i64::<LittleEndian>(origin.y);
+    buf.put_i64::<LittleEndian>(origin.z);
+    for axis_resolution in &chunk.chunk_resolution {
+        buf.put_i64::<LittleEndian>(*axis_resolution);
+    }
+    buf.put_u64::<LittleEndian>(chunk.owned_edge_version);
+}
+
+// Everything from `put_header`.
+struct Header {
+    origin: ChunkOrigin,
+    owned_edge_version: u64,
+}
+
+// LEB128-style variable length integer; small numbers take a single byte.
+fn put_varint(buf: &mut Vec<u8>, mut n: u32) {
+    while n >= 0x80 {
+        buf.put_u8((n as u8 & 0x7f) | 0x80);
+        n >>= 7;
+    }
+    buf.put_u8(n as u8);
+}
+
+// Bounds-checked reads; `bytes::Buf` panics if we run out of data.
+struct Reader<'a> {
+    cursor: io::Cursor<&'a [u8]>,
+}
+
+impl<'a> Reader<'a> {
+    fn header(
+        &mut self,
+        expected_magic: &[u8; 4],
+        expected_version: u8,
+        root_resolution: [GridCoord; 2],
+        chunk_resolution: [GridCoord; 3],
+    ) -> Result<Header, ChunkDecodeError> {
+        let mut magic = [0u8; 4];
+        self.copy_to_slice(&mut magic)?;
+        if &magic != expected_magic {
+            return Err(ChunkDecodeError::NotAChunk);
+        }
+        let version = self.u8()?;
+        if version != expected_version {
+            return Err(ChunkDecodeError::UnsupportedVersion(version));
+        }
+
+        let root = self.u8()?;
+        let x = self.i64()?;
+        let y = self.i64()?;
+        let z = self.i64()?;
+        let encoded_chunk_resolution = [self.i64()?, self.i64()?, self.i64()?];
+        if encoded_chunk_resolution != chunk_resolution {
+            return Err(ChunkDecodeError::WrongResolution);
+        }
+        let is_valid_origin = root < 5 && x >= 0 && y >= 0 && z >= 0 &&
+            x < root_resolution[0] && y < root_resolution[1] &&
+            x % chunk_resolution[0] == 0 && y % chunk_resolution[1] == 0 &&
+            z % chunk_resolution[2] == 0;
+        if !is_valid_origin {
+            return Err(ChunkDecodeError::InvalidOrigin);
+        }
+        let origin = ChunkOrigin::new(
+            GridPoint3::new(root.into(), x, y, z),
+            root_resolution,
+            chunk_resolution,
+        );
+        let owned_edge_version = self.u64()?;
+        Ok(Header {
+            origin: origin,
+            owned_edge_version: owned_edge_version,
+        })
+    }
+
+    fn ensure_remaining(&self, len: usize) -> Result<(), ChunkDecodeError> {
+        if self.cursor.remaining() < len {
+            Err(ChunkDecodeError::Truncated)
+        } else {
+            Ok(())
+        }
+    }
+
+    fn copy_to_slice(&mut self, dst: &mut [u8]) -> Result<(), ChunkDecodeError> {
+        self.ensure_remaining(dst.len())?;
+        self.cursor.copy_to_slice(dst);
+        Ok(())
+    }
+
+    fn u8(&mut self) -> Result<u8, ChunkDecodeError> {
+        self.ensure_remaining(1)?;
+        Ok(self.cursor.get_u8())
+    }
+
+    fn u16(&mut self) -> Result<u16, ChunkDecodeError> {
+        self.ensure_remaining(2)?;
+        Ok(self.cursor.get_u16::<LittleEndian>())
+    }
+
+    fn u64(&mut self) -> Result<u64, ChunkDecodeError> {
+        self.ensure_remaining(8)?;
+        Ok(self.cursor.get_u64::<LittleEndian>())
+    }
+
+    fn i64(&mut self) -> Result<i64, ChunkDecodeError> {
+        self.ensure_remaining(8)?;
+        Ok(self.cursor.get_i64::<LittleEndian>())
+    }
+
+    fn varint(&mut self) -> Result<u32, ChunkDecodeError> {
+        let mut n: u32 = 0;
+        let mut shift = 0;
+        loop {
+            let byte = self.u8()?;
+            if shift > 28 {
+                // Wouldn't fit in a u32.
+                return Err(ChunkDecodeError::WrongCellCount);
+            }
+            n |= ((byte & 0x7f) as u32) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(n);
+            }
+            shift += 7;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use grid::GridPoint3;
+    use super::super::{Globe, ChunkOrigin};
+    use super::*;
+
+    fn build_example_chunk(z: GridCoord) -> (Globe, ChunkOrigin) {
+        let mut globe = Globe::new_example();
+        let spec = globe.spec();
+        let origin = ChunkOrigin::new(
+            GridPoint3::new(2.into(), 16, 48, z),
+            spec.root_resolution,
+            spec.chunk_resolution,
+        );
+        globe.ensure_chunk_present(origin).unwrap();
+        (globe, origin)
+    }
+
+    fn assert_round_trips(globe: &Globe, origin: ChunkOrigin) {
+        let spec = globe.spec();
+        let chunk = globe.chunk_at(origin).unwrap();
+        let mut data = Vec::new();
+        encode_chunk(chunk, &mut data);
+        let decoded = decode_chunk(&data, spec.root_resolution, spec.chunk_resolution).unwrap();
+
+        assert_eq!(chunk.origin, decoded.origin);
+        assert_eq!(chunk.chunk_resolution, decoded.chunk_resolution);
+        assert_eq!(chunk.owned_edge_version, decoded.owned_edge_version);
+        assert_eq!(chunk.cells.len(), decoded.cells.len());
+        for (cell, decoded_cell) in chunk.cells.iter().zip(decoded.cells.iter()) {
+            assert_eq!(cell.material, decoded_cell.material);
+        }
+    }
+
+    #[test]
+    fn round_trip_generated_chunks() {
+        // Try chunks from deep underground, around sea level, and high in the air,
+        // so we get a mixture of single and multiple material chunks.
+        for z in &[0, 60, 64, 68, 200] {
+            let (globe, origin) = build_example_chunk(*z);
+            assert_round_trips(&globe, origin);
+        }
+    }
+
+    #[test]
+    fn round_trip_modified_chunk() {
+        let (mut globe, origin) = build_example_chunk(64);
+        {
+            use globe::globe::GlobeGuts;
+            let chunk = globe.chunks_mut().get_mut(&origin).unwrap();
+            chunk.owned_edge_version = 1234;
+            // Alternate materials every cell to make sure short runs work.
+            for (i, cell) in chunk.cells.iter_mut().enumerate() {
+                cell.material = if i % 3 == 0 {
+                    MaterialId::WATER
+                } else if i % 3 == 1 {
+                    MaterialId::AIR
+                } else {
+                    // Something from beyond the built-in materials.
+                    MaterialId(300)
+                };
+            }
+        }
+        assert_round_trips(&globe, origin);
+    }
+
+    #[test]
+    fn round_trip_chunk_with_many_materials() {
+        // Either side of the most that would fit in a byte.
+        for material_count in &[255, 256, 257, 1000] {
+            let (mut globe, origin) = build_example_chunk(64);
+            {
+                use globe::globe::GlobeGuts;
+                let chunk = globe.chunks_mut().get_mut(&origin).unwrap();
+                for (i, cell) in chunk.cells.iter_mut().enumerate() {
+                    cell.material = MaterialId((i % material_count) as u16);
+                }
+            }
+            assert_round_trips(&globe, origin);
+        }
+    }
+
+    #[test]
+    fn generated_chunks_compress_well() {
+        let (globe, origin) = build_example_chunk(200);
+        let chunk = globe.chunk_at(origin).unwrap();
+        let mut data = Vec::new();
+        encode_chunk(chunk, &mut data);
+        // All air; the materials should take only a handful of bytes
+        // on top of the fixed-size header.
+        assert!(data.len() < 80);
+    }
+
+    #[test]
+    fn reject_bad_data() {
+        let (globe, origin) = build_example_chunk(64);
+        let spec = globe.spec();
+        let mut data = Vec::new();
+        encode_chunk(globe.chunk_at(origin).unwrap(), &mut data);
+        let decode = |data: &[u8]| decode_chunk(data, spec.root_resolution, spec.chunk_resolution);
+
+        let mut bad_magic = data.clone();
+        bad_magic[0] = b'X';
+        assert_eq!(Err(ChunkDecodeError::NotAChunk), decode(&bad_magic).map(|_| ()));
+
+        let mut bad_version = data.clone();
+        bad_version[4] = CHUNK_FORMAT_VERSION + 1;
+        assert_eq!(
+            Err(ChunkDecodeError::UnsupportedVersion(CHUNK_FORMAT_VERSION + 1)),
+            decode(&bad_version).map(|_| ())
+        );
+
+        let truncated = &data[..data.len() - 1];
+        assert_eq!(Err(ChunkDecodeError::Truncated), decode(truncated).map(|_| ()));
+
+        let mut trailing = data.clone();
+        trailing.push(0);
+        assert_eq!(Err(ChunkDecodeError::TrailingData), decode(&trailing).map(|_| ()));
+
+        assert_eq!(
+            Err(ChunkDecodeError::WrongResolution),
+            decode_chunk(&data, [128, 256], [32, 32, 4]).map(|_| ())
+        );
+    }
+
+    #[test]
+    fn round_trip_chunk_delta() {
+        let (mut globe, origin) = build_example_chunk(64);
+        let spec = globe.spec();
+        let changed_cells = [0, 1, 2, 100, 101, 500, Chunk::cell_count(spec.chunk_resolution) - 1];
+        {
+            use globe::globe::GlobeGuts;
+            let chunk = globe.chunks_mut().get_mut(&origin).unwrap();
+            chunk.owned_edge_version = 1234;
+            for cell_i in &changed_cells {
+                let cell = &mut chunk.cells[*cell_i];
+                cell.material = if cell.material == MaterialId(300) {
+                    MaterialId::AIR
+                } else {
+                    MaterialId(300)
+                };
+            }
+        }
+
+        let chunk = globe.chunk_at(origin).unwrap();
+        let mut data = Vec::new();
+        encode_chunk_delta(chunk, spec, globe.gen(), &mut data);
+        // Fixed-size header, count, and three bytes for each changed cell.
+        assert!(data.len() <= 62 + 1 + changed_cells.len() * 4);
+
+        let decoded = decode_chunk_or_delta(&data, spec, globe.gen()).unwrap();
+        assert_eq!(chunk.origin, decoded.origin);
+        assert_eq!(chunk.owned_edge_version, decoded.owned_edge_version);
+        for (cell, decoded_cell) in chunk.cells.iter().zip(decoded.cells.iter()) {
+            assert_eq!(cell.material, decoded_cell.material);
+        }
+
+        // The full encoding should still be accepted too.
+        let mut data = Vec::new();
+        encode_chunk(chunk, &mut data);
+        let decoded = decode_chunk_or_delta(&data, spec, globe.gen()).unwrap();
+        assert_eq!(chunk.cells[500].material, decoded.cells[500].material);
+    }
+
+    #[test]
+    fn unmodified_chunk_delta_is_tiny() {
+        let (globe, origin) = build_example_chunk(60);
+        let spec = globe.spec();
+        let mut data = Vec::new();
+        encode_chunk_delta(globe.chunk_at(origin).unwrap(), spec, globe.gen(), &mut data);
+        // Just the header, and a count of zero.
+        assert_eq!(63, data.len());
+    }
+
+    #[test]
+    fn reject_bad_chunk_delta() {
+        let (globe, origin) = build_example_chunk(60);
+        let spec = globe.spec();
+        let mut data = Vec::new();
+        encode_chunk_delta(globe.chunk_at(origin).unwrap(), spec, globe.gen(), &mut data);
+
+        // One changed cell, beyond the end of the chunk.
+        data.pop();
+        data.push(1);
+        put_varint(&mut data, Chunk::cell_count(spec.chunk_resolution) as u32);
+        data.put_u16::<LittleEndian>(MaterialId::DIRT.0);
+        assert_eq!(
+            Err(ChunkDecodeError::WrongCellCount),
+            decode_chunk_delta(&data, spec, globe.gen()).map(|_| ())
+        );
+
+        // Full encodings aren't deltas.
+        let mut data = Vec::new();
+        encode_chunk(globe.chunk_at(origin).unwrap(), &mut data);
+        assert_eq!(
+            Err(ChunkDecodeError::NotAChunk),
+            decode_chunk_delta(&data, spec, globe.gen()).map(|_| ())
+        );
+    }
+}
@@ -0,0 +1,335 @@
+use std::sync::{Arc, Mutex};
+use std::sync::mpsc;
+use std::thread;
+
+use specs;
+
+use super::{ChunkOrigin, Spec, WorldGen, MaterialRegistry};
+use super::chunk::Chunk;
+use super::chunk_format::{ChunkDecodeError, encode_chunk_delta, decode_chunk_or_delta};
+use super::globe::generate_chunk;
+use super::lighting::light_chunk_in_isolation;
+
+enum Job {
+    // Generate a chunk, or decode it from saved data, and light it.
+    Load {
+        globe_entity: specs::Entity,
+        origin: ChunkOrigin,
+        spec: Spec,
+        gen: Arc<WorldGen>,
+        materials: Arc<MaterialRegistry>,
+        saved_data: Option<Vec<u8>>,
+    },
+    // Encode a chunk's changes so they can be saved.
+    Save {
+        globe_entity: specs::Entity,
+        chunk: Arc<Chunk>,
+        spec: Spec,
+        gen: Arc<WorldGen>,
+    },
+}
+
+/// A chunk finished by a `ChunkGenPool`, ready to be added to its globe.
+///
+/// It has already been lit as if it were the only chunk loaded;
+/// see `Globe::light_chunk`.
+pub struct GeneratedChunk {
+    pub globe_entity: specs::Entity,
+    pub chunk: Chunk,
+    /// The saved data the chunk was decoded from, if any;
+    /// see `Globe::add_generated_chunk`.
+    pub saved_data: Option<Vec<u8>>,
+    /// Why the saved data couldn't be decoded, if it couldn't,
+    /// in which case the chunk was generated fresh instead.
+    pub decode_error: Option<ChunkDecodeError>,
+}
+
+/// A chunk's changes encoded by a `ChunkGenPool`, ready to be saved;
+/// see `Globe::finish_saving_chunk`.
+pub struct EncodedChunk {
+    pub globe_entity: specs::Entity,
+    pub chunk: Arc<Chunk>,
+    pub data: Vec<u8>,
+}
+
+/// Generates chunks on a pool of background threads, so that
+/// the thread running the systems never has to wait for them.
+///
+/// Decoding and encoding saved chunks means generating them again,
+/// so that happens here too.
+///
+/// The threads shut down when the pool is dropped.
+pub struct ChunkGenPool {
+    job_sender: mpsc::Sender<Job>,
+    generated_receiver: mpsc::Receiver<GeneratedChunk>,
+    encoded_receiver: mpsc::Receiver<EncodedChunk>,
+}
+
+impl ChunkGenPool {
+    pub fn new(worker_threads: usize) -> ChunkGenPool {
+        assert!(worker_threads > 0, "Need at least one thread to generate chunks");
+
+        let (job_sender, job_receiver) = mpsc::channel::<Job>();
+        let (generated_sender, generated_receiver) = mpsc::channel();
+        let (encoded_sender, encoded_receiver) = mpsc::channel();
+        // All the workers take jobs from the same queue.
+        let job_receiver = Arc::new(Mutex::new(job_receiver));
+        for i in 0..worker_threads {
+            let job_receiver = job_receiver.clone();
+            let generated_sender = generated_sender.clone();
+            let encoded_sender = encoded_sender.clone();
+            thread::Builder::new()
+                .name(format!("chunk_gen_{}", i))
+                .spawn(move || loop {
+                    let job = {
+                        let job_receiver = job_receiver.lock().expect(
+                            "Another chunk generation thread panicked",
+                        );
+                        match job_receiver.recv() {
+                            Ok(job) => job,
+                            // The pool has been dropped; we're done.
+                            Err(_) => return,
+                        }
+                    };
+                    // If the pool has been dropped then nobody wants the result.
+                    let sent = match job {
+                        Job::Load {
+                            globe_entity,
+                            origin,
+                            spec,
+                            gen,
+                            materials,
+                            saved_data,
+                        } => {
+                            let generated_chunk = load_chunk(
+                                globe_entity,
+                                origin,
+                                spec,
+                                &*gen,
+                                &materials,
+                                saved_data,
+                            );
+                            generated_sender.send(generated_chunk).is_ok()
+                        }
+                        Job::Save { globe_entity, chunk, spec, gen } => {
+                            let mut data = Vec::new();
+                            encode_chunk_delta(&chunk, spec, &*gen, &mut data);
+                            let encoded_chunk = EncodedChunk {
+                                globe_entity: globe_entity,
+                                chunk: chunk,
+                                data: data,
+                            };
+                            encoded_sender.send(encoded_chunk).is_ok()
+                        }
+                    };
+                    if !sent {
+                        return;
+                    }
+                })
+                .expect("Failed to spawn chunk generation thread");
+        }
+        ChunkGenPool {
+            job_sender: job_sender,
+            generated_receiver: generated_receiver,
+            encoded_receiver: encoded_receiver,
+        }
+    }
+
+    /// Queue a chunk to be generated for the given globe, or decoded
+    /// from `saved_data` if it has been saved before;
+    /// see `Globe::load_saved_chunk_data`.
+    ///
+    /// Requests are handled roughly in the order they are made.
+    pub fn request(
+        &self,
+        globe_entity: specs::Entity,
+        origin: ChunkOrigin,
+        spec: Spec,
+        gen: Arc<WorldGen>,
+        materials: Arc<MaterialRegistry>,
+        saved_data: Option<Vec<u8>>,
+    ) {
+        self.send(Job::Load {
+            globe_entity: globe_entity,
+            origin: origin,
+            spec: spec,
+            gen: gen,
+            materials: materials,
+            saved_data: saved_data,
+        });
+    }
+
+    /// Queue the changes to a chunk to be encoded so that they can be saved;
+    /// see `Globe::unload_chunk_to_save_later`.
+    pub fn request_encoding(
+        &self,
+        globe_entity: specs::Entity,
+        chunk: Arc<Chunk>,
+        spec: Spec,
+        gen: Arc<WorldGen>,
+    ) {
+        self.send(Job::Save {
+            globe_entity: globe_entity,
+            chunk: chunk,
+            spec: spec,
+            gen: gen,
+        });
+    }
+
+    fn send(&self, job: Job) {
+        self.job_sender.send(job).expect(
+            "Chunk generation threads all died",
+        );
+    }
+
+    /// Take a chunk that has finished generating, if there are any.
+    pub fn try_take(&self) -> Option<GeneratedChunk> {
+        self.generated_receiver.try_recv().ok()
+    }
+
+    /// Take a chunk whose changes have finished encoding, if there are any.
+    pub fn try_take_encoded(&self) -> Option<EncodedChunk> {
+        self.encoded_receiver.try_recv().ok()
+    }
+}
+
+fn load_chunk(
+    globe_entity: specs::Entity,
+    origin: ChunkOrigin,
+    spec: Spec,
+    gen: &WorldGen,
+    materials: &MaterialRegistry,
+    saved_data: Option<Vec<u8>>,
+) -> GeneratedChunk {
+    let decoded = saved_data.as_ref().map(
+        |data| decode_chunk_or_delta(data, spec, gen),
+    );
+    let (mut chunk, decode_error) = match decoded {
+        Some(Ok(chunk)) => (chunk, None),
+        Some(Err(err)) => (generate_chunk(origin, spec, gen), Some(err)),
+        None => (generate_chunk(origin, spec, gen), None),
+    };
+    light_chunk_in_isolation(&mut chunk, spec, gen, materials);
+    GeneratedChunk {
+        globe_entity: globe_entity,
+        chunk: chunk,
+        saved_data: saved_data,
+        decode_error: decode_error,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use specs;
+
+    use grid::{GridPoint3, PosInOwningRoot};
+    use super::super::Globe;
+    use super::super::globe::GlobeGuts;
+    use super::*;
+
+    #[test]
+    fn generate_chunks_in_background() {
+        let globe = Globe::new_example();
+        let spec = globe.spec();
+        let mut world = specs::World::new();
+        let globe_entity = world.create_entity().build();
+
+        let pool = ChunkGenPool::new(2);
+        let origins: Vec<ChunkOrigin> = [0, 60, 64]
+            .iter()
+            .map(|z| {
+                ChunkOrigin::new(
+                    GridPoint3::new(1.into(), 16, 32, *z),
+                    spec.root_resolution,
+                    spec.chunk_resolution,
+                )
+            })
+            .collect();
+        for origin in &origins {
+            pool.request(
+                globe_entity,
+                *origin,
+                spec,
+                globe.shared_gen(),
+                globe.shared_materials(),
+                None,
+            );
+        }
+
+        let mut generated_chunks = Vec::new();
+        while generated_chunks.len() < origins.len() {
+            match pool.try_take() {
+                Some(generated_chunk) => generated_chunks.push(generated_chunk),
+                None => thread::sleep(Duration::from_millis(1)),
+            }
+        }
+
+        for generated_chunk in generated_chunks {
+            assert_eq!(globe_entity, generated_chunk.globe_entity);
+            let chunk = generated_chunk.chunk;
+            assert!(origins.contains(&chunk.origin));
+            let expected_chunk = generate_chunk(chunk.origin, spec, globe.gen());
+            for (cell, expected_cell) in chunk.cells.iter().zip(expected_chunk.cells.iter()) {
+                assert_eq!(expected_cell.material, cell.material);
+            }
+        }
+    }
+
+    #[test]
+    fn encode_and_decode_saved_chunks_in_background() {
+        use super::super::MaterialId;
+
+        let mut globe = Globe::new_example();
+        let spec = globe.spec();
+        let mut world = specs::World::new();
+        let globe_entity = world.create_entity().build();
+        let origin = ChunkOrigin::new(
+            GridPoint3::new(1.into(), 16, 32, 60),
+            spec.root_resolution,
+            spec.chunk_resolution,
+        );
+        globe.ensure_chunk_present(origin).unwrap();
+        let pos = origin.pos().with_x(20).with_y(35);
+        globe.authoritative_cell_mut(PosInOwningRoot::new(pos, spec.root_resolution))
+            .material = MaterialId::WATER;
+
+        let pool = ChunkGenPool::new(1);
+        let chunk = globe.unload_chunk_to_save_later(origin);
+        pool.request_encoding(globe_entity, chunk, spec, globe.shared_gen());
+        let encoded_chunk = loop {
+            match pool.try_take_encoded() {
+                Some(encoded_chunk) => break encoded_chunk,
+                None => thread::sleep(Duration::from_millis(1)),
+            }
+        };
+        assert_eq!(globe_entity, encoded_chunk.globe_entity);
+
+        // Corrupt data should be reported, and a fresh chunk generated instead.
+        for saved_data in vec![encoded_chunk.data.clone(), vec![0xff; 3]] {
+            pool.request(
+                globe_entity,
+                origin,
+                spec,
+                globe.shared_gen(),
+                globe.shared_materials(),
+                Some(saved_data.clone()),
+            );
+            let generated_chunk = loop {
+                match pool.try_take() {
+                    Some(generated_chunk) => break generated_chunk,
+                    None => thread::sleep(Duration::from_millis(1)),
+                }
+            };
+            assert_eq!(Some(saved_data), generated_chunk.saved_data);
+            let is_corrupt = generated_chunk.decode_error.is_some();
+            let expected_material = if is_corrupt {
+                generate_chunk(origin, spec, globe.gen()).cell(pos).material
+            } else {
+                MaterialId::WATER
+            };
+            assert_eq!(expected_material, generated_chunk.chunk.cell(pos).material);
+        }
+    }
+}
@@ -0,0 +1,43 @@
+use specs;
+
+/// Keeps the chunks around an entity loaded.
+///
+/// Attach this to an entity that has a `CellDweller`, or to anything else
+/// with a `Spatial` beneath a globe's, like a camera. `ChunkSystem` keeps the
+/// chunks around every anchor on a globe loaded, and unloads the chunks
+/// farthest from any of them first.
+///
+/// Cell dwellers without an anchor behave as if they had
+/// `ChunkLoadAnchor::default()`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct ChunkLoadAnchor {
+    /// How far from the chunk containing the entity to load chunks,
+    /// counted in steps from each chunk to the chunks accessible from it.
+    /// (See `Chunk::list_accessible_chunks`.)
+    pub radius: u8,
+    /// Chunks around anchors with higher priority are loaded first.
+    /// Among anchors with the same priority, the nearest chunks are loaded first.
+    pub priority: i32,
+}
+
+impl ChunkLoadAnchor {
+    pub fn new(radius: u8, priority: i32) -> ChunkLoadAnchor {
+        ChunkLoadAnchor {
+            radius: radius,
+            priority: priority,
+        }
+    }
+}
+
+impl Default for ChunkLoadAnchor {
+    fn default() -> ChunkLoadAnchor {
+        // Enough to make sure the chunks a cell dweller could end up in
+        // after a single action are present; a single action can lead to
+        // multiple cell jumps, e.g., stepping up a small ledge.
+        ChunkLoadAnchor::new(2, 0)
+    }
+}
+
+impl specs::Component for ChunkLoadAnchor {
+    type Storage = specs::HashMapStorage<ChunkLoadAnchor>;
+}
@@ -0,0 +1,380 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fs;
+use std::io;
+use std::io::{Read, Write};
+use std::path::PathBuf;
+
+use bytes::{Buf, BufMut, LittleEndian};
+
+use grid::{GridCoord, GridPoint3};
+use super::ChunkOrigin;
+
+/// Somewhere to put `Chunk`s that have been modified, so that they
+/// can be unloaded without losing those modifications.
+///
+/// Stores deal only in opaque encoded chunk data; `Globe` is responsible
+/// for encoding and decoding chunks, because it knows what `Spec` they
+/// were built for.
+pub trait ChunkStore: Send + Sync {
+    /// Load the encoded data for the chunk at the given origin,
+    /// or `None` if that chunk has never been saved.
+    fn load_chunk(&mut self, origin: ChunkOrigin) -> io::Result<Option<Vec<u8>>>;
+
+    /// Save encoded data for the chunk at the given origin,
+    /// replacing any data previously saved for it.
+    ///
+    /// Stores may hold on to saved data and only write it out
+    /// when they are flushed; see `flush`.
+    fn save_chunk(&mut self, origin: ChunkOrigin, data: &[u8]) -> io::Result<()>;
+
+    /// Make sure everything saved so far has actually been written out.
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+// Number of chunks along each axis of a region.
+//
+// TODO: make this configurable once we know what sort of
+// numbers make sense for different chunk resolutions.
+const CHUNKS_PER_REGION_SIDE: GridCoord = 8;
+
+// Identifies the first few bytes of a region file,
+// so we don't try to read something that isn't one.
+const REGION_FILE_MAGIC: &[u8; 4] = b"PKRG";
+const REGION_FILE_VERSION: u8 = 1;
+
+// Root index, x, y, and z for chunk origin, then data length.
+const REGION_ENTRY_HEADER_LEN: usize = 1 + 8 * 3 + 4;
+
+// How many regions to keep in memory by default before
+// forgetting about the ones that were least recently used.
+const DEFAULT_MAX_CACHED_REGIONS: usize = 64;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Hash)]
+struct RegionKey {
+    root: u8,
+    x: GridCoord,
+    y: GridCoord,
+    z: GridCoord,
+}
+
+/// Stores chunks on disk, grouped together into region files.
+///
+/// Each region file holds a block of neighboring chunks from the same
+/// root quad, so that a player exploring an area doesn't leave behind
+/// a separate file for every chunk they touched.
+///
+/// Regions are read into memory the first time any chunk in them is
+/// requested. Saving a chunk only changes the copy in memory; each
+/// changed region file is rewritten once when the store is flushed,
+/// or when the region is evicted to make room for others.
+/// Anything not yet flushed is lost if the store is dropped.
+pub struct RegionFileChunkStore {
+    dir: PathBuf,
+    chunk_resolution: [GridCoord; 3],
+    max_cached_regions: usize,
+    // Encoded chunks in each region, keyed by chunk origin.
+    regions: HashMap<RegionKey, HashMap<GridPoint3, Vec<u8>>>,
+    // Loaded regions, least recently used first.
+    region_lru: VecDeque<RegionKey>,
+    // Regions with changes that haven't been written to disk yet.
+    dirty_regions: HashSet<RegionKey>,
+}
+
+impl RegionFileChunkStore {
+    /// Create a store that keeps its region files in `dir`,
+    /// creating the directory if it doesn't already exist.
+    pub fn new<P: Into<PathBuf>>(
+        dir: P,
+        chunk_resolution: [GridCoord; 3],
+    ) -> io::Result<RegionFileChunkStore> {
+        let dir = dir.into();
+        fs::create_dir_all(&dir)?;
+        Ok(RegionFileChunkStore {
+            dir: dir,
+            chunk_resolution: chunk_resolution,
+            max_cached_regions: DEFAULT_MAX_CACHED_REGIONS,
+            regions: HashMap::new(),
+            region_lru: VecDeque::new(),
+            dirty_regions: HashSet::new(),
+        })
+    }
+
+    /// Keep at most this many regions in memory at once.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `max_cached_regions` is zero.
+    pub fn with_max_cached_regions(mut self, max_cached_regions: usize) -> RegionFileChunkStore {
+        assert!(max_cached_regions > 0, "Need to cache at least one region");
+        self.max_cached_regions = max_cached_regions;
+        self
+    }
+
+    fn region_key(&self, origin: ChunkOrigin) -> RegionKey {
+        let pos = origin.pos();
+        RegionKey {
+            root: pos.root.index,
+            x: pos.x / self.chunk_resolution[0] / CHUNKS_PER_REGION_SIDE,
+            y: pos.y / self.chunk_resolution[1] / CHUNKS_PER_REGION_SIDE,
+            z: pos.z / self.chunk_resolution[2] / CHUNKS_PER_REGION_SIDE,
+        }
+    }
+
+    fn region_path(&self, key: RegionKey) -> PathBuf {
+        self.dir.join(format!(
+            "r.{}.{}.{}.{}.pkr",
+            key.root,
+            key.x,
+            key.y,
+            key.z
+        ))
+    }
+
+    // Read the region from disk if we haven't already,
+    // and mark it as the most recently used.
+    fn ensure_region_loaded(&mut self, key: RegionKey) -> io::Result<()> {
+        if self.regions.contains_key(&key) {
+            if let Some(index) = self.region_lru.iter().position(|&lru_key| lru_key == key) {
+                self.region_lru.remove(index);
+            }
+            self.region_lru.push_back(key);
+            return Ok(());
+        }
+        let max_other_regions = self.max_cached_regions - 1;
+        self.evict_regions_down_to(max_other_regions)?;
+        let path = self.region_path(key);
+        let region = match fs::File::open(&path) {
+            Ok(mut file) => {
+                let mut data = Vec::new();
+                file.read_to_end(&mut data)?;
+                decode_region(&data)?
+            }
+            // The region has never been saved; that's fine.
+            Err(ref err) if err.kind() == io::ErrorKind::NotFound => HashMap::new(),
+            Err(err) => return Err(err),
+        };
+        self.regions.insert(key, region);
+        self.region_lru.push_back(key);
+        Ok(())
+    }
+
+    // Forget about the least recently used regions, writing them out first
+    // if they have unsaved changes, until there are at most `max_regions` left.
+    fn evict_regions_down_to(&mut self, max_regions: usize) -> io::Result<()> {
+        while self.regions.len() > max_regions {
+            let key = *self.region_lru.front().expect(
+                "Every loaded region should be in the LRU list",
+            );
+            if self.dirty_regions.contains(&key) {
+                self.write_region(key)?;
+                self.dirty_regions.remove(&key);
+            }
+            self.region_lru.pop_front();
+            self.regions.remove(&key);
+        }
+        Ok(())
+    }
+
+    fn write_region(&self, key: RegionKey) -> io::Result<()> {
+        let region = self.regions.get(&key).expect(
+            "Tried to write a region that isn't loaded",
+        );
+        let data = encode_region(region);
+        // Write to a temporary file first, and then move it into place,
+        // so that we never leave behind a half-written region.
+        let path = self.region_path(key);
+        let tmp_path = path.with_extension("pkr.tmp");
+        {
+            let mut file = fs::File::create(&tmp_path)?;
+            file.write_all(&data)?;
+            file.sync_all()?;
+        }
+        fs::rename(&tmp_path, &path)
+    }
+}
+
+impl ChunkStore for RegionFileChunkStore {
+    fn load_chunk(&mut self, origin: ChunkOrigin) -> io::Result<Option<Vec<u8>>> {
+        let key = self.region_key(origin);
+        self.ensure_region_loaded(key)?;
+        Ok(self.regions[&key].get(origin.pos()).cloned())
+    }
+
+    fn save_chunk(&mut self, origin: ChunkOrigin, data: &[u8]) -> io::Result<()> {
+        let key = self.region_key(origin);
+        self.ensure_region_loaded(key)?;
+        self.regions
+            .get_mut(&key)
+            .expect("We just ensured the region is loaded")
+            .insert(*origin.pos(), data.to_vec());
+        self.dirty_regions.insert(key);
+        Ok(())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        let dirty_regions: Vec<RegionKey> = self.dirty_regions.iter().cloned().collect();
+        for key in dirty_regions {
+            self.write_region(key)?;
+            self.dirty_regions.remove(&key);
+        }
+        Ok(())
+    }
+}
+
+fn encode_region(region: &HashMap<GridPoint3, Vec<u8>>) -> Vec<u8> {
+    let mut buf = Vec::<u8>::new();
+    buf.put_slice(REGION_FILE_MAGIC);
+    buf.put_u8(REGION_FILE_VERSION);
+    for (pos, data) in region {
+        buf.put_u8(pos.root.index);
+        buf.put_i64::<LittleEndian>(pos.x);
+        buf.put_i64::<LittleEndian>(pos.y);
+        buf.put_i64::<LittleEndian>(pos.z);
+        buf.put_u32::<LittleEndian>(data.len() as u32);
+        buf.put_slice(data);
+    }
+    buf
+}
+
+fn decode_region(data: &[u8]) -> io::Result<HashMap<GridPoint3, Vec<u8>>> {
+    let header_len = REGION_FILE_MAGIC.len() + 1;
+    if data.len() < header_len || &data[..REGION_FILE_MAGIC.len()] != &REGION_FILE_MAGIC[..] {
+        return Err(invalid_data("Not a region file"));
+    }
+    if data[REGION_FILE_MAGIC.len()] != REGION_FILE_VERSION {
+        return Err(invalid_data("Unsupported region file version"));
+    }
+
+    let mut region = HashMap::new();
+    let mut cursor = io::Cursor::new(&data[header_len..]);
+    while cursor.has_remaining() {
+        if cursor.remaining() < REGION_ENTRY_HEADER_LEN {
+            return Err(invalid_data("Truncated region file entry header"));
+        }
+        let root = cursor.get_u8();
+        let x = cursor.get_i64::<LittleEndian>();
+        let y = cursor.get_i64::<LittleEndian>();
+        let z = cursor.get_i64::<LittleEndian>();
+        let len = cursor.get_u32::<LittleEndian>() as usize;
+        if cursor.remaining() < len {
+            return Err(invalid_data("Truncated region file entry data"));
+        }
+        if root > 4 {
+            return Err(invalid_data("Region file contains an invalid root index"));
+        }
+        let mut chunk_data = vec![0u8; len];
+        cursor.copy_to_slice(&mut chunk_data);
+        region.insert(GridPoint3::new(root.into(), x, y, z), chunk_data);
+    }
+    Ok(region)
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs;
+    use std::path::PathBuf;
+
+    use rand;
+
+    use grid::GridPoint3;
+    use super::super::ChunkOrigin;
+    use super::*;
+
+    const ROOT_RESOLUTION: [GridCoord; 2] = [64, 128];
+    const CHUNK_RESOLUTION: [GridCoord; 3] = [16, 16, 4];
+
+    fn origin(root: u8, x: GridCoord, y: GridCoord, z: GridCoord) -> ChunkOrigin {
+        ChunkOrigin::new(
+            GridPoint3::new(root.into(), x, y, z),
+            ROOT_RESOLUTION,
+            CHUNK_RESOLUTION,
+        )
+    }
+
+    // Somewhere nobody else is using, even other test runs happening at the same time.
+    fn unique_temp_dir(name: &str) -> PathBuf {
+        env::temp_dir().join(format!("{}_{:08x}", name, rand::random::<u32>()))
+    }
+
+    #[test]
+    fn save_and_load_from_separate_stores() {
+        let dir = unique_temp_dir("planetkit_test_region_file_chunk_store");
+        let _ = fs::remove_dir_all(&dir);
+
+        let a = origin(0, 0, 0, 0);
+        let b = origin(0, 16, 32, 4);
+        let c = origin(3, 48, 112, 40);
+        {
+            let mut store = RegionFileChunkStore::new(dir.clone(), CHUNK_RESOLUTION).unwrap();
+            assert_eq!(None, store.load_chunk(a).unwrap());
+            store.save_chunk(a, &[1, 2, 3]).unwrap();
+            store.save_chunk(b, &[4, 5]).unwrap();
+            store.save_chunk(c, &[]).unwrap();
+            // Overwrite an existing chunk.
+            store.save_chunk(a, &[6]).unwrap();
+            store.flush().unwrap();
+        }
+
+        // Make sure everything was actually written to disk.
+        let mut store = RegionFileChunkStore::new(dir.clone(), CHUNK_RESOLUTION).unwrap();
+        assert_eq!(Some(vec![6]), store.load_chunk(a).unwrap());
+        assert_eq!(Some(vec![4, 5]), store.load_chunk(b).unwrap());
+        assert_eq!(Some(vec![]), store.load_chunk(c).unwrap());
+        assert_eq!(None, store.load_chunk(origin(1, 0, 0, 0)).unwrap());
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn evicted_regions_are_written_before_being_forgotten() {
+        let dir = unique_temp_dir("planetkit_test_region_file_chunk_store_eviction");
+        let _ = fs::remove_dir_all(&dir);
+
+        // All in different regions.
+        let a = origin(0, 0, 0, 0);
+        let b = origin(1, 0, 0, 0);
+        let c = origin(2, 0, 0, 0);
+        {
+            let mut store = RegionFileChunkStore::new(dir.clone(), CHUNK_RESOLUTION)
+                .unwrap()
+                .with_max_cached_regions(2);
+            store.save_chunk(a, &[1]).unwrap();
+            store.save_chunk(b, &[2]).unwrap();
+            // Nothing has been written yet.
+            assert!(fs::read_dir(&dir).unwrap().next().is_none());
+            // Using `a` again should make `b` the one to go.
+            assert_eq!(Some(vec![1]), store.load_chunk(a).unwrap());
+            store.save_chunk(c, &[3]).unwrap();
+            assert_eq!(2, store.regions.len());
+            assert!(!store.regions.contains_key(&store.region_key(b)));
+            // Dropped without flushing, so only `b` should make it to disk.
+        }
+
+        let mut store = RegionFileChunkStore::new(dir.clone(), CHUNK_RESOLUTION).unwrap();
+        assert_eq!(None, store.load_chunk(a).unwrap());
+        assert_eq!(Some(vec![2]), store.load_chunk(b).unwrap());
+        assert_eq!(None, store.load_chunk(c).unwrap());
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn reject_corrupt_region() {
+        let mut data = encode_region(&HashMap::new());
+        data[0] = b'X';
+        assert!(decode_region(&data).is_err());
+
+        let mut region = HashMap::new();
+        region.insert(*origin(2, 16, 16, 8).pos(), vec![1, 2, 3, 4]);
+        let mut data = encode_region(&region);
+        data.pop();
+        assert!(decode_region(&data).is_err());
+    }
+}
@@ -1,185 +1,754 @@
+use std::collections::{HashMap, HashSet};
+
 use specs;
 use specs::{ReadStorage, WriteStorage};
 use specs::Entities;
 use slog::Logger;
 
+use types::*;
 use grid::PosInOwningRoot;
-use super::{Globe, ChunkOrigin};
+use super::{Globe, ChunkOrigin, ChunkLoadAnchor};
+use super::chunk::Chunk;
+use super::chunk_gen_pool::ChunkGenPool;
 use cell_dweller::CellDweller;
+use spatial::SpatialStorage;
+use Spatial;
+
+// How much to prefer unloading chunks that no anchor has wanted
+// for a while over chunks that are merely far away. A chunk that
+// hasn't been wanted for this many ticks counts as if it were
+// twice as far away as it really is.
+const EVICTION_AGE_SCALE: f64 = 100.0;
+
+// A `ChunkLoadAnchor` along with where it is.
+struct Anchor {
+    globe_entity: specs::Entity,
+    // Chunk owning the anchor's cell.
+    chunk_origin: ChunkOrigin,
+    real_pos: Vec3,
+    radius: u8,
+    priority: i32,
+}
 
 /// Loads and unloads `Chunk`s for a `Globe`.
 ///
-/// The `Chunk`s may be loaded from disk, or generated fresh if
-/// they have never existed before.
+/// Chunks are kept loaded around every `CellDweller` on the globe,
+/// as far out as its `ChunkLoadAnchor` says, and around any other entity
+/// with a `ChunkLoadAnchor` whose `Spatial` is somewhere beneath the globe's.
+/// Beyond those, each globe keeps as many chunks loaded as its `ChunkBudget` allows.
+///
+/// The `Chunk`s may be loaded from the globe's `ChunkStore`, or generated
+/// fresh if they have never been saved. Modified chunks are saved to the
+/// store before they are unloaded.
+///
+/// Generating chunks is slow, so it is done on background threads, and
+/// finished chunks are added to their globe the next time this system runs.
+/// Until then, anything that needs those chunks will have to wait;
+/// see `Globe::maybe_non_authoritative_cell_if_ready`.
 pub struct ChunkSystem {
     log: Logger,
-    // When we go higher than this many chunks loaded...
-    max_chunks_loaded_per_globe: usize,
-    // ...we will unload chunks to leave only this many.
-    // This is a kind of hysteresis. I haven't yet validated
-    // that this actually improves performance _at all_.
-    cull_chunks_down_to: usize,
+    gen_pool: ChunkGenPool,
+    // Chunks we've asked `gen_pool` for, but haven't got back yet.
+    pending_chunks: HashSet<(specs::Entity, ChunkOrigin)>,
+    // How many times this system has run.
+    ticks: u64,
+    // The last tick on which each loaded chunk was wanted by an anchor,
+    // or when we first noticed it was loaded if no anchor has wanted it yet.
+    last_wanted: HashMap<(specs::Entity, ChunkOrigin), u64>,
 }
 
 impl ChunkSystem {
-    pub fn new(parent_log: &Logger) -> ChunkSystem {
+    /// Generate chunks on `gen_threads` background threads.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `gen_threads` is zero.
+    pub fn new(parent_log: &Logger, gen_threads: usize) -> ChunkSystem {
         ChunkSystem {
             log: parent_log.new(o!()),
-            // TODO: accept as arguments.
-            //
-            // There appears to be at least ~110
-            // loaded at a minimum the way I have it at the moment;
-            // have to be super careful to get these numbers right
-            // so we don't unnecessarily churn chunks.
-            //
-            // TODO: how to make sure this is automatically right?
-            max_chunks_loaded_per_globe: 200,
-            cull_chunks_down_to: 150,
+            gen_pool: ChunkGenPool::new(gen_threads),
+            pending_chunks: HashSet::new(),
+            ticks: 0,
+            last_wanted: HashMap::new(),
         }
     }
 
-    fn unload_excess_chunks_if_necessary<'a>(
+    fn unload_excess_chunks_if_necessary(
         &mut self,
         globe: &mut Globe,
         globe_entity: specs::Entity,
-        cds: &specs::ReadStorage<'a, CellDweller>,
+        anchors: &[Anchor],
+        wanted_chunks: &HashSet<(specs::Entity, ChunkOrigin)>,
     ) {
         use super::globe::GlobeGuts;
 
-        if globe.chunks().len() < self.max_chunks_loaded_per_globe {
+        let budget = globe.chunk_budget();
+        if globe.chunks().len() < budget.max_chunks_loaded {
             // We're under the limit; nothing to do.
             return;
         }
 
-        // TEMP: assume only one cell dweller per globe.
-        // TODO: proper entities/points/volumes of interest system.
-        use specs::Join;
-        // Use the first CellDweller we find on this Globe.
-        let one_true_cd = match cds.join()
-            .filter(|cd| cd.globe_entity == Some(globe_entity))
-            .next() {
-            Some(cd) => cd,
-            // There are no cell dwellers, so no interesting terrain.
-            // (If a tree falls in a forest...)
-            None => return,
-        };
-        let one_true_cd_pos = one_true_cd
-            .real_transform_without_setting_clean()
-            .translation
-            .vector;
+        // Forget about chunks that have been unloaded some other way.
+        self.last_wanted.retain(|&(chunk_globe_entity, chunk_origin), _| {
+            chunk_globe_entity != globe_entity || globe.chunk_at(chunk_origin).is_some()
+        });
+
+        let anchor_positions: Vec<Vec3> = anchors
+            .iter()
+            .filter(|anchor| anchor.globe_entity == globe_entity)
+            .map(|anchor| anchor.real_pos)
+            .collect();
 
-        // Unload the most distant chunks.
+        // Unload the chunks that are far from any anchor, and haven't been wanted
+        // by one for a long time, first. Never unload chunks that an anchor wants
+        // right now, or that have changes we have nowhere to save.
         //
         // TODO: Don't allocate memory all the time here.
         // At very least use a persistent scratch buffer instead
         // of allocating every time!
-        let mut chunk_distances: Vec<(ChunkOrigin, f64)> = globe
+        let can_save_chunks = globe.has_chunk_store();
+        let chunk_distances: Vec<(ChunkOrigin, f64)> = globe
             .chunks()
-            .keys()
-            .map(|chunk_origin| {
+            .values()
+            .filter(|chunk| {
+                !wanted_chunks.contains(&(globe_entity, chunk.origin)) &&
+                    (can_save_chunks || !chunk.has_unsaved_changes)
+            })
+            .map(|chunk| {
                 // TODO: don't use chunk origin; use the middle cell,
                 // or otherwise whatever the closest corner is.
                 // Or even a bounding sphere.
                 // (Cache this per Chunk).
-                let chunk_origin_pos = globe.spec().cell_bottom_center(*chunk_origin.pos());
-                let distance_from_one_true_cd = (one_true_cd_pos - chunk_origin_pos.coords).norm();
-                (*chunk_origin, distance_from_one_true_cd)
+                let chunk_origin_pos = globe.spec().cell_bottom_center(*chunk.origin.pos());
+                // If there are no anchors then all chunks are equally far away,
+                // and we can only go by how long ago they were wanted.
+                let distance_from_nearest_anchor = if anchor_positions.is_empty() {
+                    1.0
+                } else {
+                    anchor_positions
+                        .iter()
+                        .map(|anchor_pos| (anchor_pos - chunk_origin_pos.coords).norm())
+                        .fold(::std::f64::INFINITY, f64::min)
+                };
+                (chunk.origin, distance_from_nearest_anchor)
+            })
+            .collect();
+        let ticks = self.ticks;
+        let mut chunk_scores: Vec<(ChunkOrigin, f64)> = chunk_distances
+            .into_iter()
+            .map(|(chunk_origin, distance)| {
+                let last_wanted_tick = *self.last_wanted
+                    .entry((globe_entity, chunk_origin))
+                    .or_insert(ticks);
+                let ticks_since_wanted = (ticks - last_wanted_tick) as f64;
+                (chunk_origin, distance * (1.0 + ticks_since_wanted / EVICTION_AGE_SCALE))
             })
             .collect();
-        // Farthest away chunks come first.
-        chunk_distances.sort_by(|a, b| {
+        // Chunks we want the least come first.
+        chunk_scores.sort_by(|a, b| {
             b.1.partial_cmp(&a.1).expect(
-                "All chunk origins and CellDwellers should be real distances from each other!",
+                "All chunk origins and anchors should be real distances from each other!",
             )
         });
-        let chunks_to_remove = self.max_chunks_loaded_per_globe - self.cull_chunks_down_to;
-        chunk_distances.truncate(chunks_to_remove);
+        let chunks_to_remove = globe.chunks().len() - budget.cull_chunks_down_to;
+        let mut chunks_removed = 0;
+        for (chunk_origin, _score) in chunk_scores {
+            if chunks_removed >= chunks_to_remove {
+                break;
+            }
+            // Modified chunks get saved before they're unloaded,
+            // so that they can be loaded again later instead of being
+            // generated fresh. Working out what to save means generating
+            // the chunk again, so do that in the background.
+            let has_unsaved_changes = globe
+                .chunk_at(chunk_origin)
+                .expect("We just listed this chunk")
+                .has_unsaved_changes;
+            if has_unsaved_changes {
+                let chunk = globe.unload_chunk_to_save_later(chunk_origin);
+                self.gen_pool.request_encoding(
+                    globe_entity,
+                    chunk,
+                    globe.spec(),
+                    globe.shared_gen(),
+                );
+            } else {
+                globe.remove_chunk(chunk_origin);
+            }
+            self.last_wanted.remove(&(globe_entity, chunk_origin));
+            chunks_removed += 1;
+        }
+    }
+
+    // Save the changes to any chunks that have finished being encoded.
+    fn save_encoded_chunks<'a>(&mut self, globes: &mut specs::WriteStorage<'a, Globe>) {
+        while let Some(encoded_chunk) = self.gen_pool.try_take_encoded() {
+            let chunk_origin = encoded_chunk.chunk.origin;
+            match globes.get_mut(encoded_chunk.globe_entity) {
+                Some(globe) => {
+                    let result = globe.finish_saving_chunk(encoded_chunk.chunk, &encoded_chunk.data);
+                    if let Err(err) = result {
+                        warn!(
+                            self.log,
+                            "Failed to save chunk; keeping it loaded";
+                            "origin" => format!("{:?}", chunk_origin),
+                            "error" => format!("{}", err)
+                        );
+                    }
+                }
+                None => {
+                    debug!(
+                        self.log,
+                        "Discarding changes to a chunk of a globe that no longer exists";
+                        "origin" => format!("{:?}", chunk_origin)
+                    );
+                }
+            }
+        }
+    }
 
-        for (chunk_origin, _distance) in chunk_distances {
-            globe.remove_chunk(chunk_origin);
+    // Add any chunks that have finished generating to their globes.
+    fn add_generated_chunks<'a>(&mut self, globes: &mut specs::WriteStorage<'a, Globe>) {
+        while let Some(generated_chunk) = self.gen_pool.try_take() {
+            let globe_entity = generated_chunk.globe_entity;
+            let chunk_origin = generated_chunk.chunk.origin;
+            self.pending_chunks.remove(&(globe_entity, chunk_origin));
+            match globes.get_mut(globe_entity) {
+                Some(globe) => {
+                    if let Some(err) = generated_chunk.decode_error {
+                        warn!(
+                            self.log,
+                            "Failed to decode saved chunk; generated it instead";
+                            "origin" => format!("{:?}", chunk_origin),
+                            "error" => format!("{}", err)
+                        );
+                    }
+                    let saved_data = generated_chunk.saved_data.as_ref().map(|data| &data[..]);
+                    if let Err(err) = globe.add_generated_chunk(generated_chunk.chunk, saved_data) {
+                        warn!(
+                            self.log,
+                            "Failed to load saved chunk; using generated chunk instead";
+                            "origin" => format!("{:?}", chunk_origin),
+                            "error" => format!("{}", err)
+                        );
+                    }
+                }
+                None => {
+                    // The globe is gone; nobody needs this chunk anymore.
+                    debug!(
+                        self.log,
+                        "Discarding chunk generated for a globe that no longer exists";
+                        "origin" => format!("{:?}", chunk_origin)
+                    );
+                }
+            }
         }
     }
 
-    fn ensure_essential_chunks_for_cell_dweller_present<'a>(
+    // Make sure the chunk is either loaded or on its way.
+    //
+    // Returns `true` if the chunk is loaded now.
+    fn request_chunk(
         &mut self,
-        cd: &CellDweller,
-        globes: &mut specs::WriteStorage<'a, Globe>,
-    ) {
-        if let Some(globe_entity) = cd.globe_entity {
+        globe: &mut Globe,
+        globe_entity: specs::Entity,
+        chunk_origin: ChunkOrigin,
+    ) -> bool {
+        use super::globe::GlobeGuts;
+
+        if self.pending_chunks.contains(&(globe_entity, chunk_origin)) {
+            return false;
+        }
+        if globe.chunk_at(chunk_origin).is_some() || globe.reload_chunk_being_saved(chunk_origin) {
+            return true;
+        }
+        // Decoding a saved chunk means generating it again, so that happens
+        // in the background too. If it can't be read, then generate it
+        // rather than having a hole in the world.
+        let saved_data = match globe.load_saved_chunk_data(chunk_origin) {
+            Ok(saved_data) => saved_data,
+            Err(err) => {
+                warn!(
+                    self.log,
+                    "Failed to load saved chunk; generating it instead";
+                    "origin" => format!("{:?}", chunk_origin),
+                    "error" => format!("{}", err)
+                );
+                None
+            }
+        };
+        self.gen_pool.request(
+            globe_entity,
+            chunk_origin,
+            globe.spec(),
+            globe.shared_gen(),
+            globe.shared_materials(),
+            saved_data,
+        );
+        self.pending_chunks.insert((globe_entity, chunk_origin));
+        false
+    }
+
+    // Find all the anchors, highest priority first.
+    fn find_anchors<'a>(
+        &self,
+        entities: &specs::Entities<'a>,
+        cds: &specs::ReadStorage<'a, CellDweller>,
+        chunk_load_anchors: &specs::ReadStorage<'a, ChunkLoadAnchor>,
+        spatials: &specs::ReadStorage<'a, Spatial>,
+        globes: &specs::WriteStorage<'a, Globe>,
+    ) -> Vec<Anchor> {
+        use specs::Join;
+
+        let mut anchors = Vec::new();
+        for (entity, cd) in (&**entities, cds).join() {
+            let globe_entity = match cd.globe_entity {
+                Some(globe_entity) => globe_entity,
+                None => continue,
+            };
             // Get the associated globe, complaining loudly if we fail.
             // TODO: this is becoming a common pattern; factor out.
-            let globe = match globes.get_mut(globe_entity) {
+            let globe = match globes.get(globe_entity) {
                 Some(globe) => globe,
                 None => {
                     warn!(
                         self.log,
                         "The globe associated with this CellDweller is not alive! Can't proceed!"
                     );
-                    return;
+                    continue;
                 }
             };
-
-            // TODO: throttle, and do in background.
-            // (Except that the truly essential chunks really do need to be loaded _now_.)
-
-            // TODO: see remarks in `Chunk::list_accessible_chunks`
-            // about this actually being an inappropriate way to approach
-            // this problem; we'll load a bunch of chunks we don't need to yet
-            // in a desperate attempt to not miss the ones we do need.
-
-            // Load all the chunks that we could possibly try
-            // to move into from this chunk within two steps.
-            //
-            // Takes into account that a single user action could lead to
-            // multiple cell jumps, e.g., stepping up a small ledge.
-            //
-            // TODO: this is all a bit finicky and fragile.
+            let chunk_load_anchor = chunk_load_anchors.get(entity).cloned().unwrap_or_default();
             let cd_pos_in_owning_root = PosInOwningRoot::new(cd.pos, globe.spec().root_resolution);
-            let chunk_origin = globe.origin_of_chunk_owning(cd_pos_in_owning_root);
-            globe.ensure_chunk_present(chunk_origin);
-            let accessible_chunks = {
-                use super::globe::GlobeGuts;
-                let chunk = globe.chunks().get(&chunk_origin).expect(
-                    "We just ensured this chunk is loaded.",
-                );
-                // TODO: Gah, such slow!
-                chunk.accessible_chunks.clone()
+            anchors.push(Anchor {
+                globe_entity: globe_entity,
+                chunk_origin: globe.origin_of_chunk_owning(cd_pos_in_owning_root),
+                real_pos: cd.real_transform_without_setting_clean().translation.vector,
+                radius: chunk_load_anchor.radius,
+                priority: chunk_load_anchor.priority,
+            });
+        }
+        // Anything else with an anchor, e.g., a camera, goes by where its `Spatial` is
+        // relative to whichever globe it's attached to.
+        for (entity, chunk_load_anchor, _) in (&**entities, chunk_load_anchors, spatials).join() {
+            if cds.get(entity).is_some() {
+                // Already done.
+                continue;
+            }
+            let globe_entity = match self.globe_above(entity, spatials, globes) {
+                Some(globe_entity) => globe_entity,
+                None => continue,
             };
-            for accessible_chunk_origin in accessible_chunks {
-                globe.ensure_chunk_present(accessible_chunk_origin);
-
-                // Repeat this from each immediately accessible chunk.
-                let next_level_accessible_chunks = {
-                    use super::globe::GlobeGuts;
-                    let chunk = globe.chunks().get(&accessible_chunk_origin).expect(
-                        "We just ensured this chunk is loaded.",
-                    );
-                    // TODO: Gah, such slow!
-                    chunk.accessible_chunks.clone()
+            let globe = globes.get(globe_entity).expect("Globe disappeared");
+            let spec = globe.spec();
+            let real_pos = spatials.a_relative_to_b(entity, globe_entity).translation.vector;
+            let cell_pos = spec.cell_containing(Pt3::from_coordinates(real_pos));
+            // Anything below the bottom of the world is as close as it can get
+            // to the bottom layer of chunks.
+            let cell_pos = cell_pos.with_z(cell_pos.z.max(0));
+            let pos_in_owning_root = PosInOwningRoot::new(cell_pos, spec.root_resolution);
+            anchors.push(Anchor {
+                globe_entity: globe_entity,
+                chunk_origin: globe.origin_of_chunk_owning(pos_in_owning_root),
+                real_pos: real_pos,
+                radius: chunk_load_anchor.radius,
+                priority: chunk_load_anchor.priority,
+            });
+        }
+        // Stable, so anchors with the same priority stay in a consistent order.
+        anchors.sort_by(|a, b| b.priority.cmp(&a.priority));
+        anchors
+    }
+
+    // The nearest globe among the entity's ancestors, if any.
+    fn globe_above<'a>(
+        &self,
+        entity: specs::Entity,
+        spatials: &specs::ReadStorage<'a, Spatial>,
+        globes: &specs::WriteStorage<'a, Globe>,
+    ) -> Option<specs::Entity> {
+        let mut ancestor = spatials.get(entity).and_then(|spatial| spatial.parent_entity());
+        while let Some(ancestor_entity) = ancestor {
+            if globes.get(ancestor_entity).is_some() {
+                return Some(ancestor_entity);
+            }
+            ancestor = spatials.get(ancestor_entity).and_then(|spatial| spatial.parent_entity());
+        }
+        None
+    }
+
+    // Make sure the chunks around all the anchors are present, or on their way,
+    // and return all the chunks that the anchors want loaded.
+    fn request_chunks_near_anchors<'a>(
+        &mut self,
+        anchors: &[Anchor],
+        globes: &mut specs::WriteStorage<'a, Globe>,
+    ) -> HashSet<(specs::Entity, ChunkOrigin)> {
+        let mut wanted_chunks = HashSet::new();
+        // Anchors are sorted by priority, so take each run of
+        // anchors with the same priority in turn.
+        let mut group_start = 0;
+        while group_start < anchors.len() {
+            let priority = anchors[group_start].priority;
+            let group_end = anchors[group_start..]
+                .iter()
+                .position(|anchor| anchor.priority != priority)
+                .map_or(anchors.len(), |i| group_start + i);
+            self.request_chunks_near_anchor_group(
+                &anchors[group_start..group_end],
+                globes,
+                &mut wanted_chunks,
+            );
+            group_start = group_end;
+        }
+        wanted_chunks
+    }
+
+    // Request chunks around each anchor in the group,
+    // working outwards from all of them at once so that
+    // the nearest chunks get requested first.
+    fn request_chunks_near_anchor_group<'a>(
+        &mut self,
+        anchors: &[Anchor],
+        globes: &mut specs::WriteStorage<'a, Globe>,
+        wanted_chunks: &mut HashSet<(specs::Entity, ChunkOrigin)>,
+    ) {
+        // TODO: throttle.
+
+        // TODO: see remarks in `Chunk::list_accessible_chunks`
+        // about this actually being an inappropriate way to approach
+        // this problem; we'll load a bunch of chunks we don't need to yet
+        // in a desperate attempt to not miss the ones we do need.
+        //
+        // TODO: this is all a bit finicky and fragile.
+
+        // Chunks each anchor has already seen, and the ones it will visit next.
+        let mut visited: Vec<HashSet<ChunkOrigin>> = vec![HashSet::new(); anchors.len()];
+        let mut frontiers: Vec<Vec<ChunkOrigin>> =
+            anchors.iter().map(|anchor| vec![anchor.chunk_origin]).collect();
+        let max_radius = anchors.iter().map(|anchor| anchor.radius).max().unwrap_or(0);
+        for distance in 0..(max_radius + 1) {
+            for (i, anchor) in anchors.iter().enumerate() {
+                if distance > anchor.radius {
+                    continue;
+                }
+                let globe = match globes.get_mut(anchor.globe_entity) {
+                    Some(globe) => globe,
+                    // We already complained about this when finding the anchors.
+                    None => continue,
                 };
-                for next_level_accessible_chunk_origin in next_level_accessible_chunks {
-                    globe.ensure_chunk_present(next_level_accessible_chunk_origin);
+                let frontier = ::std::mem::replace(&mut frontiers[i], Vec::new());
+                for chunk_origin in frontier {
+                    if !visited[i].insert(chunk_origin) {
+                        continue;
+                    }
+                    wanted_chunks.insert((anchor.globe_entity, chunk_origin));
+                    let is_loaded = self.request_chunk(globe, anchor.globe_entity, chunk_origin);
+                    // Only look further afield from chunks that are already loaded;
+                    // we'll get to the rest once they've been generated.
+                    if is_loaded && distance < anchor.radius {
+                        frontiers[i].extend(chunks_accessible_from(globe, chunk_origin));
+                    }
                 }
             }
         }
     }
 }
 
+// Chunks accessible from the given chunk; see `Chunk::accessible_chunks`.
+fn chunks_accessible_from(globe: &Globe, chunk_origin: ChunkOrigin) -> Vec<ChunkOrigin> {
+    use super::globe::GlobeGuts;
+
+    match globe.chunks().get(&chunk_origin) {
+        // TODO: Gah, such slow!
+        Some(chunk) => chunk.accessible_chunks.clone(),
+        None => {
+            let spec = globe.spec();
+            Chunk::list_accessible_chunks(chunk_origin, spec.root_resolution, spec.chunk_resolution)
+        }
+    }
+}
+
 impl<'a> specs::System<'a> for ChunkSystem {
-    type SystemData = (Entities<'a>, WriteStorage<'a, Globe>, ReadStorage<'a, CellDweller>);
+    type SystemData = (
+        Entities<'a>,
+        WriteStorage<'a, Globe>,
+        ReadStorage<'a, CellDweller>,
+        ReadStorage<'a, ChunkLoadAnchor>,
+        ReadStorage<'a, Spatial>,
+    );
 
     fn run(&mut self, data: Self::SystemData) {
         use specs::Join;
 
-        let (entities, mut globes, cds) = data;
+        let (entities, mut globes, cds, chunk_load_anchors, spatials) = data;
+
+        self.add_generated_chunks(&mut globes);
+        self.save_encoded_chunks(&mut globes);
+
+        // Make sure the chunks near all the anchors are present, or on their way.
+        let anchors =
+            self.find_anchors(&entities, &cds, &chunk_load_anchors, &spatials, &globes);
+        let wanted_chunks = self.request_chunks_near_anchors(&anchors, &mut globes);
+        for wanted_chunk in &wanted_chunks {
+            self.last_wanted.insert(*wanted_chunk, self.ticks);
+        }
 
         // If we have too many chunks loaded, then unload some of them.
         for (mut globe, globe_entity) in (&mut globes, &*entities).join() {
-            self.unload_excess_chunks_if_necessary(&mut globe, globe_entity, &cds);
+            self.unload_excess_chunks_if_necessary(
+                &mut globe,
+                globe_entity,
+                &anchors,
+                &wanted_chunks,
+            );
+            // Write out everything that was saved this tick in one go.
+            if let Err(err) = globe.flush_chunk_store() {
+                warn!(
+                    self.log,
+                    "Failed to flush chunk store; will try again next tick";
+                    "error" => format!("{}", err)
+                );
+            }
         }
-        // Make sure the chunks under/near the player are present.
-        for cd in cds.join() {
-            self.ensure_essential_chunks_for_cell_dweller_present(cd, &mut globes);
+
+        self.ticks += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+    use std::time::Duration;
+
+    use slog;
+    use na;
+
+    use grid::{Dir, GridPoint3, PosInOwningRoot};
+    use super::super::ChunkBudget;
+    use super::super::globe::GlobeGuts;
+    use super::*;
+
+    #[test]
+    fn load_chunks_around_every_anchor() {
+        let log = slog::Logger::root(slog::Discard, o!());
+        let mut world = specs::World::new();
+        world.register::<CellDweller>();
+        world.register::<Globe>();
+        world.register::<ChunkLoadAnchor>();
+        world.register::<Spatial>();
+        let mut dispatcher = specs::DispatcherBuilder::new()
+            .add(ChunkSystem::new(&log, 2), "chunk", &[])
+            .build();
+
+        // One cell dweller with the default anchor,
+        // and another far away that only wants its own chunk.
+        let near_pos = GridPoint3::new(1.into(), 20, 35, 41);
+        let far_pos = GridPoint3::new(3.into(), 40, 90, 41);
+
+        let globe = Globe::new_example();
+        let spec = globe.spec();
+        let near_chunk_origin =
+            globe.origin_of_chunk_owning(PosInOwningRoot::new(near_pos, spec.root_resolution));
+        let far_chunk_origin =
+            globe.origin_of_chunk_owning(PosInOwningRoot::new(far_pos, spec.root_resolution));
+        let globe_entity = world.create_entity().with(globe).build();
+
+        world
+            .create_entity()
+            .with(CellDweller::new(near_pos, Dir::default(), spec, Some(globe_entity)))
+            .build();
+        world
+            .create_entity()
+            .with(CellDweller::new(far_pos, Dir::default(), spec, Some(globe_entity)))
+            .with(ChunkLoadAnchor::new(0, 1))
+            .build();
+
+        // Wait for the chunks to be generated.
+        let mut ticks = 0;
+        loop {
+            dispatcher.dispatch(&mut world.res);
+            let globes = world.read::<Globe>();
+            let globe = globes.get(globe_entity).unwrap();
+            let all_loaded = globe.chunk_at(near_chunk_origin).is_some() &&
+                globe.chunk_at(far_chunk_origin).is_some() &&
+                globe.chunks()[&near_chunk_origin].accessible_chunks.iter().all(
+                    |chunk_origin| globe.chunk_at(*chunk_origin).is_some(),
+                );
+            if all_loaded {
+                break;
+            }
+            ticks += 1;
+            assert!(ticks < 10_000, "Took too long to load chunks");
+            thread::sleep(Duration::from_millis(1));
+        }
+
+        // The far cell dweller's anchor has a radius of zero,
+        // so nothing around its chunk should have been loaded.
+        let globes = world.read::<Globe>();
+        let globe = globes.get(globe_entity).unwrap();
+        for chunk_origin in &globe.chunks()[&far_chunk_origin].accessible_chunks {
+            if *chunk_origin != far_chunk_origin {
+                assert!(globe.chunk_at(*chunk_origin).is_none());
+            }
+        }
+    }
+
+    #[test]
+    fn load_chunks_around_anchors_without_cell_dwellers() {
+        let log = slog::Logger::root(slog::Discard, o!());
+        let mut world = specs::World::new();
+        world.register::<CellDweller>();
+        world.register::<Globe>();
+        world.register::<ChunkLoadAnchor>();
+        world.register::<Spatial>();
+        let mut dispatcher = specs::DispatcherBuilder::new()
+            .add(ChunkSystem::new(&log, 2), "chunk", &[])
+            .build();
+
+        // Something like a camera, hovering above the globe.
+        let camera_pos = GridPoint3::new(1.into(), 20, 35, 41);
+        let globe = Globe::new_example();
+        let spec = globe.spec();
+        let camera_chunk_origin =
+            globe.origin_of_chunk_owning(PosInOwningRoot::new(camera_pos, spec.root_resolution));
+        let globe_entity = world
+            .create_entity()
+            .with(globe)
+            .with(Spatial::new_root())
+            .build();
+        let camera_transform = Iso3::new(spec.cell_center_center(camera_pos).coords, na::zero());
+        world
+            .create_entity()
+            .with(Spatial::new(globe_entity, camera_transform))
+            .with(ChunkLoadAnchor::new(0, 0))
+            .build();
+
+        let mut ticks = 0;
+        loop {
+            dispatcher.dispatch(&mut world.res);
+            let globes = world.read::<Globe>();
+            let globe = globes.get(globe_entity).unwrap();
+            if globe.chunk_at(camera_chunk_origin).is_some() {
+                break;
+            }
+            ticks += 1;
+            assert!(ticks < 10_000, "Took too long to load chunks");
+            thread::sleep(Duration::from_millis(1));
         }
     }
+
+    #[test]
+    fn never_unload_pinned_or_unsaved_chunks() {
+        let log = slog::Logger::root(slog::Discard, o!());
+        let mut world = specs::World::new();
+        world.register::<CellDweller>();
+        world.register::<Globe>();
+        world.register::<ChunkLoadAnchor>();
+        world.register::<Spatial>();
+        let mut dispatcher = specs::DispatcherBuilder::new()
+            .add(ChunkSystem::new(&log, 2), "chunk", &[])
+            .build();
+
+        let anchor_pos = GridPoint3::new(1.into(), 20, 35, 41);
+        let modified_pos = GridPoint3::new(3.into(), 40, 90, 41);
+        let unmodified_pos = GridPoint3::new(4.into(), 40, 90, 41);
+
+        // Squeeze the globe down to a budget that it can't possibly meet,
+        // and load a couple of chunks that nobody wants, modifying one of them.
+        // There's no chunk store, so the modified chunk can't be saved.
+        let mut globe = Globe::new_example();
+        globe.set_chunk_budget(ChunkBudget::new(1, 0));
+        let spec = globe.spec();
+        let chunk_origin_owning = |globe: &Globe, pos| {
+            globe.origin_of_chunk_owning(PosInOwningRoot::new(pos, spec.root_resolution))
+        };
+        let anchor_chunk_origin = chunk_origin_owning(&globe, anchor_pos);
+        let modified_chunk_origin = chunk_origin_owning(&globe, modified_pos);
+        let unmodified_chunk_origin = chunk_origin_owning(&globe, unmodified_pos);
+        globe.ensure_chunk_present(modified_chunk_origin).unwrap();
+        globe.ensure_chunk_present(unmodified_chunk_origin).unwrap();
+        globe
+            .chunks_mut()
+            .get_mut(&modified_chunk_origin)
+            .unwrap()
+            .mark_as_changed();
+        let globe_entity = world.create_entity().with(globe).build();
+
+        world
+            .create_entity()
+            .with(CellDweller::new(anchor_pos, Dir::default(), spec, Some(globe_entity)))
+            .with(ChunkLoadAnchor::new(0, 0))
+            .build();
+
+        // Wait for the anchor's chunk to be generated,
+        // and for the unwanted chunk to be unloaded.
+        let mut ticks = 0;
+        loop {
+            dispatcher.dispatch(&mut world.res);
+            let globes = world.read::<Globe>();
+            let globe = globes.get(globe_entity).unwrap();
+            if globe.chunk_at(anchor_chunk_origin).is_some() &&
+                globe.chunk_at(unmodified_chunk_origin).is_none()
+            {
+                break;
+            }
+            ticks += 1;
+            assert!(ticks < 10_000, "Took too long to load chunks");
+            thread::sleep(Duration::from_millis(1));
+        }
+
+        // Run a few more times for good measure.
+        for _ in 0..10 {
+            dispatcher.dispatch(&mut world.res);
+        }
+        let globes = world.read::<Globe>();
+        let globe = globes.get(globe_entity).unwrap();
+        assert!(globe.chunk_at(anchor_chunk_origin).is_some());
+        assert!(globe.chunk_at(modified_chunk_origin).is_some());
+        assert!(globe.chunk_at(unmodified_chunk_origin).is_none());
+    }
+
+    #[test]
+    fn unload_chunks_once_budget_is_reached() {
+        let log = slog::Logger::root(slog::Discard, o!());
+        let mut world = specs::World::new();
+        let globe_entity = world.create_entity().build();
+        let mut chunk_system = ChunkSystem::new(&log, 2);
+
+        let mut globe = Globe::new_example();
+        globe.set_chunk_budget(ChunkBudget::new(3, 1));
+        let spec = globe.spec();
+        let chunk_origins: Vec<ChunkOrigin> = (0..3)
+            .map(|z| {
+                ChunkOrigin::new(
+                    GridPoint3::new(1.into(), 16, 32, z * spec.chunk_resolution[2]),
+                    spec.root_resolution,
+                    spec.chunk_resolution,
+                )
+            })
+            .collect();
+        let wanted_chunks = HashSet::new();
+
+        // Just under the limit; leave them be.
+        globe.ensure_chunk_present(chunk_origins[0]).unwrap();
+        globe.ensure_chunk_present(chunk_origins[1]).unwrap();
+        chunk_system.unload_excess_chunks_if_necessary(
+            &mut globe,
+            globe_entity,
+            &[],
+            &wanted_chunks,
+        );
+        assert_eq!(2, globe.chunks().len());
+
+        // Right on the limit; cull them.
+        globe.ensure_chunk_present(chunk_origins[2]).unwrap();
+        chunk_system.unload_excess_chunks_if_necessary(
+            &mut globe,
+            globe_entity,
+            &[],
+            &wanted_chunks,
+        );
+        assert_eq!(1, globe.chunks().len());
+    }
 }
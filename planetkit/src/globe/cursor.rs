@@ -1,3 +1,5 @@
+use std::io;
+
 use grid::GridPoint3;
 use super::{Globe, ChunkOrigin};
 use super::chunk::{Chunk, Cell};
@@ -173,10 +175,10 @@ impl<'a> Cursor<'a> {
 
 impl<'a> CursorMut<'a> {
     // See `Globe::ensure_chunk_present`.
-    pub fn ensure_chunk_present(&mut self) {
+    pub fn ensure_chunk_present(&mut self) -> io::Result<()> {
         let chunk_origin: ChunkOrigin =
             self.globe.origin_of_chunk_in_same_root_containing(self.pos);
-        self.globe.ensure_chunk_present(chunk_origin);
+        self.globe.ensure_chunk_present(chunk_origin)
     }
 
     /// Only use this if you don't care about which chunk
@@ -0,0 +1,268 @@
+// Batched edits to cells in a `Globe`.
+
+use std::collections::HashMap;
+use std::thread;
+
+use grid::{GridPoint3, PosInOwningRoot, Neighbors};
+use super::{ChunkOrigin, MaterialId, is_point_on_chunk_edge};
+use super::chunk::Cell;
+use super::globe::{Globe, ChunkNotReady};
+use super::journal::{CellChange, Transaction};
+
+/// A batch of changes to cells in a `Globe`; see `Globe::edit`.
+///
+/// When the edit is committed, it does all the bookkeeping
+/// needed to keep the globe consistent:
+///
+/// - Chunks that own edited cells are marked as having unsaved changes.
+/// - Edited cells on the edge of their chunk are copied to any neighboring
+///   chunks that share them.
+/// - The views of all chunks whose appearance might be affected by
+///   the edited cells are marked as dirty.
+/// - Light is updated around the cells that changed; see `Globe::update_light`.
+/// - Cells that changed, and their neighbors, are marked as unsettled,
+///   so that, e.g., `WaterFlowSystem` can let water flow into new holes;
+///   see `Globe::track_unsettled_cells`.
+///
+/// This is done once per batch, no matter how many cells were edited,
+/// or how many times each one was changed.
+///
+/// If the globe has an `EditJournal`, then the batch is also recorded
+/// there as a single `Transaction`.
+///
+/// An edit that is dropped without calling `commit` is committed then
+/// instead, unless the thread is panicking, in which case the globe
+/// may be left with cells that have changed without any of the above.
+pub struct GlobeEdit<'a> {
+    globe: &'a mut Globe,
+    // What each edited cell looked like before this edit.
+    edited_cells: HashMap<PosInOwningRoot, Cell>,
+    // False when undoing or redoing, which the journal keeps track of itself,
+    // or for changes that shouldn't be recorded at all.
+    record_in_journal: bool,
+    committed: bool,
+}
+
+impl Globe {
+    /// Start a batch of changes to cells in the globe.
+    ///
+    /// Use this rather than `authoritative_cell_mut` to change cells;
+    /// it takes care of keeping chunks in sync with their neighbors
+    /// and their views.
+    pub fn edit(&mut self) -> GlobeEdit {
+        GlobeEdit {
+            globe: self,
+            edited_cells: HashMap::new(),
+            record_in_journal: true,
+            committed: false,
+        }
+    }
+
+    /// Like `edit`, but the changes aren't recorded in the globe's `EditJournal`,
+    /// so they can't be undone, and don't appear in its change feed.
+    ///
+    /// This is meant for changes that follow on automatically from other
+    /// changes, e.g., water flowing into a hole, which every copy of the globe
+    /// can work out for itself.
+    pub fn edit_without_recording(&mut self) -> GlobeEdit {
+        let mut edit = self.edit();
+        edit.record_in_journal = false;
+        edit
+    }
+
+    /// Revert the most recent transaction in the globe's `EditJournal`.
+    ///
+    /// Returns `Ok(false)` if there is nothing to undo, or the globe has no journal.
+    /// Returns an error without changing anything if any of the chunks
+    /// involved aren't loaded.
+    pub fn undo(&mut self) -> Result<bool, ChunkNotReady> {
+        let transaction = match self.edit_journal().and_then(|journal| journal.next_undo()) {
+            Some(transaction) => transaction.inverse(),
+            None => return Ok(false),
+        };
+        self.apply_without_recording(&transaction)?;
+        self.edit_journal_mut()
+            .expect("Edit journal disappeared")
+            .finish_undo();
+        Ok(true)
+    }
+
+    /// Reapply the most recently undone transaction in the globe's `EditJournal`.
+    ///
+    /// Returns `Ok(false)` if there is nothing to redo, or the globe has no journal.
+    /// Returns an error without changing anything if any of the chunks
+    /// involved aren't loaded.
+    pub fn redo(&mut self) -> Result<bool, ChunkNotReady> {
+        let transaction = match self.edit_journal().and_then(|journal| journal.next_redo()) {
+            Some(transaction) => transaction.clone(),
+            None => return Ok(false),
+        };
+        self.apply_without_recording(&transaction)?;
+        self.edit_journal_mut()
+            .expect("Edit journal disappeared")
+            .finish_redo();
+        Ok(true)
+    }
+
+    /// Apply all the changes in `transaction`, e.g., one received from
+    /// another copy of the globe's change feed.
+    ///
+    /// The changes are recorded in the globe's journal as a new transaction, if it has one.
+    /// Returns an error without changing anything if any of the chunks
+    /// involved aren't loaded.
+    pub fn apply_transaction(&mut self, transaction: &Transaction) -> Result<(), ChunkNotReady> {
+        self.ensure_chunks_ready_for(transaction)?;
+        let mut edit = self.edit();
+        edit.apply(transaction);
+        edit.commit();
+        Ok(())
+    }
+
+    fn apply_without_recording(&mut self, transaction: &Transaction) -> Result<(), ChunkNotReady> {
+        self.ensure_chunks_ready_for(transaction)?;
+        let mut edit = self.edit_without_recording();
+        edit.apply(transaction);
+        edit.commit();
+        Ok(())
+    }
+
+    // Make sure we can apply the whole transaction before we apply any of it.
+    fn ensure_chunks_ready_for(&self, transaction: &Transaction) -> Result<(), ChunkNotReady> {
+        for change in &transaction.changes {
+            let chunk_origin = self.origin_of_chunk_owning(change.pos);
+            if self.chunk_at(chunk_origin).is_none() {
+                return Err(ChunkNotReady { chunk_origin: chunk_origin });
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<'a> GlobeEdit<'a> {
+    /// Get the cell at `pos`, including any changes made so far in this edit.
+    ///
+    /// `pos` need not be in the root that owns the cell; this
+    /// always reads the authoritative copy of the cell.
+    ///
+    /// Returns an error if the chunk owning the cell isn't loaded yet.
+    pub fn cell(&self, pos: GridPoint3) -> Result<&Cell, ChunkNotReady> {
+        let pos_in_owning_root = PosInOwningRoot::new(pos, self.globe.spec().root_resolution);
+        let chunk_origin = self.globe.origin_of_chunk_owning(pos_in_owning_root);
+        if self.globe.chunk_at(chunk_origin).is_none() {
+            return Err(ChunkNotReady { chunk_origin: chunk_origin });
+        }
+        Ok(self.globe.authoritative_cell(pos_in_owning_root))
+    }
+
+    /// Get the cell at `pos` for modification.
+    ///
+    /// `pos` need not be in the root that owns the cell; this
+    /// always modifies the authoritative copy of the cell.
+    ///
+    /// Returns an error if the chunk owning the cell isn't loaded yet.
+    pub fn cell_mut(&mut self, pos: GridPoint3) -> Result<&mut Cell, ChunkNotReady> {
+        let pos_in_owning_root = PosInOwningRoot::new(pos, self.globe.spec().root_resolution);
+        let chunk_origin = self.globe.origin_of_chunk_owning(pos_in_owning_root);
+        if self.globe.chunk_at(chunk_origin).is_none() {
+            return Err(ChunkNotReady { chunk_origin: chunk_origin });
+        }
+        let old_cell = *self.globe.authoritative_cell(pos_in_owning_root);
+        self.edited_cells.entry(pos_in_owning_root).or_insert(old_cell);
+        Ok(self.globe.authoritative_cell_mut(pos_in_owning_root))
+    }
+
+    /// Change what the cell at `pos` is made of.
+    ///
+    /// Returns an error if the chunk owning the cell isn't loaded yet.
+    pub fn set_material(
+        &mut self,
+        pos: GridPoint3,
+        material: MaterialId,
+    ) -> Result<(), ChunkNotReady> {
+        self.cell_mut(pos)?.material = material;
+        Ok(())
+    }
+
+    /// Finish the batch of changes, doing all the bookkeeping
+    /// described on `GlobeEdit`.
+    pub fn commit(mut self) {
+        self.finish();
+        self.committed = true;
+    }
+
+    // Panics if any of the chunks involved aren't loaded.
+    fn apply(&mut self, transaction: &Transaction) {
+        for change in &transaction.changes {
+            *self.cell_mut(change.pos.into()).expect(
+                "Chunk should have been loaded",
+            ) = change.new;
+        }
+    }
+
+    fn finish(&mut self) {
+        let chunk_resolution = self.globe.spec().chunk_resolution;
+
+        // Bump the version of each chunk's owned shared cells just once,
+        // no matter how many of them changed, and then propagate the
+        // changes to neighboring chunks.
+        let mut chunks_with_edited_edges: HashMap<ChunkOrigin, PosInOwningRoot> = HashMap::new();
+        for pos in self.edited_cells.keys() {
+            if is_point_on_chunk_edge(*pos.pos(), chunk_resolution) {
+                let chunk_origin = self.globe.origin_of_chunk_owning(*pos);
+                chunks_with_edited_edges.insert(chunk_origin, *pos);
+            }
+        }
+        for (chunk_origin, pos) in chunks_with_edited_edges {
+            self.globe.increment_chunk_owned_edge_version_for_cell(pos);
+            self.globe.push_shared_cells_for_chunk(chunk_origin);
+        }
+
+        // Each cell might affect the visibility of cells in the chunks
+        // around it, so mark all their views as dirty.
+        for pos in self.edited_cells.keys() {
+            self.globe.mark_chunk_views_affected_by_cell_as_dirty((*pos).into());
+        }
+
+        let mut changes = Vec::new();
+        for (&pos, &old_cell) in &self.edited_cells {
+            let new_cell = *self.globe.authoritative_cell(pos);
+            // Leave out cells that ended up the same as they started.
+            if new_cell != old_cell {
+                changes.push(CellChange {
+                    pos: pos,
+                    old: old_cell,
+                    new: new_cell,
+                });
+            }
+        }
+
+        // Light might now shine into, or be blocked by, the cells that changed.
+        self.globe.update_light(changes.iter().map(|change| change.pos));
+
+        // Anything near the cells that changed might need to move now.
+        let root_resolution = self.globe.spec().root_resolution;
+        for change in &changes {
+            self.globe.mark_cell_unsettled(change.pos);
+            for neighbor in Neighbors::new(change.pos.into(), root_resolution) {
+                self.globe.mark_cell_unsettled(PosInOwningRoot::new(neighbor, root_resolution));
+            }
+        }
+
+        if !self.record_in_journal {
+            return;
+        }
+        if let Some(edit_journal) = self.globe.edit_journal_mut() {
+            edit_journal.record(Transaction { changes: changes });
+        }
+    }
+}
+
+impl<'a> Drop for GlobeEdit<'a> {
+    fn drop(&mut self) {
+        // Don't risk panicking again while unwinding; that would abort.
+        if self.committed || thread::panicking() {
+            return;
+        }
+        self.finish();
+    }
+}
@@ -0,0 +1,135 @@
+// Finding connected regions of cells.
+
+use std::collections::{HashSet, VecDeque};
+use std::io;
+
+use grid::{GridPoint3, PosInOwningRoot, Neighbors};
+use super::{ChunkOrigin, CursorMut};
+use super::chunk::Cell;
+use super::globe::Globe;
+
+/// Connected cells found by `Globe::flood_fill`.
+#[derive(Clone, Debug, Default)]
+pub struct CellRegion {
+    /// Every cell found in the region, expressed in its owning root.
+    pub cells: HashSet<PosInOwningRoot>,
+    /// Origins of all the chunks that own cells in the region,
+    /// in the order they were first reached.
+    pub chunks: Vec<ChunkOrigin>,
+    /// Whether the fill stopped at its limit before it found every
+    /// connected cell.
+    ///
+    /// If not, then the region is completely enclosed by cells that
+    /// don't match, e.g., a pocket of air with no way out to the sky.
+    pub hit_limit: bool,
+}
+
+impl Globe {
+    /// Find all the cells for which `predicate` is true that are connected
+    /// to `start` through other such cells, either side by side,
+    /// or one above the other.
+    ///
+    /// Stops after finding `max_cells` cells, and sets `hit_limit` on the region
+    /// if there were more to find. The region is empty if `predicate` isn't
+    /// true for `start` itself.
+    ///
+    /// Loads or generates any chunks it needs to look at;
+    /// see `ensure_chunk_present`. Returns an error if any of those
+    /// chunks couldn't be loaded.
+    pub fn flood_fill<P>(
+        &mut self,
+        start: GridPoint3,
+        max_cells: usize,
+        mut predicate: P,
+    ) -> io::Result<CellRegion>
+    where
+        P: FnMut(&Cell) -> bool,
+    {
+        self.flood_fill_with(start, max_cells, &mut predicate)
+    }
+
+    /// Group `seeds` into connected regions of cells for which `predicate`
+    /// is true, flood filling from each seed that isn't already part of a region
+    /// found from an earlier seed. See `flood_fill`.
+    ///
+    /// Seeds for which `predicate` is false are ignored, so this never
+    /// returns any empty regions.
+    pub fn connected_regions<P>(
+        &mut self,
+        seeds: &[GridPoint3],
+        max_cells_per_region: usize,
+        mut predicate: P,
+    ) -> io::Result<Vec<CellRegion>>
+    where
+        P: FnMut(&Cell) -> bool,
+    {
+        let root_resolution = self.spec().root_resolution;
+        let mut regions: Vec<CellRegion> = Vec::new();
+        for &seed in seeds {
+            let seed_in_owning_root = PosInOwningRoot::new(seed, root_resolution);
+            if regions.iter().any(|region| region.cells.contains(&seed_in_owning_root)) {
+                continue;
+            }
+            let region = self.flood_fill_with(seed, max_cells_per_region, &mut predicate)?;
+            if !region.cells.is_empty() {
+                regions.push(region);
+            }
+        }
+        Ok(regions)
+    }
+
+    fn flood_fill_with<P>(
+        &mut self,
+        start: GridPoint3,
+        max_cells: usize,
+        predicate: &mut P,
+    ) -> io::Result<CellRegion>
+    where
+        P: FnMut(&Cell) -> bool,
+    {
+        let root_resolution = self.spec().root_resolution;
+        let start = PosInOwningRoot::new(start, root_resolution);
+        let chunk_origin = self.origin_of_chunk_owning(start);
+        let mut cursor = CursorMut::new_in_chunk(self, chunk_origin);
+
+        let mut region = CellRegion::default();
+        let mut chunks_reached: HashSet<ChunkOrigin> = HashSet::new();
+        // Every cell we've queued up to look at, whether it matched or not.
+        let mut cells_seen: HashSet<PosInOwningRoot> = HashSet::new();
+        // Go breadth-first, so that if we hit the limit we'll have found
+        // the cells nearest to the start.
+        let mut cells_to_visit: VecDeque<PosInOwningRoot> = VecDeque::new();
+        cells_seen.insert(start);
+        cells_to_visit.push_back(start);
+        while let Some(pos) = cells_to_visit.pop_front() {
+            cursor.set_pos(pos.into());
+            cursor.ensure_chunk_present()?;
+            {
+                // Non-lexical lifetimes SVP.
+                let cell = cursor.cell().expect(
+                    "We just ensured the chunk is present, but apparently it's not. Kaboom!",
+                );
+                if !predicate(cell) {
+                    continue;
+                }
+            }
+            if region.cells.len() >= max_cells {
+                region.hit_limit = true;
+                break;
+            }
+
+            region.cells.insert(pos);
+            let chunk_origin = cursor.globe().origin_of_chunk_owning(pos);
+            if chunks_reached.insert(chunk_origin) {
+                region.chunks.push(chunk_origin);
+            }
+            for neighbor in Neighbors::new(pos.into(), root_resolution) {
+                let neighbor = PosInOwningRoot::new(neighbor, root_resolution);
+                if cells_seen.insert(neighbor) {
+                    cells_to_visit.push_back(neighbor);
+                }
+            }
+        }
+        Ok(region)
+    }
+}
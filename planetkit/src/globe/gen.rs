@@ -1,15 +1,54 @@
+use std::ops::Range;
+
 use noise;
 
-use grid::{GridPoint2, GridPoint3};
+use grid::{GridCoord, GridPoint2, GridPoint3};
+use types::*;
 use super::spec::Spec;
-use super::chunk::{Cell, Material};
+use super::chunk::Cell;
+use super::material::MaterialId;
+use super::biome::{Biome, Climate};
+use super::icosahedron;
+
+/// Source of the naturally generated content of a `Globe`.
+///
+/// `Globe` asks its generator for every cell of a chunk when that chunk
+/// is first needed, so implementations must be deterministic: the same
+/// inputs must always give the same cell, regardless of what order
+/// chunks are generated in, or on which peer.
+pub trait WorldGen: Send + Sync {
+    /// Distance from the center of the globe to the surface of the land
+    /// in the given column. This is only a hint for finding the surface,
+    /// e.g., when placing new entities, so it doesn't need to be exact.
+    fn land_height(&self, column: GridPoint2) -> f64;
+
+    /// Generate the given cell as it would be in the untouched world.
+    fn cell_at(&self, grid_point: GridPoint3) -> Cell;
+
+    /// Generate the cells in `column` at each height in `z_range`,
+    /// bottom first, exactly as `cell_at` would.
+    ///
+    /// `generate_chunk` asks for cells this way, so generators that do a lot
+    /// of work for each column, e.g., to find the height of the land,
+    /// should override this to only do that work once.
+    fn cells_in_column(&self, column: GridPoint2, z_range: Range<GridCoord>) -> Vec<Cell> {
+        z_range.map(|z| self.cell_at(column.with_z(z))).collect()
+    }
+}
 
 // TODO: turn this into a component that we can slap onto a Globe
 // or other globe-oid (distant point?).
 
-/// Globe content generator. Stores all the state for generating
-/// the terrain and any other parts of the globe that are derived
-/// from its seed.
+/// Default `WorldGen`, making rolling fBm noise terrain with oceans,
+/// a variety of biomes depending on the climate, and caves
+/// (see `CaveParams`).
+///
+/// Beneath each biome's soil are layers of stone, then slate,
+/// and finally bedrock at the very bottom, with veins of ore
+/// scattered through the rock.
+///
+/// Stores all the state for generating the terrain and any other
+/// parts of the globe that are derived from its seed.
 ///
 /// Will eventually do some basic caching, etc., but is pretty dumb
 /// right now.
@@ -20,6 +59,69 @@ use super::chunk::{Cell, Material};
 pub struct Gen {
     spec: Spec,
     terrain_noise: noise::Fbm<f64>,
+    // Small-scale bumps on top of the terrain; scaled by biome roughness.
+    detail_noise: noise::Fbm<f64>,
+    temperature_noise: noise::Fbm<f64>,
+    moisture_noise: noise::Fbm<f64>,
+    cavern_noise: noise::Perlin,
+    tunnel_noise: [noise::Perlin; 2],
+    // One for each of `ORES`.
+    ore_noise: Vec<noise::Perlin>,
+}
+
+// Number of cells at the bottom of the world made of bedrock,
+// for every `WorldGen` that makes any.
+pub(crate) const BEDROCK_THICKNESS: GridCoord = 2;
+
+// Proportion of the way from the surface of the land down to the floor
+// at which stone gives way to slate.
+const SLATE_MIN_DEPTH: f64 = 0.6;
+
+// Where and how often each kind of ore shows up.
+struct OreParams {
+    material: MaterialId,
+    // Range of depths to find the ore at, as a proportion of the way
+    // from the surface of the land down to the floor.
+    min_depth: f64,
+    max_depth: f64,
+    // Rough size of veins, in cells.
+    wavelength: f64,
+    // Put ore wherever noise (roughly in [-1, 1]) is above this;
+    // bigger values make for rarer ore.
+    threshold: f64,
+}
+
+// TODO: make these part of the spec, or maybe even the material registry,
+// once we know what people want to do with them.
+const ORES: [OreParams; 3] = [
+    OreParams {
+        material: MaterialId::COAL,
+        min_depth: 0.05,
+        max_depth: 0.5,
+        wavelength: 6.0,
+        threshold: 0.6,
+    },
+    OreParams {
+        material: MaterialId::IRON_ORE,
+        min_depth: 0.2,
+        max_depth: 0.8,
+        wavelength: 4.0,
+        threshold: 0.6,
+    },
+    OreParams {
+        material: MaterialId::GOLD_ORE,
+        min_depth: 0.6,
+        max_depth: 1.0,
+        wavelength: 3.0,
+        threshold: 0.65,
+    },
+];
+
+// Everything about a column that we need to figure out which
+// material each cell in it is made of.
+struct Column {
+    land_height: f64,
+    biome: Biome,
 }
 
 impl Gen {
@@ -27,10 +129,10 @@ impl Gen {
         use noise::Seedable;
         use noise::MultiFractal;
 
-        assert!(spec.is_valid(), "Invalid globe spec!");
+        if let Err(err) = spec.validate() {
+            panic!("Invalid globe spec: {}", err);
+        }
 
-        // TODO: get parameters from spec
-        //
         // TODO: store this function... when you figure
         // out what's going on with the types.
         // ("expected fn pointer, found fn item")
@@ -39,70 +141,210 @@ impl Gen {
         // changed to deprecate PermutationTable; is it now stored
         // within Fbm? This might be super-slow now...
         let terrain_noise = noise::Fbm::<f64>::new()
-        // TODO: make wavelength etc. part of spec;
-        // the octaves and wavelength of noise you want
-        // will probably depend on planet size.
-            .set_octaves(6)
-            .set_frequency(1.0 / 700.0)
+            .set_octaves(spec.terrain.octaves)
+            .set_frequency(1.0 / spec.terrain.wavelength)
+            .set_persistence(spec.terrain.persistence)
             // TODO: probably allow a bigger seed; what's the smallest usize on any real platform?
             .set_seed(spec.seed as usize);
+        let detail_noise = noise::Fbm::<f64>::new()
+            .set_octaves(3)
+            .set_frequency(1.0 / 40.0)
+            .set_seed(spec.seed.wrapping_add(1) as usize);
+        // Climate noise is sampled on the unit sphere rather than at sea level,
+        // so that climate zones are a similar size on globes of any size.
+        let temperature_noise = noise::Fbm::<f64>::new()
+            .set_octaves(3)
+            .set_frequency(2.0)
+            .set_seed(spec.seed.wrapping_add(2) as usize);
+        let moisture_noise = noise::Fbm::<f64>::new()
+            .set_octaves(3)
+            .set_frequency(2.0)
+            .set_seed(spec.seed.wrapping_add(3) as usize);
+        let cavern_noise = noise::Perlin::new().set_seed(spec.seed.wrapping_add(4) as usize);
+        let tunnel_noise = [
+            noise::Perlin::new().set_seed(spec.seed.wrapping_add(5) as usize),
+            noise::Perlin::new().set_seed(spec.seed.wrapping_add(6) as usize),
+        ];
+        let ore_noise = (0..ORES.len())
+            .map(|i| {
+                noise::Perlin::new().set_seed(spec.seed.wrapping_add(7 + i as u32) as usize)
+            })
+            .collect();
         Gen {
             spec: spec,
             terrain_noise: terrain_noise,
+            detail_noise: detail_noise,
+            temperature_noise: temperature_noise,
+            moisture_noise: moisture_noise,
+            cavern_noise: cavern_noise,
+            tunnel_noise: tunnel_noise,
+            ore_noise: ore_noise,
         }
     }
 
-    pub fn land_height(&self, column: GridPoint2) -> f64 {
+    // Height of the column above or below sea level before any biome-specific
+    // bumps are added, as a proportion of the maximum; i.e. roughly in [-1, 1].
+    fn base_elevation(&self, unit_pt3: Pt3) -> f64 {
         use noise::NoiseModule;
 
-        // Calculate height for this cell from world spec.
-        // To do this, project the cell onto a sea-level sphere
-        // and sample 3D simplex noise to get a height value.
+        // Sample noise on a sea-level sphere.
         //
         // Basing on sea-level lets us use similar wavelengths
         // to similar effect, regardless of the globe radius.
-        //
-        // TODO: split out a proper world generator
-        // that layers in lots of different kinds of noise etc.
-        let sea_level_pt3 = self.spec.cell_center_on_unit_sphere(column) * self.spec.ocean_radius;
-        // Vary a little bit around 1.0.
-        let delta = self.terrain_noise.get([sea_level_pt3.x, sea_level_pt3.y, sea_level_pt3.z])
-            * (self.spec.ocean_radius - self.spec.floor_radius)
-            // TODO: this 0.9 is only to stop the dirt level
-            // going below bedrock. Need something a bit more sophisticated
-            // than this eventually.
-            //
-            // Also... OpenSimplex, which FBM uses, appears to be totally bonkers?
-            // https://github.com/brendanzab/noise-rs/issues/149
-            * 0.45;
-        self.spec.ocean_radius + delta
+        let sea_level_pt3 = unit_pt3 * self.spec.ocean_radius;
+        self.terrain_noise.get([sea_level_pt3.x, sea_level_pt3.y, sea_level_pt3.z]) +
+            self.spec.terrain.sea_level_bias
+    }
+
+    fn climate_at(&self, unit_pt3: Pt3, elevation: f64) -> Climate {
+        use noise::NoiseModule;
+        use std::f64::consts::FRAC_PI_2;
+
+        let latitude = unit_pt3.coords.dot(&icosahedron::north_pole()).max(-1.0).min(1.0).asin();
+
+        let noise_pt = [unit_pt3.x, unit_pt3.y, unit_pt3.z];
+        // Cold at the poles and up high; hot at the equator.
+        let temperature = 1.0 - latitude.abs() / FRAC_PI_2 - elevation.max(0.0) * 0.5 +
+            self.temperature_noise.get(noise_pt) * 0.2;
+        let moisture = 0.5 + self.moisture_noise.get(noise_pt) * 0.6;
+        Climate {
+            temperature: temperature,
+            moisture: moisture,
+        }
+    }
+
+    /// Climate of the given column; see `Climate`.
+    pub fn climate(&self, column: GridPoint2) -> Climate {
+        let unit_pt3 = self.spec.cell_center_on_unit_sphere(column);
+        let elevation = self.base_elevation(unit_pt3);
+        self.climate_at(unit_pt3, elevation)
+    }
+
+    /// Which biome the given column is in; see `Biome`.
+    pub fn biome(&self, column: GridPoint2) -> Biome {
+        self.column(column).biome
     }
 
-    pub fn cell_at(&self, grid_point: GridPoint3) -> Cell {
-        let land_height = self.land_height(grid_point.rxy);
+    // Whether the given cell, which is `depth` cells below the
+    // surface of the land in `column`, should be carved out.
+    fn is_cave(&self, grid_point: GridPoint3, column: &Column, depth: f64) -> bool {
+        use noise::NoiseModule;
+
+        let caves = self.spec.caves;
+        // Don't let caves open up to the sea; we'd end up with
+        // air pockets under the water.
+        let min_depth = if column.biome == Biome::Ocean {
+            caves.min_depth.max(2.0)
+        } else {
+            caves.min_depth
+        };
+        if depth < min_depth {
+            return false;
+        }
+
+        let cell_pt3 = self.spec.cell_center_center(grid_point) /
+            (caves.wavelength * self.spec.block_height);
+        let noise_pt = [cell_pt3.x, cell_pt3.y, cell_pt3.z];
+        if self.cavern_noise.get(noise_pt) > caves.cavern_threshold {
+            return true;
+        }
+        self.tunnel_noise[0].get(noise_pt).abs() < caves.tunnel_width &&
+            self.tunnel_noise[1].get(noise_pt).abs() < caves.tunnel_width
+    }
+
+    // What kind of rock the given cell is made of, if it's below the soil.
+    fn rock_at(&self, grid_point: GridPoint3, column: &Column, cell_height: f64) -> MaterialId {
+        use noise::NoiseModule;
+
+        // How far down we are, as a proportion of the way to the floor.
+        let depth = (column.land_height - cell_height) /
+            (column.land_height - self.spec.floor_radius);
+        let cell_pt3 = self.spec.cell_center_center(grid_point) / self.spec.block_height;
+        for (ore, ore_noise) in ORES.iter().zip(self.ore_noise.iter()) {
+            if depth < ore.min_depth || depth > ore.max_depth {
+                continue;
+            }
+            let noise_pt = cell_pt3 / ore.wavelength;
+            if ore_noise.get([noise_pt.x, noise_pt.y, noise_pt.z]) > ore.threshold {
+                return ore.material;
+            }
+        }
+        if depth < SLATE_MIN_DEPTH {
+            MaterialId::STONE
+        } else {
+            MaterialId::SLATE
+        }
+    }
+
+    fn column(&self, column: GridPoint2) -> Column {
+        use noise::NoiseModule;
+
+        let unit_pt3 = self.spec.cell_center_on_unit_sphere(column);
+        let elevation = self.base_elevation(unit_pt3);
+        let climate = self.climate_at(unit_pt3, elevation);
+        let biome = Biome::choose(climate, elevation);
+
+        // Vary a little bit around sea level.
+        let base_delta = elevation * (self.spec.ocean_radius - self.spec.floor_radius) *
+            self.spec.terrain.amplitude;
+        // Then add some bumps, depending on how rough this kind of terrain is.
+        let sea_level_pt3 = unit_pt3 * self.spec.ocean_radius;
+        let detail_delta = self.detail_noise.get([sea_level_pt3.x, sea_level_pt3.y, sea_level_pt3.z])
+            * biome.roughness(elevation)
+            * self.spec.block_height;
+        Column {
+            land_height: self.spec.ocean_radius + base_delta + detail_delta,
+            biome: biome,
+        }
+    }
+
+    // The given cell, which must be in `column`.
+    fn cell_in_column(&self, grid_point: GridPoint3, column: &Column) -> Cell {
         let cell_pt3 = self.spec.cell_center_center(grid_point);
         // TEMP: ...
         let cell_height = cell_pt3.coords.norm();
-        let material = if cell_height < land_height {
-            Material::Dirt
+        let material = if cell_height < column.land_height {
+            let depth = (column.land_height - cell_height) / self.spec.block_height;
+            if grid_point.z < BEDROCK_THICKNESS {
+                // Leave the very bottom of the world intact,
+                // so nobody falls out of it.
+                MaterialId::BEDROCK
+            } else if self.is_cave(grid_point, column, depth) {
+                MaterialId::AIR
+            } else if depth < column.biome.soil_depth() {
+                column.biome.surface_material()
+            } else {
+                self.rock_at(grid_point, column, cell_height)
+            }
         } else if cell_height < self.spec.ocean_radius {
-            Material::Water
+            MaterialId::WATER
         } else {
-            Material::Air
+            MaterialId::AIR
         };
         Cell {
             material: material,
-            // `Globe` fills this in; it's not really a property
-            // of the naturally generated world, and it's not
-            // deterministic from the world seed, so we don't
-            // want to pollute `Gen` with it.
-            //
-            // TODO: probably remove this? We're just using
-            // temporarily to create some texture across
-            // cells to make them easy to tell apart and look
-            // kinda nice, but this probably isn't a great
-            // long-term solution...
-            shade: 1.0,
+            // `Globe` works these out once the chunk is loaded;
+            // they depend on what's around the cell, including
+            // any changes made since it was generated.
+            sky_light: 0,
+            block_light: 0,
         }
     }
 }
+
+impl WorldGen for Gen {
+    fn land_height(&self, column: GridPoint2) -> f64 {
+        self.column(column).land_height
+    }
+
+    fn cell_at(&self, grid_point: GridPoint3) -> Cell {
+        self.cell_in_column(grid_point, &self.column(grid_point.rxy))
+    }
+
+    fn cells_in_column(&self, column: GridPoint2, z_range: Range<GridCoord>) -> Vec<Cell> {
+        let column_params = self.column(column);
+        z_range
+            .map(|z| self.cell_in_column(column.with_z(z), &column_params))
+            .collect()
+    }
+}
@@ -1,33 +1,62 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::io;
+use std::mem;
+use std::sync::Arc;
 
 use specs;
 
-use grid::{GridPoint3, PosInOwningRoot, Neighbors};
+use grid::{GridPoint2, GridPoint3, PosInOwningRoot, Neighbors};
 use super::{origin_of_chunk_owning, origin_of_chunk_in_same_root_containing};
 use super::ChunkOrigin;
 use super::chunk::{Chunk, Cell};
 use super::spec::Spec;
-use super::gen::Gen;
+use super::gen::{WorldGen, Gen};
 use super::chunk_pair::{ChunkPairOrigins, ChunkPair};
+use super::chunk_store::ChunkStore;
+use super::chunk_budget::ChunkBudget;
+use super::journal::EditJournal;
+use super::chunk_format::{encode_chunk_delta, decode_chunk_or_delta};
+use super::material::MaterialRegistry;
+use super::lighting::light_chunk_in_isolation;
 
-// TODO: split out a WorldGen type that handles all the procedural
-// generation, because none of that really needs to be tangled
-// with the realised Globe.
 pub struct Globe {
     spec: Spec,
-    // TODO: temporarily making this public because I'm planning to
-    // rip it out of `Globe` anyway.
-    pub gen: Gen,
+    // Generates chunks that aren't in the chunk store.
+    //
+    // Shared so that chunks can be generated on other threads.
+    gen: Arc<WorldGen>,
+    // Definitions of every material that cells in this globe can be made of.
+    //
+    // Shared so that many globes in the same universe can use the same set.
+    materials: Arc<MaterialRegistry>,
     // Map chunk origins to chunks.
     //
-    // TODO: you'll probably also want to store some lower-res
-    // pseudo-chunks for rendering planets at a distance.
-    // But maybe you can put that off? Or maybe that's an entirely
-    // different type of Globe-oid?
+    // Planets seen from a distance are drawn without any chunks at all;
+    // see `LodView`.
     chunks: HashMap<ChunkOrigin, Chunk>,
     // Track which chunks are up-to-date with authoritative data for cells
     // they share with a neighbor.
     chunk_pairs: HashMap<ChunkPairOrigins, ChunkPair>,
+    // Where to save modified chunks before unloading them, and where
+    // to look for chunks before generating them fresh.
+    //
+    // If there is no chunk store, then modified chunks are never unloaded.
+    chunk_store: Option<Box<ChunkStore>>,
+    // Modified chunks that have been unloaded, but whose changes are still
+    // being encoded elsewhere; see `unload_chunk_to_save_later`.
+    //
+    // They're kept here so that they can be loaded again in the meantime
+    // without losing those changes.
+    chunks_being_saved: HashMap<ChunkOrigin, Arc<Chunk>>,
+    // How many chunks `ChunkSystem` should try to keep loaded at most.
+    chunk_budget: ChunkBudget,
+    // Where to record edits so they can be undone, if anywhere.
+    edit_journal: Option<EditJournal>,
+    // Cells that might need to move because something changed near them,
+    // e.g., water next to a hole that was just dug; see `WaterFlowSystem`.
+    // Only tracked if somebody is going to take them, or they'd pile up forever.
+    tracking_unsettled_cells: bool,
+    unsettled_cells: HashSet<PosInOwningRoot>,
 }
 
 // Allowing sibling modules to reach into semi-private parts
@@ -35,6 +64,8 @@ pub struct Globe {
 pub trait GlobeGuts<'a> {
     fn chunks(&'a self) -> &'a HashMap<ChunkOrigin, Chunk>;
     fn chunks_mut(&'a mut self) -> &'a mut HashMap<ChunkOrigin, Chunk>;
+    fn shared_gen(&'a self) -> Arc<WorldGen>;
+    fn shared_materials(&'a self) -> Arc<MaterialRegistry>;
 }
 
 impl<'a> GlobeGuts<'a> for Globe {
@@ -45,39 +76,153 @@ impl<'a> GlobeGuts<'a> for Globe {
     fn chunks_mut(&'a mut self) -> &'a mut HashMap<ChunkOrigin, Chunk> {
         &mut self.chunks
     }
+
+    fn shared_gen(&'a self) -> Arc<WorldGen> {
+        self.gen.clone()
+    }
+
+    fn shared_materials(&'a self) -> Arc<MaterialRegistry> {
+        self.materials.clone()
+    }
+}
+
+/// Returned when looking for a cell in a chunk that isn't loaded yet,
+/// e.g., because it is still being generated in the background.
+///
+/// Callers should generally just try again later; `ChunkSystem` will
+/// load the chunks near any `CellDweller`s soon enough.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct ChunkNotReady {
+    pub chunk_origin: ChunkOrigin,
 }
 
 impl Globe {
-    pub fn new(spec: Spec) -> Globe {
+    /// Create a globe whose content is generated by `gen`.
+    ///
+    /// Use `Gen` for PlanetKit's default terrain.
+    pub fn new(spec: Spec, gen: Box<WorldGen>) -> Globe {
+        Globe::new_with_materials(spec, gen, Arc::new(MaterialRegistry::new()))
+    }
+
+    /// Create a globe that can use any of the given materials,
+    /// rather than just PlanetKit's built-in materials.
+    pub fn new_with_materials(
+        spec: Spec,
+        gen: Box<WorldGen>,
+        materials: Arc<MaterialRegistry>,
+    ) -> Globe {
         Globe {
             spec: spec,
-            gen: Gen::new(spec),
+            gen: Arc::from(gen),
+            materials: materials,
             chunks: HashMap::new(),
             chunk_pairs: HashMap::new(),
+            chunk_store: None,
+            chunks_being_saved: HashMap::new(),
+            chunk_budget: ChunkBudget::default(),
+            edit_journal: None,
+            tracking_unsettled_cells: false,
+            unsettled_cells: HashSet::new(),
         }
     }
 
     pub fn new_example() -> Globe {
-        Globe::new(Spec {
-            seed: 14,
-            floor_radius: 25.0,
-            ocean_radius: 66.6,
-            block_height: 0.65,
-            root_resolution: [64, 128],
-            // Chunks should probably be taller, but short chunks are a bit
-            // better for now in exposing bugs visually.
-            chunk_resolution: [16, 16, 4],
-        })
+        let spec = Spec::new_example();
+        Globe::new(spec, Box::new(Gen::new(spec)))
     }
 
     pub fn new_earth_scale_example() -> Globe {
-        Globe::new(Spec::new_earth_scale_example())
+        let spec = Spec::new_earth_scale_example();
+        Globe::new(spec, Box::new(Gen::new(spec)))
     }
 
     pub fn spec(&self) -> Spec {
         self.spec
     }
 
+    pub fn gen(&self) -> &WorldGen {
+        &*self.gen
+    }
+
+    pub fn materials(&self) -> &MaterialRegistry {
+        &self.materials
+    }
+
+    /// Use the given store to save modified chunks before they are unloaded,
+    /// and to load chunks that have previously been saved instead of
+    /// generating them fresh.
+    ///
+    /// Any chunks that are already loaded are unaffected until they are
+    /// next saved. Chunks that are still loaded are _not_ saved when the
+    /// globe is dropped; call `save_all_changed_chunks` before then.
+    pub fn set_chunk_store(&mut self, chunk_store: Box<ChunkStore>) {
+        self.chunk_store = Some(chunk_store);
+    }
+
+    pub fn has_chunk_store(&self) -> bool {
+        self.chunk_store.is_some()
+    }
+
+    pub fn chunk_budget(&self) -> ChunkBudget {
+        self.chunk_budget
+    }
+
+    /// Set how many chunks `ChunkSystem` should try to keep loaded at most.
+    ///
+    /// Use `ChunkBudget::from_max_bytes` to limit how much memory they use.
+    pub fn set_chunk_budget(&mut self, chunk_budget: ChunkBudget) {
+        self.chunk_budget = chunk_budget;
+    }
+
+    /// Record all future edits made through `Globe::edit` in the given journal,
+    /// so that they can be undone.
+    ///
+    /// Replaces any journal the globe already had.
+    pub fn set_edit_journal(&mut self, edit_journal: EditJournal) {
+        self.edit_journal = Some(edit_journal);
+    }
+
+    pub fn edit_journal(&self) -> Option<&EditJournal> {
+        self.edit_journal.as_ref()
+    }
+
+    pub fn edit_journal_mut(&mut self) -> Option<&mut EditJournal> {
+        self.edit_journal.as_mut()
+    }
+
+    /// Start or stop keeping track of which cells are unsettled;
+    /// see `mark_cell_unsettled`.
+    ///
+    /// Tracking is off by default. `WaterFlowSystem` turns it on the first time
+    /// it runs. Anything else that calls `take_unsettled_cells` needs to turn it on
+    /// itself. Turning it off forgets about any cells that are still unsettled.
+    pub fn track_unsettled_cells(&mut self, track: bool) {
+        self.tracking_unsettled_cells = track;
+        if !track {
+            self.unsettled_cells.clear();
+        }
+    }
+
+    /// Note that the cell at `pos` might need to move, e.g., because it's water
+    /// and the cell below it was just dug out.
+    ///
+    /// `GlobeEdit` does this for every cell it changes, and all their neighbors.
+    /// Does nothing unless unsettled cells are being tracked;
+    /// see `track_unsettled_cells`.
+    pub fn mark_cell_unsettled(&mut self, pos: PosInOwningRoot) {
+        if self.tracking_unsettled_cells {
+            self.unsettled_cells.insert(pos);
+        }
+    }
+
+    /// Take all the cells marked as unsettled since this was last called.
+    ///
+    /// `WaterFlowSystem` calls this every tick. Always empty
+    /// unless unsettled cells are being tracked; see `track_unsettled_cells`.
+    pub fn take_unsettled_cells(&mut self) -> HashSet<PosInOwningRoot> {
+        mem::replace(&mut self.unsettled_cells, HashSet::new())
+    }
+
     /// Copy shared cells owned by a chunk for any loaded downstream chunks
     /// that have an outdated copy.
     ///
@@ -318,43 +463,284 @@ impl Globe {
         chunk
     }
 
-    // TODO: consider moving `load_or_build_chunk`, `ensure_chunk_present`,
+    // TODO: consider moving `ensure_saved_chunk_present`, `ensure_chunk_present`,
     // and `find_lowest_cell_containing` back out into a smarter component
     // so that `Globe` can be dumber, or move more of `Globe` down into a new
     // dumber component, e.g., `GlobeVoxMap`.
 
-    pub fn load_or_build_chunk(&mut self, origin: ChunkOrigin) {
-        use rand;
-        use rand::Rng;
-
-        let spec = self.spec();
-
-        let mut cells: Vec<Cell> = Vec::new();
-        // Include cells _on_ the far edge of the chunk;
-        // even though we don't own them we'll need to draw part of them.
-        let end_x = origin.pos().x + spec.chunk_resolution[0];
-        let end_y = origin.pos().y + spec.chunk_resolution[1];
-        // Chunks don't share cells in the z-direction,
-        // but do in the x- and y-directions.
-        let end_z = origin.pos().z + spec.chunk_resolution[2] - 1;
-        for cell_z in origin.pos().z..(end_z + 1) {
-            for cell_y in origin.pos().y..(end_y + 1) {
-                for cell_x in origin.pos().x..(end_x + 1) {
-                    let grid_point = GridPoint3::new(origin.pos().root, cell_x, cell_y, cell_z);
-                    let mut cell = self.gen.cell_at(grid_point);
-                    // Temp hax?
-                    let mut rng = rand::thread_rng();
-                    cell.shade = 1.0 - 0.5 * rng.next_f32();
-                    cells.push(cell);
-                }
+    /// Save the chunk at the given origin to the globe's chunk store
+    /// if it has been modified since it was last saved.
+    ///
+    /// Does nothing if there is no chunk store; the chunk will
+    /// still be marked as having unsaved changes.
+    ///
+    /// The chunk store might not write the chunk out until it is flushed;
+    /// see `flush_chunk_store`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if there was no chunk loaded at the given chunk origin.
+    pub fn save_chunk_if_changed(&mut self, chunk_origin: ChunkOrigin) -> io::Result<()> {
+        let chunk_store = match self.chunk_store {
+            Some(ref mut chunk_store) => chunk_store,
+            None => return Ok(()),
+        };
+        let chunk = self.chunks.get_mut(&chunk_origin).expect(
+            "Tried to save a chunk that isn't loaded",
+        );
+        if !chunk.has_unsaved_changes {
+            return Ok(());
+        }
+        // Only store how the chunk differs from what we'd generate;
+        // that's usually tiny.
+        let mut data = Vec::new();
+        encode_chunk_delta(chunk, self.spec, &*self.gen, &mut data);
+        chunk_store.save_chunk(chunk_origin, &data)?;
+        chunk.mark_as_saved();
+        Ok(())
+    }
+
+    /// Save every loaded chunk that has been modified since it was last saved,
+    /// and every chunk still waiting to be saved after being unloaded,
+    /// and flush the chunk store.
+    ///
+    /// Does nothing if there is no chunk store.
+    pub fn save_all_changed_chunks(&mut self) -> io::Result<()> {
+        if self.chunk_store.is_none() {
+            return Ok(());
+        }
+        let chunks_being_saved: Vec<Arc<Chunk>> =
+            self.chunks_being_saved.values().cloned().collect();
+        for chunk in chunks_being_saved {
+            let mut data = Vec::new();
+            encode_chunk_delta(&chunk, self.spec, &*self.gen, &mut data);
+            self.finish_saving_chunk(chunk, &data)?;
+        }
+        let changed_chunk_origins: Vec<ChunkOrigin> = self.chunks
+            .values()
+            .filter(|chunk| chunk.has_unsaved_changes)
+            .map(|chunk| chunk.origin)
+            .collect();
+        for chunk_origin in changed_chunk_origins {
+            self.save_chunk_if_changed(chunk_origin)?;
+        }
+        self.flush_chunk_store()
+    }
+
+    /// Make sure every chunk saved so far has been written out by the chunk store;
+    /// see `ChunkStore::flush`.
+    ///
+    /// Does nothing if there is no chunk store.
+    pub fn flush_chunk_store(&mut self) -> io::Result<()> {
+        match self.chunk_store {
+            Some(ref mut chunk_store) => chunk_store.flush(),
+            None => Ok(()),
+        }
+    }
+
+    /// Save the chunk at the given origin if necessary, and then unload it.
+    ///
+    /// Returns `Ok(false)` without unloading the chunk if it has unsaved changes
+    /// that could not be saved because there is no chunk store; unloading
+    /// it would lose those changes forever.
+    ///
+    /// # Panics
+    ///
+    /// Panics if there was no chunk loaded at the given chunk origin.
+    pub fn unload_chunk(&mut self, chunk_origin: ChunkOrigin) -> io::Result<bool> {
+        self.save_chunk_if_changed(chunk_origin)?;
+        let has_unsaved_changes = self.chunks
+            .get(&chunk_origin)
+            .expect("Tried to unload a chunk that isn't loaded")
+            .has_unsaved_changes;
+        if has_unsaved_changes {
+            return Ok(false);
+        }
+        self.remove_chunk(chunk_origin);
+        Ok(true)
+    }
+
+    /// Unload the chunk at the given origin, and return it so that its changes
+    /// can be encoded elsewhere, e.g., on a background thread.
+    ///
+    /// Pass the chunk and its encoded changes to `finish_saving_chunk` afterwards.
+    /// Until then, the chunk can still be loaded again with all its changes.
+    ///
+    /// # Panics
+    ///
+    /// Panics if there was no chunk loaded at the given chunk origin.
+    pub fn unload_chunk_to_save_later(&mut self, chunk_origin: ChunkOrigin) -> Arc<Chunk> {
+        let chunk = Arc::new(self.remove_chunk(chunk_origin));
+        self.chunks_being_saved.insert(chunk_origin, chunk.clone());
+        chunk
+    }
+
+    /// Save the encoded changes to a chunk unloaded by `unload_chunk_to_save_later`;
+    /// see `encode_chunk_delta`.
+    ///
+    /// Does nothing if the chunk has been loaded again since, because it
+    /// still has unsaved changes, and so will be saved again anyway.
+    ///
+    /// If the changes can't be saved, then the chunk is loaded again
+    /// so that they aren't lost, and the error is returned.
+    pub fn finish_saving_chunk(&mut self, chunk: Arc<Chunk>, data: &[u8]) -> io::Result<()> {
+        let chunk_origin = chunk.origin;
+        let is_still_being_saved = self.chunks_being_saved
+            .get(&chunk_origin)
+            .map_or(false, |being_saved| Arc::ptr_eq(being_saved, &chunk));
+        if !is_still_being_saved {
+            return Ok(());
+        }
+        let result = match self.chunk_store {
+            Some(ref mut chunk_store) => chunk_store.save_chunk(chunk_origin, data),
+            None => Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                "Globe has no chunk store to save chunks to",
+            )),
+        };
+        if result.is_err() {
+            self.reload_chunk_being_saved(chunk_origin);
+        } else {
+            self.chunks_being_saved.remove(&chunk_origin);
+        }
+        result
+    }
+
+    /// Load the chunk at the given origin again if it was unloaded by
+    /// `unload_chunk_to_save_later` and hasn't finished being saved yet.
+    ///
+    /// Returns `true` if the chunk was loaded again.
+    pub fn reload_chunk_being_saved(&mut self, chunk_origin: ChunkOrigin) -> bool {
+        let chunk = match self.chunks_being_saved.remove(&chunk_origin) {
+            Some(chunk) => chunk,
+            None => return false,
+        };
+        // Whoever is encoding it might not be done with it yet.
+        let mut chunk = Arc::try_unwrap(chunk).unwrap_or_else(|chunk| (*chunk).clone());
+        // Its view was removed when it was unloaded.
+        chunk.view_entity = None;
+        chunk.is_view_dirty = true;
+        // Its neighbors might have changed since it was last lit.
+        light_chunk_in_isolation(&mut chunk, self.spec, &*self.gen, &self.materials);
+        self.add_chunk_and_sync_neighbors(chunk);
+        true
+    }
+
+    /// Read the encoded data for the chunk at the given origin from
+    /// the globe's chunk store, without decoding it; see `decode_chunk_or_delta`.
+    ///
+    /// Returns `None` if there is no chunk store, or the chunk has never been saved,
+    /// or an error if the chunk store couldn't be read.
+    pub fn load_saved_chunk_data(
+        &mut self,
+        chunk_origin: ChunkOrigin,
+    ) -> io::Result<Option<Vec<u8>>> {
+        match self.chunk_store {
+            Some(ref mut chunk_store) => chunk_store.load_chunk(chunk_origin),
+            None => Ok(None),
+        }
+    }
+
+    // Returns `None` if there is no chunk store, or the chunk has never been saved,
+    // or an error if the chunk couldn't be read, or was corrupt.
+    fn load_chunk_from_store(&mut self, origin: ChunkOrigin) -> io::Result<Option<Chunk>> {
+        let data = match self.load_saved_chunk_data(origin)? {
+            Some(data) => data,
+            None => return Ok(None),
+        };
+        decode_chunk_or_delta(&data, self.spec, &*self.gen)
+            .map(Some)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+    }
+
+    /// Load the specified chunk from the globe's chunk store if it has
+    /// ever been saved there, and make sure it is up-to-date with its neighbors.
+    ///
+    /// Returns `Ok(true)` if the chunk is now present, either because it was
+    /// already loaded or it was loaded from the store, or `Ok(false)` if it
+    /// will need to be generated. Returns an error if the saved chunk
+    /// couldn't be read from the store, or was corrupt.
+    ///
+    /// Decoding the saved chunk means generating it again, so this is slow;
+    /// `ChunkSystem` does that on its background threads instead.
+    pub fn ensure_saved_chunk_present(&mut self, chunk_origin: ChunkOrigin) -> io::Result<bool> {
+        if self.chunk_at(chunk_origin).is_some() || self.reload_chunk_being_saved(chunk_origin) {
+            return Ok(true);
+        }
+        match self.load_chunk_from_store(chunk_origin)? {
+            Some(mut chunk) => {
+                light_chunk_in_isolation(&mut chunk, self.spec, &*self.gen, &self.materials);
+                self.add_chunk_and_sync_neighbors(chunk);
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    /// Add a chunk that was generated elsewhere, e.g., on a background thread,
+    /// and make sure it is up-to-date with its neighbors.
+    ///
+    /// If the chunk was decoded from saved data rather than generated fresh,
+    /// then `saved_data` should be that data; see `load_saved_chunk_data`.
+    ///
+    /// The chunk should already have been lit as if it were the only one loaded,
+    /// as `ChunkSystem` does on its background threads; see `light_chunk`.
+    ///
+    /// Because the world might have moved on while the chunk was being generated,
+    /// the chunk is discarded if that chunk has since been loaded some other way,
+    /// and is replaced with the saved version if it has since been saved.
+    ///
+    /// If the saved version can't be read, then the generated chunk is added
+    /// anyway so that the world can carry on, and the error is returned
+    /// so that it can be reported.
+    pub fn add_generated_chunk(
+        &mut self,
+        chunk: Chunk,
+        saved_data: Option<&[u8]>,
+    ) -> io::Result<()> {
+        let chunk_origin = chunk.origin;
+        if self.chunk_at(chunk_origin).is_some() || self.reload_chunk_being_saved(chunk_origin) {
+            return Ok(());
+        }
+        let is_up_to_date = match self.load_saved_chunk_data(chunk_origin) {
+            Ok(latest_data) => latest_data.as_ref().map(|data| &data[..]) == saved_data,
+            Err(err) => {
+                self.add_chunk_and_sync_neighbors(chunk);
+                return Err(err);
             }
+        };
+        if is_up_to_date {
+            self.add_chunk_and_sync_neighbors(chunk);
+            return Ok(());
         }
-        self.add_chunk(Chunk::new(
-            origin,
-            cells,
-            spec.root_resolution,
-            spec.chunk_resolution,
-        ));
+        // It has been saved since; that should be rare,
+        // so it's fine to just decode it here.
+        match self.ensure_saved_chunk_present(chunk_origin) {
+            Ok(true) => Ok(()),
+            Ok(false) => {
+                self.add_chunk_and_sync_neighbors(chunk);
+                Ok(())
+            }
+            Err(err) => {
+                self.add_chunk_and_sync_neighbors(chunk);
+                Err(err)
+            }
+        }
+    }
+
+    fn add_chunk_and_sync_neighbors(&mut self, chunk: Chunk) {
+        let chunk_origin = chunk.origin;
+        self.add_chunk(chunk);
+
+        // Make sure this chunk has up-to-date data for edge cells that it doesn't own.
+        self.pull_shared_cells_for_chunk(chunk_origin);
+
+        // Make sure that neighboring chunks have up-to-date data for edge cells owned
+        // by this chunk.
+        self.push_shared_cells_for_chunk(chunk_origin);
+
+        // Let light into the chunk, and out of it into its neighbors.
+        self.light_chunk(chunk_origin);
     }
 
     /// Ensures the specified chunk is present.
@@ -367,22 +753,21 @@ impl Globe {
     /// be loaded, and chunks added through this mechanism may well be unloaded
     /// immediately the next time this system is invoked, making this only suitable
     /// for immediate actions.
+    ///
+    /// Returns an error, without loading the chunk, if it was saved
+    /// but couldn't be read from the chunk store.
     //
     // TODO: this definitely belongs elsewhere; somewhere that knows about
     // loading things from disk. The simpler version of just making sure the
     // voxmap buffer exists should exist on a dumber struct extracted from `Globe`.
-    pub fn ensure_chunk_present(&mut self, chunk_origin: ChunkOrigin) {
-        if self.chunk_at(chunk_origin).is_some() {
-            return;
+    pub fn ensure_chunk_present(&mut self, chunk_origin: ChunkOrigin) -> io::Result<()> {
+        if self.ensure_saved_chunk_present(chunk_origin)? {
+            return Ok(());
         }
-        self.load_or_build_chunk(chunk_origin);
-
-        // Make sure this chunk has up-to-date data for edge cells that it doesn't own.
-        self.pull_shared_cells_for_chunk(chunk_origin);
-
-        // Make sure that neighboring chunks have up-to-date data for edge cells owned
-        // by this chunk.
-        self.push_shared_cells_for_chunk(chunk_origin);
+        let mut chunk = generate_chunk(chunk_origin, self.spec, &*self.gen);
+        light_chunk_in_isolation(&mut chunk, self.spec, &*self.gen, &self.materials);
+        self.add_chunk_and_sync_neighbors(chunk);
+        Ok(())
     }
 
     /// Make sure we are tracking the currency of shared data in all chunks
@@ -455,11 +840,17 @@ impl<'a> Globe {
         chunk.cell(pos.into())
     }
 
+    /// Note that this marks the owning chunk as having unsaved changes,
+    /// regardless of whether or not you actually change the cell.
+    ///
+    /// This does nothing to keep neighboring chunks or chunk views up-to-date;
+    /// prefer `Globe::edit` unless you're going to take care of that yourself.
     pub fn authoritative_cell_mut(&'a mut self, pos: PosInOwningRoot) -> &'a mut Cell {
         let chunk_origin = self.origin_of_chunk_owning(pos);
         let chunk = self.chunks.get_mut(&chunk_origin).expect(
             "Uh oh, I don't know how to handle chunks that aren't loaded yet.",
         );
+        chunk.mark_as_changed();
         chunk.cell_mut(pos.into())
     }
 
@@ -470,6 +861,55 @@ impl<'a> Globe {
         );
         chunk.cell(pos)
     }
+
+    /// Like `maybe_non_authoritative_cell`, but tells you if the chunk
+    /// containing the cell isn't loaded yet instead of panicking.
+    pub fn maybe_non_authoritative_cell_if_ready(
+        &'a self,
+        pos: GridPoint3,
+    ) -> Result<&'a Cell, ChunkNotReady> {
+        let chunk_origin = self.origin_of_chunk_in_same_root_containing(pos);
+        match self.chunks.get(&chunk_origin) {
+            Some(chunk) => Ok(chunk.cell(pos)),
+            None => Err(ChunkNotReady { chunk_origin: chunk_origin }),
+        }
+    }
+}
+
+/// Generate a chunk from scratch, without looking at any chunks already
+/// loaded or saved, or touching any other state of a `Globe`.
+///
+/// This is safe to call from any thread.
+pub fn generate_chunk(origin: ChunkOrigin, spec: Spec, gen: &WorldGen) -> Chunk {
+    let mut cells: Vec<Cell> = Vec::new();
+    // Include cells _on_ the far edge of the chunk;
+    // even though we don't own them we'll need to draw part of them.
+    let end_x = origin.pos().x + spec.chunk_resolution[0];
+    let end_y = origin.pos().y + spec.chunk_resolution[1];
+    // Chunks don't share cells in the z-direction,
+    // but do in the x- and y-directions.
+    let end_z = origin.pos().z + spec.chunk_resolution[2] - 1;
+    // Ask for a whole column at a time, so the generator only has to do
+    // the work that's the same for every cell in a column once.
+    let mut columns: Vec<Vec<Cell>> = Vec::new();
+    for cell_y in origin.pos().y..(end_y + 1) {
+        for cell_x in origin.pos().x..(end_x + 1) {
+            let column = GridPoint2::new(origin.pos().root, cell_x, cell_y);
+            columns.push(gen.cells_in_column(column, origin.pos().z..(end_z + 1)));
+        }
+    }
+    // Then lay them out in storage order, one layer at a time.
+    for layer in 0..spec.chunk_resolution[2] as usize {
+        for column in &columns {
+            cells.push(column[layer]);
+        }
+    }
+    Chunk::new(
+        origin,
+        cells,
+        spec.root_resolution,
+        spec.chunk_resolution,
+    )
 }
 
 impl specs::Component for Globe {
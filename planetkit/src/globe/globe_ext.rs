@@ -1,11 +1,13 @@
 // "Extension" functions for `Globe`. These do not use any private details of `Globe`,
 // and so they are exposed on its _inherent impl_ for convenience only.
 
+use std::io;
+
 use rand::Rng;
 
 use grid::{GridPoint2, GridPoint3, PosInOwningRoot, GridCoord};
 use grid::random_column;
-use super::chunk::Material;
+use super::MaterialId;
 use super::CursorMut;
 use super::globe::Globe;
 
@@ -18,7 +20,7 @@ impl Globe {
         max_distance_from_starting_point: GridCoord,
     ) -> Option<GridPoint3> {
         // Use land height from world gen to approximate cell position where we might find land.
-        let land_height = self.gen.land_height(column);
+        let land_height = self.gen().land_height(column);
         let approx_cell_z = self.spec().approx_cell_z_from_radius(land_height);
         // Augment original column with approximate z-value and pass the buck.
         let pos = column.with_z(approx_cell_z);
@@ -29,10 +31,13 @@ impl Globe {
     ///
     /// A land cell position will only be returned if it has at least as many
     /// contiguous cells of air directly above it as specified by `min_air_cells_above`.
+    /// Here "land" means any solid material, and "air" means any material that is
+    /// neither solid nor liquid.
     ///
     /// Returns `None` if no such cell can be found within the maximum distance given, e.g.,
     /// if the highest land was below water, or our guess about where there should be land
-    /// exposed to air turned out to be wrong.
+    /// exposed to air turned out to be wrong. Cells in chunks that couldn't be loaded
+    /// from the chunk store are skipped over.
     ///
     /// Note that this returns the position of the cell found containing land, not the first cell
     /// above it containing air. If you are trying to find a suitable location to, e.g., spawn new
@@ -60,13 +65,15 @@ impl Globe {
                 }
                 // If it's not land, then we're not interested.
                 cursor.set_pos(*hopefully_land_pos);
-                cursor.ensure_chunk_present();
+                if cursor.ensure_chunk_present().is_err() {
+                    continue;
+                }
                 {
                     // Non-lexical lifetimes SVP.
-                    let cell = cursor.cell().expect(
+                    let material = cursor.cell().expect(
                         "We just ensured the chunk is present, but apparently it's not. Kaboom!",
-                    );
-                    if cell.material != Material::Dirt {
+                    ).material;
+                    if !cursor.globe().materials().is_solid(material) {
                         continue;
                     }
                 }
@@ -75,11 +82,13 @@ impl Globe {
                 for _ in 0..min_air_cells_above {
                     hopefully_air_pos.z = hopefully_air_pos.z + 1;
                     cursor.set_pos(hopefully_air_pos);
-                    cursor.ensure_chunk_present();
-                    let cell = cursor.cell().expect(
+                    if cursor.ensure_chunk_present().is_err() {
+                        continue 'candidate_land;
+                    }
+                    let material = cursor.cell().expect(
                         "We just ensured the chunk is present, but apparently it's not. Kaboom!",
-                    );
-                    if cell.material != Material::Air {
+                    ).material;
+                    if !cursor.globe().materials().is_empty(material) {
                         continue 'candidate_land;
                     }
                 }
@@ -137,8 +146,8 @@ impl Globe {
     pub fn find_lowest_cell_containing(
         &mut self,
         column: GridPoint3,
-        material: Material,
-    ) -> GridPoint3 {
+        material: MaterialId,
+    ) -> io::Result<GridPoint3> {
         // Translate into owning root, then start at bedrock.
         let mut column = PosInOwningRoot::new(column, self.spec().root_resolution);
         column.set_z(0);
@@ -157,7 +166,7 @@ impl Globe {
             // and automatically updates itself whenever you set its position.
             //
             // TODO: "collect garbage" occasionally? Or every iteration, even.
-            cursor.ensure_chunk_present();
+            cursor.ensure_chunk_present()?;
             {
                 let pos = cursor.pos();
                 let cell = cursor.cell().expect(
@@ -165,7 +174,7 @@ impl Globe {
                 );
                 if cell.material == material {
                     // Yay, we found it!
-                    return pos.into();
+                    return Ok(pos.into());
                 }
             }
             let new_pos = cursor.pos().with_z(cursor.pos().z + 1);
@@ -0,0 +1,235 @@
+use std::f64::consts::{PI, FRAC_PI_2};
+use std::ops::Range;
+use std::path::Path;
+
+use image;
+use image::{DynamicImage, GrayImage, RgbImage};
+
+use grid::{GridCoord, GridPoint2, GridPoint3};
+use super::spec::Spec;
+use super::chunk::Cell;
+use super::material::MaterialId;
+use super::biome::Biome;
+use super::gen::{WorldGen, BEDROCK_THICKNESS};
+
+// How many cells deep the material from a material map goes
+// before we hit the stone beneath.
+const MAPPED_SOIL_DEPTH: f64 = 3.0;
+
+/// `WorldGen` that takes the shape of the land from a heightmap image,
+/// e.g., to make an Earth-like globe, instead of from noise.
+///
+/// Images use the equirectangular projection: the left edge is 180°W
+/// and the right edge is 180°E, and the top edge is the north pole and
+/// the bottom edge is the south pole. See `Spec::latitude_longitude`
+/// for where those are on the globe. Heights are sampled with bilinear
+/// filtering, so low resolution images still make smooth land.
+///
+/// The land is covered in a layer of sand below sea level, and dirt above it,
+/// unless a material map is given (see `with_material_map`), with stone
+/// beneath that, and bedrock at the very bottom. There are no caves.
+///
+/// Use this anywhere you would use `Gen`, e.g., pass
+/// `Box::new(HeightmapGen::open(spec, "earth.png", -30.0, 30.0)?)`
+/// to `Globe::new`.
+pub struct HeightmapGen {
+    spec: Spec,
+    heightmap: GrayImage,
+    // Elevations of black and white in the heightmap, relative to sea level.
+    lowest: f64,
+    highest: f64,
+    material_map: Option<MaterialMap>,
+}
+
+struct MaterialMap {
+    image: RgbImage,
+    palette: Vec<([u8; 3], MaterialId)>,
+}
+
+impl HeightmapGen {
+    /// Make land from any image, which will be converted to grayscale.
+    ///
+    /// Black is `lowest` above sea level (so usually negative),
+    /// and white is `highest` above sea level, in the same units as
+    /// the globe's radii. Make sure `lowest` is well above the floor,
+    /// or there won't be much land left above the bedrock.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `spec` is invalid, or if `lowest` is above `highest`.
+    pub fn new(spec: Spec, heightmap: &DynamicImage, lowest: f64, highest: f64) -> HeightmapGen {
+        if let Err(err) = spec.validate() {
+            panic!("Invalid globe spec: {}", err);
+        }
+        assert!(lowest <= highest, "Lowest elevation is above highest elevation");
+        HeightmapGen {
+            spec: spec,
+            heightmap: heightmap.to_luma(),
+            lowest: lowest,
+            highest: highest,
+            material_map: None,
+        }
+    }
+
+    /// Read a heightmap image from a file; see `new`.
+    pub fn open<P: AsRef<Path>>(
+        spec: Spec,
+        path: P,
+        lowest: f64,
+        highest: f64,
+    ) -> image::ImageResult<HeightmapGen> {
+        let heightmap = image::open(path)?;
+        Ok(HeightmapGen::new(spec, &heightmap, lowest, highest))
+    }
+
+    /// Choose what the top layer of the land is made of from the colors
+    /// of an image, using the same projection as the heightmap, but not
+    /// necessarily the same size.
+    ///
+    /// Each pixel gets the material of whichever color in `palette`
+    /// is closest to it, so there's no need to worry about colors
+    /// that have been slightly changed by, e.g., lossy compression.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `palette` is empty.
+    pub fn with_material_map(
+        mut self,
+        material_map: &DynamicImage,
+        palette: Vec<([u8; 3], MaterialId)>,
+    ) -> HeightmapGen {
+        assert!(!palette.is_empty(), "Material map needs at least one color");
+        self.material_map = Some(MaterialMap {
+            image: material_map.to_rgb(),
+            palette: palette,
+        });
+        self
+    }
+
+    /// Read a material map image from a file; see `with_material_map`.
+    pub fn open_material_map<P: AsRef<Path>>(
+        self,
+        path: P,
+        palette: Vec<([u8; 3], MaterialId)>,
+    ) -> image::ImageResult<HeightmapGen> {
+        let material_map = image::open(path)?;
+        Ok(self.with_material_map(&material_map, palette))
+    }
+
+    // What the top layer of the land is made of in the given column,
+    // and how many cells deep it goes.
+    fn soil(&self, column: GridPoint2, land_height: f64) -> (MaterialId, f64) {
+        let material_map = match self.material_map {
+            Some(ref material_map) => material_map,
+            None => {
+                let biome = if land_height < self.spec.ocean_radius {
+                    Biome::Ocean
+                } else {
+                    Biome::Grassland
+                };
+                return (biome.surface_material(), biome.soil_depth());
+            }
+        };
+        let (latitude, longitude) = self.spec.latitude_longitude(column);
+        // Colors stand for different things, so don't blend them;
+        // just take the nearest pixel.
+        let dimensions = material_map.image.dimensions();
+        let (x, y) = pixel_coords(dimensions, latitude, longitude);
+        let (x, y) = wrap_pixel(dimensions, x.round() as i64, y.round() as i64);
+        let color = material_map.image.get_pixel(x, y).data;
+        let distance = |palette_color: &[u8; 3]| -> i32 {
+            palette_color
+                .iter()
+                .zip(color.iter())
+                .map(|(&a, &b)| (a as i32 - b as i32) * (a as i32 - b as i32))
+                .sum()
+        };
+        let material = material_map
+            .palette
+            .iter()
+            .min_by_key(|&&(ref palette_color, _)| distance(palette_color))
+            .map(|&(_, material)| material)
+            .expect("Material map palette should not be empty");
+        (material, MAPPED_SOIL_DEPTH)
+    }
+
+    // The given cell, in a column with the given land height and soil.
+    fn cell_in_column(
+        &self,
+        grid_point: GridPoint3,
+        land_height: f64,
+        (soil_material, soil_depth): (MaterialId, f64),
+    ) -> Cell {
+        let cell_height = self.spec.cell_center_center(grid_point).coords.norm();
+        let material = if cell_height < land_height {
+            let depth = (land_height - cell_height) / self.spec.block_height;
+            if grid_point.z < BEDROCK_THICKNESS {
+                MaterialId::BEDROCK
+            } else if depth < soil_depth {
+                soil_material
+            } else {
+                MaterialId::STONE
+            }
+        } else if cell_height < self.spec.ocean_radius {
+            MaterialId::WATER
+        } else {
+            MaterialId::AIR
+        };
+        Cell {
+            material: material,
+            // `Globe` works these out once the chunk is loaded.
+            sky_light: 0,
+            block_light: 0,
+        }
+    }
+}
+
+impl WorldGen for HeightmapGen {
+    fn land_height(&self, column: GridPoint2) -> f64 {
+        let (latitude, longitude) = self.spec.latitude_longitude(column);
+        let dimensions = self.heightmap.dimensions();
+        let (x, y) = pixel_coords(dimensions, latitude, longitude);
+        let (x0, y0) = (x.floor(), y.floor());
+        let (dx, dy) = (x - x0, y - y0);
+        let (x0, y0) = (x0 as i64, y0 as i64);
+        let value = |x: i64, y: i64| -> f64 {
+            let (x, y) = wrap_pixel(dimensions, x, y);
+            self.heightmap.get_pixel(x, y).data[0] as f64 / 255.0
+        };
+        let top = value(x0, y0) * (1.0 - dx) + value(x0 + 1, y0) * dx;
+        let bottom = value(x0, y0 + 1) * (1.0 - dx) + value(x0 + 1, y0 + 1) * dx;
+        let value = top * (1.0 - dy) + bottom * dy;
+        self.spec.ocean_radius + self.lowest + (self.highest - self.lowest) * value
+    }
+
+    fn cell_at(&self, grid_point: GridPoint3) -> Cell {
+        let land_height = self.land_height(grid_point.rxy);
+        let soil = self.soil(grid_point.rxy, land_height);
+        self.cell_in_column(grid_point, land_height, soil)
+    }
+
+    fn cells_in_column(&self, column: GridPoint2, z_range: Range<GridCoord>) -> Vec<Cell> {
+        let land_height = self.land_height(column);
+        let soil = self.soil(column, land_height);
+        z_range
+            .map(|z| self.cell_in_column(column.with_z(z), land_height, soil))
+            .collect()
+    }
+}
+
+// Position in an equirectangular image with the given dimensions, in pixels,
+// with the center of the top left pixel at `(0, 0)`.
+fn pixel_coords(dimensions: (u32, u32), latitude: f64, longitude: f64) -> (f64, f64) {
+    let (width, height) = dimensions;
+    let x = (longitude + PI) / (2.0 * PI) * width as f64 - 0.5;
+    let y = (FRAC_PI_2 - latitude) / PI * height as f64 - 0.5;
+    (x, y)
+}
+
+// Wrap around from east to west, but stop at the poles.
+fn wrap_pixel(dimensions: (u32, u32), x: i64, y: i64) -> (u32, u32) {
+    let (width, height) = (dimensions.0 as i64, dimensions.1 as i64);
+    let x = ((x % width) + width) % width;
+    let y = y.max(0).min(height - 1);
+    (x as u32, y as u32)
+}
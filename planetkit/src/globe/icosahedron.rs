@@ -1,3 +1,5 @@
+use types::*;
+
 // Golden ratio
 const PHI: f64 = 1.61803398874989484820458683436563811772030917980576286213544862270526046281890244970720720418939113748475;
 
@@ -24,6 +26,24 @@ pub const VERTICES: [[f64; 3]; 12] = [
     [ B,    0.0, -A  ],
 ];
 
+/// Unit vector pointing to the north pole of a globe: the first vertex,
+/// which is the corner shared by all the root quads at `(0, 0)`.
+pub fn north_pole() -> Vec3 {
+    let vertex = VERTICES[0];
+    Vec3::new(vertex[0], vertex[1], vertex[2]).normalize()
+}
+
+/// Unit vector on the equator, under the prime meridian, which runs from
+/// the north pole through the neighboring corner of root 0 at
+/// `(0, root_resolution[1])`.
+pub fn prime_meridian() -> Vec3 {
+    let north = north_pole();
+    // Neighboring corner of root 0; see `FACES`.
+    let vertex = VERTICES[8];
+    let corner = Vec3::new(vertex[0], vertex[1], vertex[2]);
+    (corner - north * corner.dot(&north)).normalize()
+}
+
 // TODO: describe the very specific and deliberate
 // order that these faces are in.
 #[cfg_attr(rustfmt, rustfmt_skip)]
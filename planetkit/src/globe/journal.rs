@@ -0,0 +1,202 @@
+use std::collections::VecDeque;
+
+use grid::PosInOwningRoot;
+use super::chunk::Cell;
+
+/// A change to a single cell in a `Globe`.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct CellChange {
+    pub pos: PosInOwningRoot,
+    pub old: Cell,
+    pub new: Cell,
+}
+
+/// All the cell changes made in a single `GlobeEdit`.
+///
+/// Each cell appears at most once.
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct Transaction {
+    pub changes: Vec<CellChange>,
+}
+
+impl Transaction {
+    /// A transaction that exactly reverses this one.
+    pub fn inverse(&self) -> Transaction {
+        Transaction {
+            changes: self.changes
+                .iter()
+                .map(|change| {
+                    CellChange {
+                        pos: change.pos,
+                        old: change.new,
+                        new: change.old,
+                    }
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Record of edits made to a `Globe`, so that they can be undone and redone.
+///
+/// Attach one to a globe with `Globe::set_edit_journal`; every `GlobeEdit`
+/// that changes any cells will then be recorded as a `Transaction`.
+/// Use `Globe::undo` and `Globe::redo` to step back and forth through them.
+///
+/// The journal can also keep a feed of every transaction applied to the globe,
+/// including undos and redos, for anything that needs to follow along, e.g.,
+/// to replicate changes over the network. See `enable_change_feed`.
+pub struct EditJournal {
+    // Most recent last.
+    undo_stack: VecDeque<Transaction>,
+    // Most recently undone last.
+    redo_stack: Vec<Transaction>,
+    max_undo_transactions: usize,
+    // `None` unless the change feed is enabled.
+    change_feed: Option<Vec<Transaction>>,
+}
+
+impl EditJournal {
+    /// Make a journal that remembers up to `max_undo_transactions`
+    /// transactions; older ones are forgotten, and can't be undone.
+    pub fn new(max_undo_transactions: usize) -> EditJournal {
+        EditJournal {
+            undo_stack: VecDeque::new(),
+            redo_stack: Vec::new(),
+            max_undo_transactions: max_undo_transactions,
+            change_feed: None,
+        }
+    }
+
+    /// Start keeping a feed of every transaction applied to the globe.
+    ///
+    /// The feed grows until drained with `take_change_feed`, so make sure
+    /// something does that regularly.
+    pub fn enable_change_feed(&mut self) {
+        if self.change_feed.is_none() {
+            self.change_feed = Some(Vec::new());
+        }
+    }
+
+    /// Take all the transactions applied to the globe since this was last called,
+    /// oldest first.
+    ///
+    /// Always empty unless the change feed is enabled.
+    pub fn take_change_feed(&mut self) -> Vec<Transaction> {
+        match self.change_feed {
+            Some(ref mut change_feed) => change_feed.drain(..).collect(),
+            None => Vec::new(),
+        }
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.undo_stack.is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.redo_stack.is_empty()
+    }
+
+    /// Record a new transaction.
+    ///
+    /// This forgets anything that was undone, as it can no longer be redone.
+    pub fn record(&mut self, transaction: Transaction) {
+        if transaction.changes.is_empty() {
+            return;
+        }
+        self.redo_stack.clear();
+        self.feed(&transaction);
+        self.undo_stack.push_back(transaction);
+        while self.undo_stack.len() > self.max_undo_transactions {
+            self.undo_stack.pop_front();
+        }
+    }
+
+    /// The transaction that `Globe::undo` would undo next.
+    pub fn next_undo(&self) -> Option<&Transaction> {
+        self.undo_stack.back()
+    }
+
+    /// The transaction that `Globe::redo` would redo next.
+    pub fn next_redo(&self) -> Option<&Transaction> {
+        self.redo_stack.last()
+    }
+
+    /// Move the transaction from `next_undo` onto the redo stack.
+    ///
+    /// `Globe::undo` calls this once it has reverted the transaction's
+    /// changes; you shouldn't need to call it yourself.
+    pub fn finish_undo(&mut self) {
+        let transaction = self.undo_stack.pop_back().expect("Nothing to undo");
+        self.feed(&transaction.inverse());
+        self.redo_stack.push(transaction);
+    }
+
+    /// Move the transaction from `next_redo` back onto the undo stack.
+    ///
+    /// `Globe::redo` calls this once it has reapplied the transaction's
+    /// changes; you shouldn't need to call it yourself.
+    pub fn finish_redo(&mut self) {
+        let transaction = self.redo_stack.pop().expect("Nothing to redo");
+        self.feed(&transaction);
+        self.undo_stack.push_back(transaction);
+    }
+
+    fn feed(&mut self, transaction: &Transaction) {
+        if let Some(ref mut change_feed) = self.change_feed {
+            change_feed.push(transaction.clone());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use grid::GridPoint3;
+    use super::super::MaterialId;
+    use super::*;
+
+    fn transaction(x: i64) -> Transaction {
+        let cell = Cell {
+            material: MaterialId::AIR,
+            sky_light: 0,
+            block_light: 0,
+        };
+        Transaction {
+            changes: vec![
+                CellChange {
+                    pos: PosInOwningRoot::new(GridPoint3::new(0.into(), x, 1, 0), [16, 32]),
+                    old: cell,
+                    new: Cell {
+                        material: MaterialId::DIRT,
+                        ..cell
+                    },
+                },
+            ],
+        }
+    }
+
+    #[test]
+    fn forget_oldest_transactions() {
+        let mut journal = EditJournal::new(2);
+        journal.record(transaction(1));
+        journal.record(transaction(2));
+        journal.record(transaction(3));
+        assert_eq!(Some(&transaction(3)), journal.next_undo());
+        journal.finish_undo();
+        journal.finish_undo();
+        assert!(!journal.can_undo());
+        assert_eq!(Some(&transaction(2)), journal.next_redo());
+    }
+
+    #[test]
+    fn new_transaction_clears_redo() {
+        let mut journal = EditJournal::new(10);
+        journal.record(transaction(1));
+        journal.finish_undo();
+        assert!(journal.can_redo());
+        journal.record(transaction(2));
+        assert!(!journal.can_redo());
+        // The change feed is off, so it should be empty.
+        assert!(journal.take_change_feed().is_empty());
+    }
+}
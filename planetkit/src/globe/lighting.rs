@@ -0,0 +1,424 @@
+// Working out how brightly lit each cell is.
+
+use std::collections::{HashSet, VecDeque};
+
+use grid::{GridPoint3, PosInOwningRoot, Neighbors};
+use super::{ChunkOrigin, Spec, WorldGen, MaterialRegistry, origin_of_chunk_owning};
+use super::chunk::{Chunk, Cell};
+use super::globe::{Globe, GlobeGuts};
+
+/// Brightest light a cell can have; that of direct sunlight.
+///
+/// Light gets one level dimmer for every cell it spreads through,
+/// except for sunlight shining straight down.
+pub const MAX_LIGHT: u8 = 15;
+
+// Cells store sunlight and light from glowing materials separately,
+// so that each one can be updated on its own when things change.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Channel {
+    Sky,
+    Block,
+}
+
+impl Channel {
+    fn light(self, cell: &Cell) -> u8 {
+        match self {
+            Channel::Sky => cell.sky_light,
+            Channel::Block => cell.block_light,
+        }
+    }
+
+    fn set_light(self, cell: &mut Cell, light: u8) {
+        match self {
+            Channel::Sky => cell.sky_light = light,
+            Channel::Block => cell.block_light = light,
+        }
+    }
+}
+
+impl Globe {
+    /// Work out the light of every cell that might have been affected
+    /// by changes to the cells at `changed_cells`.
+    ///
+    /// Sunlight shines straight down through cells of transparent material,
+    /// and light spreads out from there, and from any materials that
+    /// emit light, through other transparent cells, getting dimmer as it goes.
+    /// Opaque cells are dark, unless they glow; to see how brightly lit
+    /// their faces are, look at the cells next to them.
+    ///
+    /// Light only reaches as far as the chunks that are loaded. Above the top
+    /// of the loaded chunks, the world is assumed to be as `WorldGen` made it,
+    /// with open sky above its land height.
+    ///
+    /// This is done for you when cells are changed using `Globe::edit`,
+    /// and when chunks are loaded with `Globe::ensure_chunk_present`
+    /// or similar. Only the authoritative copy of each cell is relit,
+    /// and then copied out to any chunks that share it.
+    pub fn update_light<I>(&mut self, changed_cells: I)
+    where
+        I: IntoIterator<Item = PosInOwningRoot>,
+    {
+        let changed_cells: Vec<PosInOwningRoot> = changed_cells
+            .into_iter()
+            .filter(|&pos| self.chunk_at(self.origin_of_chunk_owning(pos)).is_some())
+            .collect();
+        self.relight(&changed_cells, &[]);
+    }
+
+    // Relight around `changed_cells`, and spread any light in `spread_from`
+    // to wherever it can reach, then let everything affected know about it.
+    fn relight(&mut self, changed_cells: &[PosInOwningRoot], spread_from: &[PosInOwningRoot]) {
+        let mut relit_chunks: HashSet<ChunkOrigin> = HashSet::new();
+        for &channel in &[Channel::Sky, Channel::Block] {
+            Relight {
+                globe: self,
+                channel: channel,
+                relit_chunks: &mut relit_chunks,
+            }.relight(changed_cells, spread_from);
+        }
+
+        for chunk_origin in relit_chunks {
+            let accessible_chunks = {
+                let chunk = self.chunks_mut().get_mut(&chunk_origin).expect(
+                    "Relit chunk disappeared",
+                );
+                chunk.owned_edge_version += 1;
+                chunk.accessible_chunks.clone()
+            };
+            self.push_shared_cells_for_chunk(chunk_origin);
+            // Cells are drawn using the light of the cells next to them,
+            // which might be in any of the chunks around this one.
+            for accessible_chunk_origin in accessible_chunks {
+                if let Some(chunk) = self.chunks_mut().get_mut(&accessible_chunk_origin) {
+                    chunk.mark_view_as_dirty();
+                }
+            }
+        }
+    }
+
+    /// Let light cross between a chunk that was just added to the globe
+    /// and the loaded chunks around it.
+    ///
+    /// The chunk must already have been lit as if it were the only one loaded;
+    /// `ChunkSystem` does that on its background threads for chunks it generates,
+    /// and `Globe::ensure_chunk_present` does it for any chunk it adds.
+    /// That leaves only the cells on either side of the chunk's boundary
+    /// to look at here.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the chunk isn't loaded.
+    pub fn light_chunk(&mut self, chunk_origin: ChunkOrigin) {
+        let spec = self.spec();
+        let root_resolution = spec.root_resolution;
+        let is_open_sky = |cell: &Cell| cell.sky_light == MAX_LIGHT;
+        let mut changed_cells: Vec<PosInOwningRoot> = Vec::new();
+        let mut boundary_cells: HashSet<PosInOwningRoot> = HashSet::new();
+        for pos in cells_owned_by_chunk(chunk_origin, spec) {
+            let cell = *self.chunk_at(chunk_origin)
+                .expect("Tried to light a chunk that isn't loaded")
+                .cell(pos.into());
+            for neighbor in Neighbors::new(pos.into(), root_resolution) {
+                if neighbor.z < 0 {
+                    continue;
+                }
+                let neighbor = PosInOwningRoot::new(neighbor, root_resolution);
+                let neighbor_chunk_origin = self.origin_of_chunk_owning(neighbor);
+                if neighbor_chunk_origin == chunk_origin {
+                    continue;
+                }
+                let neighbor_cell = match self.chunk_at(neighbor_chunk_origin) {
+                    Some(neighbor_chunk) => *neighbor_chunk.cell(neighbor.into()),
+                    None => continue,
+                };
+                boundary_cells.insert(pos);
+                boundary_cells.insert(neighbor);
+
+                // Each side was lit assuming whatever is above it was as `WorldGen`
+                // made it; relight anywhere that turned out to be wrong.
+                if neighbor.pos().rxy != pos.pos().rxy {
+                    continue;
+                }
+                let materials = self.materials();
+                if neighbor.pos().z > pos.pos().z {
+                    if materials.is_transparent(cell.material) &&
+                        is_open_sky(&cell) != is_open_sky(&neighbor_cell)
+                    {
+                        changed_cells.push(pos);
+                    }
+                } else if materials.is_transparent(neighbor_cell.material) &&
+                           is_open_sky(&neighbor_cell) != is_open_sky(&cell)
+                {
+                    changed_cells.push(neighbor);
+                }
+            }
+        }
+        let boundary_cells: Vec<PosInOwningRoot> = boundary_cells.into_iter().collect();
+        self.relight(&changed_cells, &boundary_cells);
+
+        // Cells are drawn using the light of the cells next to them, so the chunks
+        // around this one need redrawing even if none of their light changed.
+        let accessible_chunks = self.chunk_at(chunk_origin)
+            .expect("Lit chunk disappeared")
+            .accessible_chunks
+            .clone();
+        for accessible_chunk_origin in accessible_chunks {
+            if let Some(chunk) = self.chunks_mut().get_mut(&accessible_chunk_origin) {
+                chunk.mark_view_as_dirty();
+            }
+        }
+    }
+}
+
+/// Light a chunk as if it were the only one loaded, with everything
+/// above it as `gen` made it, ready for `Globe::light_chunk`.
+///
+/// This is the slow part of lighting a new chunk, but it only needs the chunk
+/// itself, so it can be done on any thread. Only the cells that the chunk owns
+/// are lit; the rest get their light from the chunks that own them.
+pub fn light_chunk_in_isolation(
+    chunk: &mut Chunk,
+    spec: Spec,
+    gen: &WorldGen,
+    materials: &MaterialRegistry,
+) {
+    let chunk_origin = chunk.origin;
+    let root_resolution = spec.root_resolution;
+    let owned_cells = cells_owned_by_chunk(chunk_origin, spec);
+    let is_owned = |pos: PosInOwningRoot| {
+        origin_of_chunk_owning(pos, root_resolution, spec.chunk_resolution) == chunk_origin
+    };
+    for &channel in &[Channel::Sky, Channel::Block] {
+        let mut lit: VecDeque<PosInOwningRoot> = VecDeque::new();
+        for &pos in &owned_cells {
+            let cell = chunk.cell_mut(pos.into());
+            let source_light = match channel {
+                Channel::Sky => {
+                    // Sunlight from higher up in the chunk gets spread down below.
+                    let above = pos.pos().with_z(pos.pos().z + 1);
+                    let is_top = !is_owned(PosInOwningRoot::new(above, root_resolution));
+                    if is_top && materials.is_transparent(cell.material) &&
+                        gen_leaves_open_sky(spec, gen, materials, above)
+                    {
+                        MAX_LIGHT
+                    } else {
+                        0
+                    }
+                }
+                Channel::Block => materials.light_emitted(cell.material),
+            };
+            channel.set_light(cell, source_light);
+            if source_light > 0 {
+                lit.push_back(pos);
+            }
+        }
+        while let Some(pos) = lit.pop_front() {
+            let light = channel.light(chunk.cell(pos.into()));
+            for neighbor in Neighbors::new(pos.into(), root_resolution) {
+                if neighbor.z < 0 {
+                    continue;
+                }
+                let neighbor = PosInOwningRoot::new(neighbor, root_resolution);
+                if !is_owned(neighbor) {
+                    continue;
+                }
+                let new_light = if is_sunbeam(channel, pos, neighbor, light) {
+                    light
+                } else {
+                    light.saturating_sub(1)
+                };
+                let neighbor_cell = chunk.cell_mut(neighbor.into());
+                if materials.is_transparent(neighbor_cell.material) &&
+                    new_light > channel.light(neighbor_cell)
+                {
+                    channel.set_light(neighbor_cell, new_light);
+                    lit.push_back(neighbor);
+                }
+            }
+        }
+    }
+}
+
+fn cells_owned_by_chunk(chunk_origin: ChunkOrigin, spec: Spec) -> Vec<PosInOwningRoot> {
+    let root_resolution = spec.root_resolution;
+    let chunk_resolution = spec.chunk_resolution;
+    let origin = *chunk_origin.pos();
+    let end_x = origin.x + chunk_resolution[0];
+    let end_y = origin.y + chunk_resolution[1];
+    let end_z = origin.z + chunk_resolution[2] - 1;
+    let mut cells: Vec<PosInOwningRoot> = Vec::new();
+    for cell_z in origin.z..(end_z + 1) {
+        for cell_y in origin.y..(end_y + 1) {
+            for cell_x in origin.x..(end_x + 1) {
+                let pos = GridPoint3::new(origin.root, cell_x, cell_y, cell_z);
+                let pos = PosInOwningRoot::new(pos, root_resolution);
+                if origin_of_chunk_owning(pos, root_resolution, chunk_resolution) == chunk_origin {
+                    cells.push(pos);
+                }
+            }
+        }
+    }
+    cells
+}
+
+// Whether light going from `from` to `to` is sunlight shining straight down,
+// which doesn't get any dimmer.
+fn is_sunbeam(channel: Channel, from: PosInOwningRoot, to: PosInOwningRoot, light: u8) -> bool {
+    channel == Channel::Sky && light == MAX_LIGHT && to.pos().rxy == from.pos().rxy &&
+        to.pos().z + 1 == from.pos().z
+}
+
+// Whether the cell at `pos` would get direct sunlight if the world
+// were exactly as `gen` made it.
+fn gen_leaves_open_sky(
+    spec: Spec,
+    gen: &WorldGen,
+    materials: &MaterialRegistry,
+    pos: GridPoint3,
+) -> bool {
+    let height = spec.cell_center_center(pos).coords.norm();
+    height > gen.land_height(pos.rxy) && materials.is_transparent(gen.cell_at(pos).material)
+}
+
+// Relights one channel after changes to some cells, by first darkening
+// everything that might have been lit by them, and then spreading light
+// back in from everything around the darkened area and any light sources
+// within it.
+struct Relight<'a> {
+    globe: &'a mut Globe,
+    channel: Channel,
+    // Chunks owning any cell whose light changed.
+    relit_chunks: &'a mut HashSet<ChunkOrigin>,
+}
+
+impl<'a> Relight<'a> {
+    fn relight(&mut self, changed_cells: &[PosInOwningRoot], spread_from: &[PosInOwningRoot]) {
+        let mut darkened: VecDeque<(PosInOwningRoot, u8)> = VecDeque::new();
+        let mut lit: VecDeque<PosInOwningRoot> = VecDeque::new();
+
+        for &pos in changed_cells {
+            let old_light = self.light(pos).expect("Chunk should be loaded");
+            if old_light > 0 {
+                self.set_light(pos, 0);
+                darkened.push_back((pos, old_light));
+            }
+        }
+        while let Some((pos, old_light)) = darkened.pop_front() {
+            for neighbor in self.neighbors(pos) {
+                let light = match self.light(neighbor) {
+                    Some(light) if light > 0 => light,
+                    _ => continue,
+                };
+                let was_sunbeam =
+                    light == old_light && is_sunbeam(self.channel, pos, neighbor, light);
+                if light < old_light || was_sunbeam {
+                    // It might have been lit by the cell we just darkened.
+                    self.set_light(neighbor, 0);
+                    darkened.push_back((neighbor, light));
+                    let source_light = self.source_light(neighbor);
+                    if source_light > 0 {
+                        self.set_light(neighbor, source_light);
+                        lit.push_back(neighbor);
+                    }
+                } else {
+                    // It's lit from somewhere else, so it can light
+                    // the darkened cells back up.
+                    lit.push_back(neighbor);
+                }
+            }
+        }
+
+        for &pos in changed_cells {
+            let source_light = self.source_light(pos);
+            if source_light > self.light(pos).expect("Chunk should be loaded") {
+                self.set_light(pos, source_light);
+                lit.push_back(pos);
+            }
+        }
+        for &pos in spread_from {
+            if self.light(pos).map_or(false, |light| light > 0) {
+                lit.push_back(pos);
+            }
+        }
+        while let Some(pos) = lit.pop_front() {
+            let light = self.light(pos).expect("Chunk should be loaded");
+            for neighbor in self.neighbors(pos) {
+                let neighbor_light = match self.cell(neighbor) {
+                    Some(cell) if self.lets_light_through(cell) => self.channel.light(cell),
+                    _ => continue,
+                };
+                let new_light = if is_sunbeam(self.channel, pos, neighbor, light) {
+                    light
+                } else {
+                    light.saturating_sub(1)
+                };
+                if new_light > neighbor_light {
+                    self.set_light(neighbor, new_light);
+                    lit.push_back(neighbor);
+                }
+            }
+        }
+    }
+
+    // Returns `None` if the chunk owning the cell isn't loaded.
+    fn cell(&self, pos: PosInOwningRoot) -> Option<&Cell> {
+        let chunk_origin = self.globe.origin_of_chunk_owning(pos);
+        self.globe.chunk_at(chunk_origin).map(
+            |chunk| chunk.cell(pos.into()),
+        )
+    }
+
+    fn light(&self, pos: PosInOwningRoot) -> Option<u8> {
+        self.cell(pos).map(|cell| self.channel.light(cell))
+    }
+
+    // Doesn't mark the chunk as having unsaved changes;
+    // light is never saved.
+    fn set_light(&mut self, pos: PosInOwningRoot, light: u8) {
+        let chunk_origin = self.globe.origin_of_chunk_owning(pos);
+        let chunk = self.globe.chunks_mut().get_mut(&chunk_origin).expect(
+            "Chunk should be loaded",
+        );
+        self.channel.set_light(chunk.cell_mut(pos.into()), light);
+        self.relit_chunks.insert(chunk_origin);
+    }
+
+    fn lets_light_through(&self, cell: &Cell) -> bool {
+        self.globe.materials().is_transparent(cell.material)
+    }
+
+    fn neighbors(&self, pos: PosInOwningRoot) -> Vec<PosInOwningRoot> {
+        let root_resolution = self.globe.spec().root_resolution;
+        Neighbors::new(pos.into(), root_resolution)
+            .filter(|neighbor| neighbor.z >= 0)
+            .map(|neighbor| PosInOwningRoot::new(neighbor, root_resolution))
+            .collect()
+    }
+
+    // How brightly the cell at `pos` would be lit if there were no light around it.
+    fn source_light(&self, pos: PosInOwningRoot) -> u8 {
+        let cell = *self.cell(pos).expect("Chunk should be loaded");
+        match self.channel {
+            Channel::Sky => {
+                if self.lets_light_through(&cell) && self.is_under_open_sky(pos) {
+                    MAX_LIGHT
+                } else {
+                    0
+                }
+            }
+            Channel::Block => self.globe.materials().light_emitted(cell.material),
+        }
+    }
+
+    // Whether the cell above `pos` gets direct sunlight. If that cell's chunk
+    // isn't loaded, then assume it's as `WorldGen` made it.
+    fn is_under_open_sky(&self, pos: PosInOwningRoot) -> bool {
+        let root_resolution = self.globe.spec().root_resolution;
+        let above = pos.pos().with_z(pos.pos().z + 1);
+        if let Some(cell) = self.cell(PosInOwningRoot::new(above, root_resolution)) {
+            return cell.sky_light == MAX_LIGHT;
+        }
+        gen_leaves_open_sky(self.globe.spec(), self.globe.gen(), self.globe.materials(), above)
+    }
+}
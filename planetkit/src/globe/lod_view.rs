@@ -0,0 +1,119 @@
+use specs;
+
+use types::{Pt3, Vec3};
+use render;
+use super::Globe;
+use super::icosahedron;
+
+// How far below the surface to put LOD geometry, in cells, so that
+// any chunks drawn at the same time cover it up.
+const LOD_SINK_CELLS: f64 = 1.0;
+
+// How far down to look for something to color LOD geometry with,
+// in case there's a cave opening at the surface.
+const LOD_MAX_COLOR_DEPTH: usize = 4;
+
+// Color for LOD geometry where we can't find anything solid.
+const LOD_FALLBACK_COLOR: [f32; 3] = [0.4, 0.4, 0.4];
+
+/// Coarse stand-in for a whole `Globe`, for drawing it from far enough away
+/// that its chunks aren't worth loading. See `LodViewSystem`.
+pub struct LodView {
+    pub globe_entity: specs::Entity,
+}
+
+impl LodView {
+    pub fn new(globe_entity: specs::Entity) -> LodView {
+        LodView { globe_entity: globe_entity }
+    }
+}
+
+impl specs::Component for LodView {
+    type Storage = specs::HashMapStorage<LodView>;
+}
+
+/// Creates a coarse approximation of the whole globe, with vertex positions
+/// specified relative to the center of the globe, for drawing it from far
+/// enough away that its chunks aren't loaded.
+///
+/// This is an icosahedron with each edge split into `subdivisions` pieces,
+/// pushed out to the surface of the land or ocean as `WorldGen` made it,
+/// and colored by whatever is there. It sits slightly below the surface,
+/// so that any chunks drawn at the same time cover it up.
+///
+/// # Panics
+///
+/// Panics if `subdivisions` is zero.
+pub fn make_lod_geometry(
+    globe: &Globe,
+    subdivisions: usize,
+    vertex_data: &mut Vec<render::Vertex>,
+    index_data: &mut Vec<u32>,
+) {
+    assert!(subdivisions > 0, "LOD geometry needs at least one subdivision");
+
+    let n = subdivisions;
+    for face in icosahedron::FACES.iter() {
+        let corners: Vec<Vec3> = face.iter()
+            .map(|&i| {
+                let vertex = icosahedron::VERTICES[i];
+                Vec3::new(vertex[0], vertex[1], vertex[2])
+            })
+            .collect();
+        let u = (corners[1] - corners[0]) / n as f64;
+        let v = (corners[2] - corners[0]) / n as f64;
+
+        // Lay out vertices in rows along `v`, each one shorter than the last.
+        let mut row_starts: Vec<u32> = Vec::new();
+        for i in 0..(n + 1) {
+            row_starts.push(vertex_data.len() as u32);
+            for j in 0..(n + 1 - i) {
+                let direction = (corners[0] + u * i as f64 + v * j as f64).normalize();
+                let (vertex_pt3, color) = lod_vertex(globe, direction);
+                vertex_data.push(render::Vertex::new_from_pt3(vertex_pt3, color));
+            }
+        }
+
+        // Keep the same winding as the face itself.
+        for i in 0..n {
+            for j in 0..(n - i) {
+                let a_i = row_starts[i] + j as u32;
+                let b_i = row_starts[i + 1] + j as u32;
+                let c_i = a_i + 1;
+                index_data.extend_from_slice(&[a_i, b_i, c_i]);
+                if j + 1 < n - i {
+                    index_data.extend_from_slice(&[b_i, b_i + 1, c_i]);
+                }
+            }
+        }
+    }
+}
+
+// Position and color of the LOD vertex in the given
+// direction from the center of the globe.
+fn lod_vertex(globe: &Globe, direction: Vec3) -> (Pt3, [f32; 3]) {
+    let spec = globe.spec();
+    let gen = globe.gen();
+    let column = spec.cell_containing(Pt3::from_coordinates(direction)).rxy;
+    let surface_radius = gen.land_height(column).max(spec.ocean_radius);
+
+    let mut cell_pos = spec.cell_containing(Pt3::from_coordinates(
+        direction * (surface_radius - spec.block_height * 0.5),
+    ));
+    let mut color = LOD_FALLBACK_COLOR;
+    for _ in 0..LOD_MAX_COLOR_DEPTH {
+        if cell_pos.z < 0 {
+            break;
+        }
+        match globe.materials().get(gen.cell_at(cell_pos).material) {
+            Some(material) if !material.is_transparent => {
+                color = material.color;
+                break;
+            }
+            _ => cell_pos.z -= 1,
+        }
+    }
+
+    let radius = surface_radius - spec.block_height * LOD_SINK_CELLS;
+    (Pt3::from_coordinates(direction * radius), color)
+}
@@ -0,0 +1,189 @@
+use std::collections::HashSet;
+
+use specs;
+use specs::{Entities, Fetch, ReadStorage, WriteStorage};
+use slog::Logger;
+
+use types::*;
+use globe::{Globe, LodView};
+use globe::lod_view::make_lod_geometry;
+use render::{Visual, ProtoMesh, Vertex};
+use camera::DefaultCamera;
+use spatial::SpatialStorage;
+use Spatial;
+use AutoResource;
+
+/// Makes a `LodView` for every `Globe`, so that globes can be seen from afar,
+/// and switches between drawing that and the globe's chunks depending on how
+/// far away the camera is.
+///
+/// Chunks are drawn whenever they're loaded, so while the camera is far away,
+/// any that are still loaded just add detail to the `LodView`, which sits
+/// slightly below them. Once the camera comes within `min_altitude` of the
+/// globe's ocean level, the `LodView` is hidden, and the chunks around the
+/// camera take over.
+pub struct LodViewSystem {
+    log: Logger,
+    // How many pieces to split each edge of the icosahedron into.
+    subdivisions: usize,
+    min_altitude: f64,
+}
+
+impl LodViewSystem {
+    pub fn new(
+        world: &mut specs::World,
+        parent_log: &Logger,
+        subdivisions: usize,
+        min_altitude: f64,
+    ) -> LodViewSystem {
+        DefaultCamera::ensure(world);
+        LodViewSystem {
+            log: parent_log.new(o!("system" => "lod_view")),
+            subdivisions: subdivisions,
+            min_altitude: min_altitude,
+        }
+    }
+
+    fn remove_views_for_dead_globes<'a>(
+        &mut self,
+        entities: &Entities<'a>,
+        globes: &ReadStorage<'a, Globe>,
+        visuals: &mut WriteStorage<'a, Visual>,
+        lod_views: &mut WriteStorage<'a, LodView>,
+    ) {
+        use specs::Join;
+
+        let mut entities_to_remove: Vec<specs::Entity> = Vec::new();
+        for (lod_view, lod_view_ent) in (&*lod_views, &**entities).join() {
+            if globes.get(lod_view.globe_entity).is_none() {
+                debug!(self.log, "Removing a LOD view for a globe that no longer exists");
+                entities_to_remove.push(lod_view_ent);
+            }
+        }
+
+        for lod_view_ent in entities_to_remove {
+            // Dropping the `Visual` drops its pointer to the mesh, so the mesh
+            // repository will free the mesh next time it collects garbage.
+            visuals.remove(lod_view_ent);
+            lod_views.remove(lod_view_ent);
+            entities.delete(lod_view_ent);
+        }
+    }
+
+    fn ensure_lod_view_entities<'a>(
+        &mut self,
+        entities: &Entities<'a>,
+        globes: &ReadStorage<'a, Globe>,
+        lod_views: &mut WriteStorage<'a, LodView>,
+        visuals: &mut WriteStorage<'a, Visual>,
+        spatials: &mut WriteStorage<'a, Spatial>,
+    ) {
+        use specs::Join;
+
+        let globes_with_views: HashSet<specs::Entity> = lod_views
+            .join()
+            .map(|lod_view| lod_view.globe_entity)
+            .collect();
+        // We can only place the view relative to a globe that has a position.
+        let globes_without_views: Vec<specs::Entity> = (globes, &**entities)
+            .join()
+            .map(|(_globe, globe_entity)| globe_entity)
+            .filter(|globe_entity| {
+                !globes_with_views.contains(globe_entity) && spatials.get(*globe_entity).is_some()
+            })
+            .collect();
+
+        for globe_entity in globes_without_views {
+            let globe = globes.get(globe_entity).expect("Globe disappeared");
+            trace!(self.log, "Making a LOD view"; "subdivisions" => self.subdivisions);
+
+            // Geometry is only based on what `WorldGen` made, so it never
+            // needs to be rebuilt.
+            let mut vertex_data: Vec<Vertex> = Vec::new();
+            let mut index_data: Vec<u32> = Vec::new();
+            make_lod_geometry(globe, self.subdivisions, &mut vertex_data, &mut index_data);
+            let mut visual = Visual::new_empty();
+            visual.proto_mesh = ProtoMesh::new(vertex_data, index_data).into();
+
+            // Geometry is relative to the center of the globe.
+            let new_ent = entities.create();
+            lod_views.insert(new_ent, LodView::new(globe_entity));
+            visuals.insert(new_ent, visual);
+            spatials.insert(new_ent, Spatial::new(globe_entity, Iso3::identity()));
+        }
+    }
+
+    fn show_or_hide_lod_views<'a>(
+        &mut self,
+        camera: Option<specs::Entity>,
+        globes: &ReadStorage<'a, Globe>,
+        lod_views: &WriteStorage<'a, LodView>,
+        visuals: &mut WriteStorage<'a, Visual>,
+        spatials: &WriteStorage<'a, Spatial>,
+    ) {
+        use specs::Join;
+
+        // Camera might not have been realized yet.
+        let camera = match camera {
+            Some(camera) if spatials.get(camera).is_some() => camera,
+            _ => return,
+        };
+
+        for (lod_view, visual) in (lod_views, visuals).join() {
+            let globe_entity = lod_view.globe_entity;
+            let globe = match globes.get(globe_entity) {
+                Some(globe) => globe,
+                None => continue,
+            };
+            // It won't be drawn anyway.
+            if !spatials.have_common_ancestor(camera, globe_entity) {
+                continue;
+            }
+            let camera_distance = spatials
+                .a_relative_to_b(camera, globe_entity)
+                .translation
+                .vector
+                .norm();
+            let altitude = camera_distance - globe.spec().ocean_radius;
+            let should_hide = altitude < self.min_altitude;
+            if should_hide != visual.is_hidden() {
+                debug!(
+                    self.log,
+                    "Switching between LOD view and chunk views";
+                    "lod_hidden" => should_hide,
+                    "altitude" => altitude
+                );
+                visual.set_hidden(should_hide);
+            }
+        }
+    }
+}
+
+impl<'a> specs::System<'a> for LodViewSystem {
+    type SystemData = (Entities<'a>,
+     Fetch<'a, DefaultCamera>,
+     ReadStorage<'a, Globe>,
+     WriteStorage<'a, Visual>,
+     WriteStorage<'a, Spatial>,
+     WriteStorage<'a, LodView>);
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (entities, default_camera, globes, mut visuals, mut spatials, mut lod_views) = data;
+
+        self.remove_views_for_dead_globes(&entities, &globes, &mut visuals, &mut lod_views);
+        self.ensure_lod_view_entities(
+            &entities,
+            &globes,
+            &mut lod_views,
+            &mut visuals,
+            &mut spatials,
+        );
+        self.show_or_hide_lod_views(
+            default_camera.camera_entity,
+            &globes,
+            &lod_views,
+            &mut visuals,
+            &spatials,
+        );
+    }
+}
@@ -0,0 +1,255 @@
+use std::collections::HashMap;
+
+/// Compact identifier for a material defined in a `MaterialRegistry`.
+///
+/// This is what gets stored in every `Cell`; look up the `MaterialDef`
+/// in the globe's registry to find out anything about the material.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Hash, Serialize, Deserialize)]
+pub struct MaterialId(pub u16);
+
+impl MaterialId {
+    // Built-in materials. Every `MaterialRegistry` starts out with these,
+    // and they always have the same IDs so that world generators and
+    // saved chunks can rely on them.
+    pub const AIR: MaterialId = MaterialId(0);
+    pub const DIRT: MaterialId = MaterialId(1);
+    pub const WATER: MaterialId = MaterialId(2);
+    pub const SAND: MaterialId = MaterialId(3);
+    pub const SNOW: MaterialId = MaterialId(4);
+    pub const STONE: MaterialId = MaterialId(5);
+    pub const SLATE: MaterialId = MaterialId(6);
+    pub const BEDROCK: MaterialId = MaterialId(7);
+    pub const COAL: MaterialId = MaterialId(8);
+    pub const IRON_ORE: MaterialId = MaterialId(9);
+    pub const GOLD_ORE: MaterialId = MaterialId(10);
+}
+
+/// Description of a kind of material that cells can be made of.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct MaterialDef {
+    /// Unique name, e.g., "dirt".
+    pub name: String,
+    /// Whether `CellDweller`s can stand on it, and are blocked from moving into it.
+    pub is_solid: bool,
+    /// Whether it flows, e.g., water or lava.
+    pub is_liquid: bool,
+    /// Whether you can see through it to the cells beyond.
+    /// Transparent materials are not drawn at all.
+    pub is_transparent: bool,
+    /// Base color used when drawing cells made of this material.
+    pub color: [f32; 3],
+    /// Whether a `CellDweller` can dig it out.
+    pub is_mineable: bool,
+    /// How brightly it glows, up to `MAX_LIGHT`; most materials don't glow at all.
+    #[serde(default)]
+    pub light_emitted: u8,
+}
+
+impl MaterialDef {
+    /// Whether there's nothing there at all; i.e. it's neither solid nor liquid.
+    pub fn is_empty(&self) -> bool {
+        !self.is_solid && !self.is_liquid
+    }
+}
+
+/// All the materials that can be used in a `Globe`.
+///
+/// Starts out containing PlanetKit's built-in materials (see `MaterialId`),
+/// and games can register their own on top of those before creating
+/// any globes that use them.
+pub struct MaterialRegistry {
+    materials: Vec<MaterialDef>,
+    ids_by_name: HashMap<String, MaterialId>,
+}
+
+impl MaterialRegistry {
+    pub fn new() -> MaterialRegistry {
+        let mut registry = MaterialRegistry {
+            materials: Vec::new(),
+            ids_by_name: HashMap::new(),
+        };
+        let air = registry.register(MaterialDef {
+            name: "air".to_string(),
+            is_solid: false,
+            is_liquid: false,
+            is_transparent: true,
+            color: [1.0, 1.0, 1.0],
+            is_mineable: false,
+            light_emitted: 0,
+        });
+        let dirt = registry.register(MaterialDef {
+            name: "dirt".to_string(),
+            is_solid: true,
+            is_liquid: false,
+            is_transparent: false,
+            // Grassy green
+            color: [0.0, 0.4, 0.0],
+            is_mineable: true,
+            light_emitted: 0,
+        });
+        let water = registry.register(MaterialDef {
+            name: "water".to_string(),
+            is_solid: false,
+            is_liquid: true,
+            // TODO: make this transparent once we can draw
+            // things behind it without it looking silly.
+            is_transparent: false,
+            // Ocean blue
+            color: [0.0, 0.1, 0.7],
+            is_mineable: false,
+            light_emitted: 0,
+        });
+        debug_assert_eq!(air, MaterialId::AIR);
+        debug_assert_eq!(dirt, MaterialId::DIRT);
+        debug_assert_eq!(water, MaterialId::WATER);
+
+        // The rest are all boring solids.
+        let solids = [
+            (MaterialId::SAND, "sand", [0.76, 0.7, 0.45], true),
+            (MaterialId::SNOW, "snow", [0.9, 0.92, 0.95], true),
+            (MaterialId::STONE, "stone", [0.4, 0.4, 0.4], true),
+            (MaterialId::SLATE, "slate", [0.28, 0.3, 0.34], true),
+            // Nobody gets to dig through the bottom of the world.
+            (MaterialId::BEDROCK, "bedrock", [0.12, 0.12, 0.12], false),
+            (MaterialId::COAL, "coal", [0.05, 0.05, 0.05], true),
+            (MaterialId::IRON_ORE, "iron_ore", [0.6, 0.4, 0.3], true),
+            (MaterialId::GOLD_ORE, "gold_ore", [0.9, 0.75, 0.2], true),
+        ];
+        for &(expected_id, name, color, is_mineable) in &solids {
+            let id = registry.register(MaterialDef {
+                name: name.to_string(),
+                is_solid: true,
+                is_liquid: false,
+                is_transparent: false,
+                color: color,
+                is_mineable: is_mineable,
+                light_emitted: 0,
+            });
+            debug_assert_eq!(expected_id, id);
+        }
+        registry
+    }
+
+    /// Add a new material, and return its ID.
+    ///
+    /// # Panics
+    ///
+    /// Panics if there is already a material with the same name,
+    /// or if the registry is full.
+    pub fn register(&mut self, material: MaterialDef) -> MaterialId {
+        assert!(
+            !self.ids_by_name.contains_key(&material.name),
+            "There is already a material with this name"
+        );
+        assert!(
+            self.materials.len() <= u16::max_value() as usize,
+            "Too many materials"
+        );
+        let id = MaterialId(self.materials.len() as u16);
+        self.ids_by_name.insert(material.name.clone(), id);
+        self.materials.push(material);
+        id
+    }
+
+    pub fn get(&self, id: MaterialId) -> Option<&MaterialDef> {
+        self.materials.get(id.0 as usize)
+    }
+
+    pub fn id_by_name(&self, name: &str) -> Option<MaterialId> {
+        self.ids_by_name.get(name).cloned()
+    }
+
+    // Convenience accessors for material properties.
+    //
+    // Unknown materials (e.g., from a chunk saved by a game that registered
+    // more materials) are treated as solid, opaque, and indestructible;
+    // that's the least surprising way for them to behave.
+
+    pub fn is_solid(&self, id: MaterialId) -> bool {
+        self.get(id).map_or(true, |material| material.is_solid)
+    }
+
+    pub fn is_liquid(&self, id: MaterialId) -> bool {
+        self.get(id).map_or(false, |material| material.is_liquid)
+    }
+
+    pub fn is_transparent(&self, id: MaterialId) -> bool {
+        self.get(id).map_or(false, |material| material.is_transparent)
+    }
+
+    pub fn is_mineable(&self, id: MaterialId) -> bool {
+        self.get(id).map_or(false, |material| material.is_mineable)
+    }
+
+    pub fn is_empty(&self, id: MaterialId) -> bool {
+        self.get(id).map_or(false, |material| material.is_empty())
+    }
+
+    pub fn light_emitted(&self, id: MaterialId) -> u8 {
+        self.get(id).map_or(0, |material| material.light_emitted)
+    }
+}
+
+impl Default for MaterialRegistry {
+    fn default() -> MaterialRegistry {
+        MaterialRegistry::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn built_in_materials() {
+        let registry = MaterialRegistry::new();
+        assert_eq!(Some(MaterialId::AIR), registry.id_by_name("air"));
+        assert_eq!(Some(MaterialId::DIRT), registry.id_by_name("dirt"));
+        assert_eq!(Some(MaterialId::WATER), registry.id_by_name("water"));
+        assert_eq!(Some(MaterialId::SAND), registry.id_by_name("sand"));
+        assert_eq!(Some(MaterialId::SNOW), registry.id_by_name("snow"));
+        assert_eq!(Some(MaterialId::STONE), registry.id_by_name("stone"));
+        assert!(registry.is_empty(MaterialId::AIR));
+        assert!(registry.is_solid(MaterialId::DIRT));
+        assert!(registry.is_mineable(MaterialId::DIRT));
+        assert!(registry.is_liquid(MaterialId::WATER));
+        assert_eq!(Some(MaterialId::GOLD_ORE), registry.id_by_name("gold_ore"));
+        assert!(registry.is_solid(MaterialId::BEDROCK));
+        assert!(!registry.is_mineable(MaterialId::BEDROCK));
+        assert!(!registry.is_empty(MaterialId::WATER));
+    }
+
+    #[test]
+    fn register_custom_material() {
+        let mut registry = MaterialRegistry::new();
+        let ice = registry.register(MaterialDef {
+            name: "ice".to_string(),
+            is_solid: true,
+            is_liquid: false,
+            is_transparent: false,
+            color: [0.8, 0.9, 1.0],
+            is_mineable: true,
+            light_emitted: 0,
+        });
+        assert_eq!(Some(ice), registry.id_by_name("ice"));
+        assert_eq!("ice", registry.get(ice).unwrap().name);
+        assert!(registry.is_solid(ice));
+    }
+
+    #[test]
+    #[should_panic]
+    fn register_duplicate_material() {
+        let mut registry = MaterialRegistry::new();
+        let dirt = registry.get(MaterialId::DIRT).unwrap().clone();
+        registry.register(dirt);
+    }
+
+    #[test]
+    fn unknown_material_is_solid() {
+        let registry = MaterialRegistry::new();
+        let unknown = MaterialId(1000);
+        assert!(registry.get(unknown).is_none());
+        assert!(registry.is_solid(unknown));
+        assert!(!registry.is_mineable(unknown));
+    }
+}
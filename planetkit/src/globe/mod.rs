@@ -2,19 +2,35 @@
 // main bits at this level below.
 mod globe;
 mod globe_ext;
+mod edit;
+mod journal;
+mod raycast;
+mod flood_fill;
+mod lighting;
 pub mod icosahedron;
 mod spec;
 pub mod chunk;
+mod material;
 mod view;
 mod gen;
+mod heightmap_gen;
+mod biome;
 mod chunk_view;
 mod chunk_view_system;
+mod lod_view;
+mod lod_view_system;
 mod chunk_system;
+mod water_flow_system;
+mod chunk_gen_pool;
+mod chunk_load_anchor;
+mod chunk_budget;
 mod cursor;
 mod chunk_origin;
 mod iters;
 mod chunk_shared_points;
 mod chunk_pair;
+mod chunk_store;
+mod chunk_format;
 
 #[cfg(test)]
 mod tests;
@@ -22,16 +38,34 @@ mod tests;
 use types::*;
 
 // TODO: be selective in what you export; no wildcards!
-pub use self::globe::Globe;
+pub use self::globe::{Globe, ChunkNotReady};
+pub use self::edit::GlobeEdit;
+pub use self::journal::{EditJournal, Transaction, CellChange};
+pub use self::raycast::{RaycastHit, CellFace};
+pub use self::flood_fill::CellRegion;
+pub use self::lighting::MAX_LIGHT;
 pub use self::spec::*;
 pub use self::view::*;
 pub use self::chunk_view::*;
 pub use self::chunk_view_system::*;
+pub use self::lod_view::LodView;
+pub use self::lod_view_system::LodViewSystem;
 pub use self::chunk_system::ChunkSystem;
+pub use self::water_flow_system::WaterFlowSystem;
+pub use self::chunk_load_anchor::ChunkLoadAnchor;
+pub use self::chunk_budget::{ChunkBudget, estimated_chunk_bytes};
 pub use self::cursor::{Cursor, CursorMut};
 pub use self::chunk_origin::*;
 pub use self::iters::*;
 pub use self::chunk_shared_points::ChunkSharedPoints;
+pub use self::chunk_store::{ChunkStore, RegionFileChunkStore};
+pub use self::chunk_format::{encode_chunk, decode_chunk, ChunkDecodeError, CHUNK_FORMAT_VERSION};
+pub use self::chunk_format::{encode_chunk_delta, decode_chunk_delta, decode_chunk_or_delta};
+pub use self::chunk_format::CHUNK_DELTA_FORMAT_VERSION;
+pub use self::material::{MaterialId, MaterialDef, MaterialRegistry};
+pub use self::gen::{WorldGen, Gen};
+pub use self::heightmap_gen::HeightmapGen;
+pub use self::biome::{Biome, Climate};
 
 use grid::{GridCoord, GridPoint3, Root, PosInOwningRoot};
 
@@ -190,6 +224,84 @@ pub fn project(root: Root, mut pt_in_root_quad: Pt2) -> Pt3 {
     Pt3::from_coordinates(pos_on_icosahedron.coords.normalize())
 }
 
+/// Inverse of `project`: find the root quad that a direction from the center
+/// of the globe passes through, and where in that root quad it passes through.
+///
+/// The position in the root quad uses the same convention as `project`.
+/// Directions passing exactly between roots may be given in either root.
+///
+/// # Panics
+///
+/// Panics if `direction` is zero.
+pub fn unproject(direction: Vec3) -> (Root, Pt2) {
+    use self::icosahedron::{FACES, VERTICES};
+    use grid::ROOTS;
+
+    let vertex = |i: usize| Vec3::new(VERTICES[i][0], VERTICES[i][1], VERTICES[i][2]);
+
+    // Find the triangle that the direction passes through most squarely.
+    // See `project` for the layout of triangles in each root quad.
+    let mut best: Option<(f64, Root, Pt2)> = None;
+    for root in &ROOTS {
+        let faces = [
+            FACES[root.index as usize * 4],
+            FACES[root.index as usize * 4 + 1],
+            FACES[root.index as usize * 4 + 2],
+            FACES[root.index as usize * 4 + 3],
+        ];
+        let a = vertex(faces[0][0]);
+        let b = vertex(faces[0][1]);
+        let c = vertex(faces[1][1]);
+        let d = vertex(faces[1][0]);
+        let e = vertex(faces[3][1]);
+        let f = vertex(faces[3][0]);
+
+        // Each triangle is described by a corner and the two edges from it
+        // that `project` uses, as in `corner + u * alpha + v * beta`.
+        let triangles = [
+            (a, b - a, c - a),
+            (d, c - d, b - d),
+            (c, d - c, e - c),
+            (f, e - f, d - f),
+        ];
+        for (triangle_index, &(corner, u, v)) in triangles.iter().enumerate() {
+            // Find where the direction hits the plane of the triangle.
+            let normal = u.cross(&v);
+            let direction_dot_normal = direction.dot(&normal);
+            if direction_dot_normal <= 0.0 {
+                // Pointing away from this triangle.
+                continue;
+            }
+            let pos = direction * (corner.dot(&normal) / direction_dot_normal);
+
+            // Express that as `corner + u * alpha + v * beta`.
+            let w = pos - corner;
+            let (uu, uv, vv) = (u.dot(&u), u.dot(&v), v.dot(&v));
+            let (wu, wv) = (w.dot(&u), w.dot(&v));
+            let denominator = uu * vv - uv * uv;
+            let alpha = (vv * wu - uv * wv) / denominator;
+            let beta = (uu * wv - uv * wu) / denominator;
+
+            // How far inside the triangle we are; negative if outside.
+            let insideness = alpha.min(beta).min(1.0 - alpha - beta);
+            if best.map_or(false, |(best_insideness, _, _)| best_insideness >= insideness) {
+                continue;
+            }
+
+            // Undo the math from `project`, remembering that it doubles y.
+            let (x, double_y) = match triangle_index {
+                0 => (alpha, beta),
+                1 => (1.0 - alpha, 1.0 - beta),
+                2 => (alpha, beta + 1.0),
+                _ => (1.0 - alpha, 2.0 - beta),
+            };
+            best = Some((insideness, *root, Pt2::new(x, double_y / 2.0)));
+        }
+    }
+    let (_, root, pt_in_root_quad) = best.expect("Direction must not be zero");
+    (root, pt_in_root_quad)
+}
+
 /// Calculate the origin of a chunk that contains the given `pos`,
 /// with the guarantee that the chunk will be in the same root even
 /// if `pos` is on the edge of that root.
@@ -276,13 +388,13 @@ pub fn origin_of_chunk_owning(
 }
 
 pub fn is_point_on_chunk_edge(point: GridPoint3, chunk_resolution: [GridCoord; 3]) -> bool {
-    (point.x % chunk_resolution[0]) == point.x
+    (point.x % chunk_resolution[0]) == 0
     ||
-    (point.y % chunk_resolution[1]) == point.y
+    (point.y % chunk_resolution[1]) == 0
     ||
-    (point.z % chunk_resolution[2]) == point.z
+    (point.z % chunk_resolution[2]) == 0
     ||
     // Last z-coordinate of cell is _just before_ the next multiple
     // of chunk z-resolution.
-    ((point.z + 1) % chunk_resolution[2]) == (point.z + 1)
+    ((point.z + 1) % chunk_resolution[2]) == 0
 }
@@ -0,0 +1,158 @@
+// Finding the cells that rays hit.
+
+use types::*;
+use grid::GridPoint3;
+use super::chunk::Cell;
+use super::globe::{Globe, ChunkNotReady};
+
+// How many times to halve the interval in which a ray crosses from
+// one cell to the next when finding exactly where it crosses.
+const CROSSING_REFINEMENTS: usize = 20;
+
+// Step along rays at this fraction of the smallest dimension of a cell.
+// Any cell that the ray passes into between steps is still found, but a cell
+// whose corner the ray clips for less than a step might be missed if the ray
+// then goes back into the cell it came from.
+const STEP_PER_CELL: f64 = 0.25;
+
+/// Which face of a cell a ray entered it through.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum CellFace {
+    /// The top of the cell, farthest from the center of the globe.
+    Top,
+    /// The bottom of the cell, nearest to the center of the globe.
+    Bottom,
+    /// Any of the cell's sides; see `RaycastHit::previous_pos` for which.
+    Side,
+}
+
+/// Where a ray cast by `Globe::raycast` hit a cell.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct RaycastHit {
+    pub pos: GridPoint3,
+    /// Distance along the ray from its origin to where it entered the cell.
+    pub distance: f64,
+    /// Which face of the cell the ray entered through,
+    /// or `None` if the ray started inside the cell.
+    pub entry_face: Option<CellFace>,
+    /// The cell the ray was in just before it entered the cell it hit,
+    /// or `None` if the ray started inside the cell.
+    ///
+    /// This is usually a neighbor of the cell that was hit, but may
+    /// not be if the ray passed very close to the corner of a cell.
+    pub previous_pos: Option<GridPoint3>,
+}
+
+impl Globe {
+    /// Find the first cell along a ray for which `predicate` is true.
+    ///
+    /// The ray starts at `origin`, and goes at most `max_distance` in
+    /// `direction`, both in the globe's frame of reference. It passes through
+    /// cells across chunk and root boundaries, and stops if it goes below the
+    /// floor of the globe.
+    ///
+    /// Returns `Ok(None)` if the ray doesn't hit any matching cells, or an error
+    /// if it reaches a chunk that isn't loaded before hitting anything.
+    ///
+    /// This is a sampled approximation rather than an exact traversal of
+    /// the cells' hexagonal prisms. The ray is sampled at intervals of a fraction
+    /// of the smallest cell dimension, and whenever a sample lands in a new cell,
+    /// the crossing is narrowed down to find the cell the ray went into straight
+    /// after leaving the last one, so a step that crosses several cells still
+    /// visits each of them in turn. A cell can only be missed if the ray clips
+    /// its corner and goes back into the cell it came from within one step,
+    /// which can happen where cells are distorted, e.g., near the edges of root quads.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `direction` is zero.
+    pub fn raycast<P>(
+        &self,
+        origin: Pt3,
+        direction: Vec3,
+        max_distance: f64,
+        mut predicate: P,
+    ) -> Result<Option<RaycastHit>, ChunkNotReady>
+    where
+        P: FnMut(&Cell) -> bool,
+    {
+        let spec = self.spec();
+        let direction = direction.normalize();
+        let step = STEP_PER_CELL * approx_min_cell_size(self);
+        let cell_at = |distance: f64| spec.cell_containing(origin + direction * distance);
+
+        let mut distance = 0.0;
+        let mut pos = cell_at(distance);
+        if pos.z < 0 {
+            return Ok(None);
+        }
+        if predicate(self.maybe_non_authoritative_cell_if_ready(pos)?) {
+            return Ok(Some(RaycastHit {
+                pos: pos,
+                distance: 0.0,
+                entry_face: None,
+                previous_pos: None,
+            }));
+        }
+
+        while distance < max_distance {
+            let next_distance = (distance + step).min(max_distance);
+            if cell_at(next_distance) == pos {
+                distance = next_distance;
+                continue;
+            }
+
+            // We left the cell somewhere in this step; find out exactly where,
+            // so that we end up in the cell we went into first, rather than
+            // wherever the step landed.
+            let mut left_distance = distance;
+            let mut entered_distance = next_distance;
+            for _ in 0..CROSSING_REFINEMENTS {
+                let middle_distance = (left_distance + entered_distance) / 2.0;
+                if cell_at(middle_distance) == pos {
+                    left_distance = middle_distance;
+                } else {
+                    entered_distance = middle_distance;
+                }
+            }
+            let previous_pos = pos;
+            pos = cell_at(entered_distance);
+            distance = entered_distance;
+            if pos.z < 0 {
+                return Ok(None);
+            }
+
+            // If we're still in the same column, then we must have
+            // gone through the top or bottom of the cell.
+            let entry_face = if pos.with_z(previous_pos.z) == previous_pos {
+                if pos.z > previous_pos.z {
+                    CellFace::Bottom
+                } else {
+                    CellFace::Top
+                }
+            } else {
+                CellFace::Side
+            };
+
+            if predicate(self.maybe_non_authoritative_cell_if_ready(pos)?) {
+                return Ok(Some(RaycastHit {
+                    pos: pos,
+                    distance: distance,
+                    entry_face: Some(entry_face),
+                    previous_pos: Some(previous_pos),
+                }));
+            }
+        }
+        Ok(None)
+    }
+}
+
+// Rough distance between cell centers at the floor of the globe,
+// or the height of a cell, whichever is smaller.
+fn approx_min_cell_size(globe: &Globe) -> f64 {
+    let spec = globe.spec();
+    // Each root quad is one edge of the icosahedron wide,
+    // and the edges of an icosahedron subtend about 1.107 radians.
+    let cell_angle = 1.107 / spec.root_resolution[0] as f64;
+    (spec.floor_radius * cell_angle).min(spec.block_height)
+}
@@ -1,6 +1,14 @@
+use std::error;
+use std::fmt;
+use std::fs::File;
+use std::io;
+use std::path::Path;
+
+use serde_json;
+
 use types::*;
 
-use grid::{GridCoord, GridPoint2, GridPoint3};
+use grid::{GridCoord, GridPoint2, GridPoint3, PosInOwningRoot};
 
 // Contains the specifications (dimensions, seed, etc.)
 // needed to deterministically generate a `Globe`.
@@ -8,18 +16,22 @@ use grid::{GridCoord, GridPoint2, GridPoint3};
 // Provides helper functions that don't need to know anything
 // beyond these values.
 //
+// Use `SpecBuilder` to make one that's sure to be valid, or `Spec::load`
+// to read one from a config file. Peers that need to agree on what a globe
+// looks like can send each other its `Spec`.
+//
 // TODO: accessors for all the fields, and make them private.
 //
 // TODO: split out parameters that are applicable to all
 // kinds of globes, and those specific to individual kinds
 // of globes.
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
 pub struct Spec {
     pub seed: u32,
     pub floor_radius: f64,
-    // NOTE: Don't let ocean radius be a neat multiple of block
-    // height above floor radius, or we'll end up with
-    // z-fighting in evaluating what blocks are water/air.
+    // NOTE: Don't let ocean radius be right in the middle of a layer
+    // of cells, or we'll end up with z-fighting in evaluating
+    // what blocks are water/air. (See `SpecError::OceanRadiusAtCellCenter`.)
     pub ocean_radius: f64,
     pub block_height: f64,
     // These are the full width/height/depth of a given root quad or chunk's voxmap;
@@ -27,9 +39,304 @@ pub struct Spec {
     // world can have unbounded total depth.
     pub root_resolution: [GridCoord; 2],
     pub chunk_resolution: [GridCoord; 3],
+    #[serde(default)]
+    pub terrain: TerrainParams,
+    #[serde(default)]
+    pub caves: CaveParams,
+}
+
+// Most octaves that `noise::Fbm` supports.
+const MAX_TERRAIN_OCTAVES: usize = 32;
+
+/// Parameters for shaping the land generated by `Gen`.
+///
+/// The land rises and falls around sea level following fBm noise,
+/// which is sampled on the surface of the sea, so that the same parameters
+/// make features the same size on a globe of any size. Bigger globes
+/// will usually want a longer wavelength, and more octaves to make up
+/// for it in the small details.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct TerrainParams {
+    /// How many layers of noise, each with half the wavelength of the last,
+    /// to add together. Must be between 1 and 32.
+    pub octaves: usize,
+    /// Rough size of the biggest features of the land, e.g., continents,
+    /// in the same units as the globe's radii.
+    pub wavelength: f64,
+    /// How far the land can rise above sea level or sink below it, as a
+    /// proportion of the distance from sea level down to the floor.
+    /// Keep this well below 1, or the soil will reach down to the bedrock.
+    pub amplitude: f64,
+    /// How much each octave contributes compared to the one before it;
+    /// bigger values make for rougher terrain.
+    pub persistence: f64,
+    /// Added to the noise (roughly in [-1, 1]) before scaling it; positive values
+    /// make for more land, and negative values for more ocean.
+    pub sea_level_bias: f64,
+}
+
+impl TerrainParams {
+    pub fn is_valid(&self) -> bool {
+        self.octaves >= 1 && self.octaves <= MAX_TERRAIN_OCTAVES && self.wavelength > 0.0 &&
+            self.amplitude >= 0.0 && self.persistence > 0.0 && self.sea_level_bias.is_finite()
+    }
+}
+
+impl Default for TerrainParams {
+    fn default() -> TerrainParams {
+        TerrainParams {
+            octaves: 6,
+            wavelength: 700.0,
+            // TODO: this is only to stop the dirt level going below bedrock.
+            // Need something a bit more sophisticated than this eventually.
+            //
+            // Also... OpenSimplex, which FBM uses, appears to be totally bonkers?
+            // https://github.com/brendanzab/noise-rs/issues/149
+            amplitude: 0.45,
+            persistence: 0.5,
+            sea_level_bias: 0.0,
+        }
+    }
+}
+
+/// Parameters for carving caves out of the land generated by `Gen`.
+///
+/// There are two kinds of caves, which can be used together:
+/// big open caverns wherever one noise field is high ("cheese" caves),
+/// and winding tunnels wherever two other noise fields are both near
+/// zero ("worm" caves).
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct CaveParams {
+    /// Rough size of cave features, in cells.
+    pub wavelength: f64,
+    /// Carve caverns wherever noise (roughly in [-1, 1]) is above this.
+    /// Set to 1 or more to disable caverns.
+    pub cavern_threshold: f64,
+    /// How close to zero both tunnel noise fields need to be to carve
+    /// a tunnel; bigger values make wider tunnels. Set to 0 to disable tunnels.
+    pub tunnel_width: f64,
+    /// Don't carve caves within this many cells of the surface of the land.
+    /// Zero lets caves break through the surface, making overhangs.
+    pub min_depth: f64,
+}
+
+impl CaveParams {
+    /// Don't carve any caves at all.
+    pub fn none() -> CaveParams {
+        CaveParams {
+            wavelength: 1.0,
+            cavern_threshold: 1.0,
+            tunnel_width: 0.0,
+            min_depth: 0.0,
+        }
+    }
+
+    pub fn is_valid(&self) -> bool {
+        self.wavelength > 0.0 && self.tunnel_width >= 0.0 && self.min_depth >= 0.0
+    }
+}
+
+impl Default for CaveParams {
+    fn default() -> CaveParams {
+        CaveParams {
+            wavelength: 24.0,
+            cavern_threshold: 0.6,
+            tunnel_width: 0.06,
+            min_depth: 0.0,
+        }
+    }
+}
+
+// How close ocean radius can be to the middle of a layer of cells,
+// as a proportion of block height.
+const MIN_OCEAN_DISTANCE_FROM_CELL_CENTER: f64 = 1e-6;
+
+/// Reasons why a `Spec` might not describe a globe that can be built.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum SpecError {
+    /// The named value, e.g. "block_height", needs to be greater than zero.
+    NotPositive(&'static str),
+    /// Ocean radius is below floor radius.
+    OceanBelowFloor,
+    /// Chunk resolution doesn't divide perfectly into root resolution.
+    ChunkResolutionDoesNotDivideRootResolution,
+    /// Root resolution in the y-direction isn't exactly twice
+    /// that in the x-direction.
+    RootResolutionNotTwiceAsTallAsWide,
+    /// Ocean radius is at the height of the middle of a layer of cells,
+    /// so whether each of those cells is water or air would depend on
+    /// rounding error.
+    OceanRadiusAtCellCenter,
+    /// `TerrainParams::is_valid` is false.
+    InvalidTerrain,
+    /// `CaveParams::is_valid` is false.
+    InvalidCaves,
+}
+
+impl fmt::Display for SpecError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use self::SpecError::*;
+        match *self {
+            NotPositive(name) => write!(f, "{} must be greater than zero", name),
+            OceanBelowFloor => write!(f, "ocean radius is below floor radius"),
+            ChunkResolutionDoesNotDivideRootResolution => {
+                write!(f, "chunk resolution doesn't divide evenly into root resolution")
+            }
+            RootResolutionNotTwiceAsTallAsWide => {
+                write!(f, "root resolution in y must be exactly twice that in x")
+            }
+            OceanRadiusAtCellCenter => {
+                write!(
+                    f,
+                    "ocean radius is in the middle of a layer of cells, which would z-fight"
+                )
+            }
+            InvalidTerrain => write!(f, "terrain parameters are invalid"),
+            InvalidCaves => write!(f, "cave parameters are invalid"),
+        }
+    }
+}
+
+impl error::Error for SpecError {
+    fn description(&self) -> &str {
+        "invalid globe spec"
+    }
+}
+
+/// Reasons why `Spec::load` or `Spec::from_json` might fail.
+#[derive(Debug)]
+pub enum SpecLoadError {
+    Io(io::Error),
+    Json(serde_json::Error),
+    /// The spec was read just fine, but isn't valid.
+    Invalid(SpecError),
+}
+
+impl fmt::Display for SpecLoadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            SpecLoadError::Io(ref err) => write!(f, "couldn't read globe spec: {}", err),
+            SpecLoadError::Json(ref err) => write!(f, "couldn't parse globe spec: {}", err),
+            SpecLoadError::Invalid(ref err) => write!(f, "invalid globe spec: {}", err),
+        }
+    }
+}
+
+impl error::Error for SpecLoadError {
+    fn description(&self) -> &str {
+        "couldn't load globe spec"
+    }
+
+    fn cause(&self) -> Option<&error::Error> {
+        match *self {
+            SpecLoadError::Io(ref err) => Some(err),
+            SpecLoadError::Json(ref err) => Some(err),
+            SpecLoadError::Invalid(ref err) => Some(err),
+        }
+    }
+}
+
+impl From<io::Error> for SpecLoadError {
+    fn from(err: io::Error) -> SpecLoadError {
+        SpecLoadError::Io(err)
+    }
+}
+
+impl From<serde_json::Error> for SpecLoadError {
+    fn from(err: serde_json::Error) -> SpecLoadError {
+        SpecLoadError::Json(err)
+    }
+}
+
+impl From<SpecError> for SpecLoadError {
+    fn from(err: SpecError) -> SpecLoadError {
+        SpecLoadError::Invalid(err)
+    }
+}
+
+/// Builds a `Spec`, making sure that it's valid.
+///
+/// Starts out the same as `Spec::new_example`; set whatever
+/// you want to be different, and then call `build`.
+#[derive(Clone, Copy, Debug)]
+pub struct SpecBuilder {
+    spec: Spec,
+}
+
+impl SpecBuilder {
+    pub fn new() -> SpecBuilder {
+        SpecBuilder { spec: Spec::new_example() }
+    }
+
+    pub fn seed(mut self, seed: u32) -> SpecBuilder {
+        self.spec.seed = seed;
+        self
+    }
+
+    pub fn floor_radius(mut self, floor_radius: f64) -> SpecBuilder {
+        self.spec.floor_radius = floor_radius;
+        self
+    }
+
+    pub fn ocean_radius(mut self, ocean_radius: f64) -> SpecBuilder {
+        self.spec.ocean_radius = ocean_radius;
+        self
+    }
+
+    pub fn block_height(mut self, block_height: f64) -> SpecBuilder {
+        self.spec.block_height = block_height;
+        self
+    }
+
+    pub fn root_resolution(mut self, root_resolution: [GridCoord; 2]) -> SpecBuilder {
+        self.spec.root_resolution = root_resolution;
+        self
+    }
+
+    pub fn chunk_resolution(mut self, chunk_resolution: [GridCoord; 3]) -> SpecBuilder {
+        self.spec.chunk_resolution = chunk_resolution;
+        self
+    }
+
+    pub fn terrain(mut self, terrain: TerrainParams) -> SpecBuilder {
+        self.spec.terrain = terrain;
+        self
+    }
+
+    pub fn caves(mut self, caves: CaveParams) -> SpecBuilder {
+        self.spec.caves = caves;
+        self
+    }
+
+    /// Returns the first problem found with the spec, if any.
+    pub fn build(self) -> Result<Spec, SpecError> {
+        self.spec.validate().map(|()| self.spec)
+    }
+}
+
+impl Default for SpecBuilder {
+    fn default() -> SpecBuilder {
+        SpecBuilder::new()
+    }
 }
 
 impl Spec {
+    /// A small globe that's quick to generate.
+    pub fn new_example() -> Spec {
+        Spec {
+            seed: 14,
+            floor_radius: 25.0,
+            ocean_radius: 66.6,
+            block_height: 0.65,
+            root_resolution: [64, 128],
+            // Chunks should probably be taller, but short chunks are a bit
+            // better for now in exposing bugs visually.
+            chunk_resolution: [16, 16, 4],
+            terrain: TerrainParams::default(),
+            caves: CaveParams::default(),
+        }
+    }
+
     pub fn new_earth_scale_example() -> Spec {
         let ocean_radius = 6_371_000.0;
         // TODO: actually more like 60_000 when we know how to:
@@ -49,10 +356,55 @@ impl Spec {
             // Chunks should probably be taller, but short chunks are a bit
             // better for now in exposing bugs visually.
             chunk_resolution: [16, 16, 4],
+            terrain: TerrainParams::default(),
+            caves: CaveParams::default(),
         }
     }
 
+    /// Read a spec from a JSON config file, and make sure it's valid.
+    ///
+    /// Terrain and cave parameters can be left out, to get
+    /// `TerrainParams::default()` and `CaveParams::default()`.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Spec, SpecLoadError> {
+        let file = File::open(path)?;
+        let spec: Spec = serde_json::from_reader(file)?;
+        spec.validate()?;
+        Ok(spec)
+    }
+
+    /// Like `load`, but from a string rather than a file.
+    pub fn from_json(json: &str) -> Result<Spec, SpecLoadError> {
+        let spec: Spec = serde_json::from_str(json)?;
+        spec.validate()?;
+        Ok(spec)
+    }
+
     pub fn is_valid(&self) -> bool {
+        self.validate().is_ok()
+    }
+
+    /// Returns the first reason found that this spec doesn't
+    /// describe a globe that can be built, if any.
+    pub fn validate(&self) -> Result<(), SpecError> {
+        let positive_values = [
+            ("floor_radius", self.floor_radius),
+            ("block_height", self.block_height),
+        ];
+        for &(name, value) in &positive_values {
+            if value.is_nan() || value <= 0.0 {
+                return Err(SpecError::NotPositive(name));
+            }
+        }
+        if self.root_resolution.iter().any(|&res| res <= 0) {
+            return Err(SpecError::NotPositive("root_resolution"));
+        }
+        if self.chunk_resolution.iter().any(|&res| res <= 0) {
+            return Err(SpecError::NotPositive("chunk_resolution"));
+        }
+        if self.ocean_radius.is_nan() || self.ocean_radius < self.floor_radius {
+            return Err(SpecError::OceanBelowFloor);
+        }
+
         // Chunk resolution needs to divide perfectly into root resolution.
         let cprs = self.chunks_per_root_side();
         let calculated_root_resolution = [
@@ -60,7 +412,7 @@ impl Spec {
             cprs[1] * self.chunk_resolution[1],
         ];
         if calculated_root_resolution != self.root_resolution {
-            return false;
+            return Err(SpecError::ChunkResolutionDoesNotDivideRootResolution);
         }
 
         // Root resolution needs to be exactly twice in the y-direction
@@ -68,10 +420,26 @@ impl Spec {
         // use cases for anything else, and it's extremely unclear how
         // a lot of scenarios should work otherwise.
         if self.root_resolution[1] != self.root_resolution[0] * 2 {
-            return false;
+            return Err(SpecError::RootResolutionNotTwiceAsTallAsWide);
         }
 
-        true
+        // `Gen` decides between water and air by comparing the height
+        // of each cell's center to the ocean radius.
+        let ocean_depth_in_cells = (self.ocean_radius - self.floor_radius) / self.block_height;
+        let distance_from_cell_center = (ocean_depth_in_cells.fract() - 0.5).abs();
+        if distance_from_cell_center < MIN_OCEAN_DISTANCE_FROM_CELL_CENTER {
+            return Err(SpecError::OceanRadiusAtCellCenter);
+        }
+
+        if !self.terrain.is_valid() {
+            return Err(SpecError::InvalidTerrain);
+        }
+
+        if !self.caves.is_valid() {
+            return Err(SpecError::InvalidCaves);
+        }
+
+        Ok(())
     }
 
     pub fn chunks_per_root_side(&self) -> [GridCoord; 2] {
@@ -93,6 +461,22 @@ impl Spec {
         super::project(column.root, pt_in_root_quad)
     }
 
+    /// Latitude and longitude of the center of `column`, in radians.
+    ///
+    /// Latitude goes from -π/2 at the south pole to π/2 at the north pole
+    /// (see `icosahedron::north_pole`), and longitude from -π in the west
+    /// to π in the east, with 0 at the prime meridian
+    /// (see `icosahedron::prime_meridian`).
+    pub fn latitude_longitude(&self, column: GridPoint2) -> (f64, f64) {
+        let unit_pt3 = self.cell_center_on_unit_sphere(column).coords;
+        let north = super::icosahedron::north_pole();
+        let prime_meridian = super::icosahedron::prime_meridian();
+        let east = north.cross(&prime_meridian);
+        let latitude = unit_pt3.dot(&north).max(-1.0).min(1.0).asin();
+        let longitude = unit_pt3.dot(&east).atan2(unit_pt3.dot(&prime_meridian));
+        (latitude, longitude)
+    }
+
     pub fn cell_center_center(&self, grid_point: GridPoint3) -> Pt3 {
         let radius = self.floor_radius + self.block_height * (grid_point.z as f64 + 0.5);
         radius * self.cell_center_on_unit_sphere(grid_point.rxy)
@@ -125,8 +509,64 @@ impl Spec {
         self.cell_bottom_vertex(grid_point, offset)
     }
 
+    /// Find the cell containing the given point, in the globe's
+    /// frame of reference.
+    ///
+    /// The cell is always expressed in the root that owns it,
+    /// so that every point in a cell gives exactly the same `GridPoint3`,
+    /// even for cells on the edges of roots, and the pentagons
+    /// at the corners of roots.
+    ///
+    /// Points exactly on the boundary between cells may end up in either.
+    /// Points below the globe's floor radius end up with a negative z-coordinate,
+    /// which isn't a valid position for a cell.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `pt` is at the very center of the globe.
+    pub fn cell_containing(&self, pt: Pt3) -> GridPoint3 {
+        let z = ((pt.coords.norm() - self.floor_radius) / self.block_height).floor() as GridCoord;
+        self.column_containing(pt.coords).with_z(z)
+    }
+
+    // Position of the column containing `direction` from the center
+    // of the globe, at `z == 0`, and in its owning root.
+    fn column_containing(&self, direction: Vec3) -> GridPoint3 {
+        // Cell vertices are laid out in each root quad before being projected
+        // onto the globe (see `cell_vertex_on_unit_sphere`), so finding the cell
+        // in the root quad is exact; there's no need to look at neighboring cells.
+        let (root, pt_in_root_quad) = super::unproject(direction);
+        let x = pt_in_root_quad[0] * self.root_resolution[0] as f64;
+        let y = pt_in_root_quad[1] * self.root_resolution[1] as f64;
+        let (x, y) = round_to_nearest_hexagon(x, y);
+        // Cells on the edges of roots straddle both roots, so a point in the quad
+        // should never round to a cell beyond its edge. Floating point error might
+        // take us there, though, and the nearest cell on the edge is just as good.
+        let x = x.max(0).min(self.root_resolution[0]);
+        let y = y.max(0).min(self.root_resolution[1]);
+        PosInOwningRoot::new(GridPoint3::new(root, x, y, 0), self.root_resolution).into()
+    }
+
     // TODO: test me.
     pub fn approx_cell_z_from_radius(&self, radius: f64) -> GridCoord {
         ((radius - self.floor_radius) / self.block_height) as GridCoord
     }
 }
+
+// Cell centers are at integer coordinates, and each cell's neighbors
+// are at offsets (±1, 0), (0, ±1), (1, -1) and (-1, 1) (see `Neighbors`),
+// which makes this an "axial" coordinate system for a hexagonal grid.
+// So find the nearest hexagon center by rounding in cube coordinates.
+fn round_to_nearest_hexagon(x: f64, y: f64) -> (GridCoord, GridCoord) {
+    let z = -x - y;
+    let (mut rx, mut ry, rz) = (x.round(), y.round(), z.round());
+    let (dx, dy, dz) = ((rx - x).abs(), (ry - y).abs(), (rz - z).abs());
+    // The rounded coordinates must still add up to zero, so fix up
+    // whichever one was rounded the furthest.
+    if dx > dy && dx > dz {
+        rx = -ry - rz;
+    } else if dy > dz {
+        ry = -rx - rz;
+    }
+    (rx as GridCoord, ry as GridCoord)
+}
@@ -1,4 +1,12 @@
+use std::collections::{HashMap, HashSet};
+use std::env;
+use std::path::PathBuf;
+
+use rand;
+use slog;
+
 use super::*;
+use grid::{GridPoint2, Neighbors};
 
 #[test]
 fn find_spawn_points() {
@@ -30,6 +38,245 @@ fn find_spawn_points() {
     assert!(successes < TRIALS - 5);
 }
 
+// Trivial world with dirt up to a fixed depth everywhere, and air above.
+struct FlatGen {
+    spec: Spec,
+    dirt_depth: GridCoord,
+}
+
+impl WorldGen for FlatGen {
+    fn land_height(&self, _column: GridPoint2) -> f64 {
+        self.spec.floor_radius + self.spec.block_height * self.dirt_depth as f64
+    }
+
+    fn cell_at(&self, grid_point: GridPoint3) -> chunk::Cell {
+        chunk::Cell {
+            material: if grid_point.z < self.dirt_depth {
+                MaterialId::DIRT
+            } else {
+                MaterialId::AIR
+            },
+            sky_light: 0,
+            block_light: 0,
+        }
+    }
+}
+
+fn flat_gen(spec: Spec) -> Box<FlatGen> {
+    Box::new(FlatGen {
+        spec: spec,
+        dirt_depth: 10,
+    })
+}
+
+// The example globe, but with flat land everywhere, so that
+// tests can know exactly where to find things.
+fn flat_globe() -> Globe {
+    let spec = Globe::new_example().spec();
+    Globe::new(spec, flat_gen(spec))
+}
+
+fn test_log() -> slog::Logger {
+    slog::Logger::root(slog::Discard, o!("pk_version" => env!("CARGO_PKG_VERSION")))
+}
+
+#[test]
+fn globe_with_custom_gen() {
+    let mut globe = flat_globe();
+    let column = GridPoint2::new(3.into(), 20, 30);
+    assert_eq!(
+        Some(column.with_z(9)),
+        globe.find_surface_dry_land(column, 3, 5)
+    );
+    assert_eq!(
+        column.with_z(10),
+        globe.find_lowest_cell_containing(column.with_z(0), MaterialId::AIR).unwrap()
+    );
+}
+
+fn example_chunk_origin(globe: &Globe) -> ChunkOrigin {
+    ChunkOrigin::new(
+        GridPoint3::new(1.into(), 16, 32, 40),
+        globe.spec().root_resolution,
+        globe.spec().chunk_resolution,
+    )
+}
+
+// Flip a cell in the middle of the chunk between air and dirt, and return its new material.
+fn flip_cell(globe: &mut Globe, pos: GridPoint3) -> MaterialId {
+    let pos_in_owning_root = PosInOwningRoot::new(pos, globe.spec().root_resolution);
+    let cell = globe.authoritative_cell_mut(pos_in_owning_root);
+    cell.material = if cell.material == MaterialId::AIR {
+        MaterialId::DIRT
+    } else {
+        MaterialId::AIR
+    };
+    cell.material
+}
+
+// Somewhere nobody else is using, even other test runs happening at the same time.
+fn unique_temp_path(name: &str) -> PathBuf {
+    env::temp_dir().join(format!("{}_{:08x}", name, rand::random::<u32>()))
+}
+
+#[test]
+fn modified_chunk_survives_unload_and_restart() {
+    use std::fs;
+
+    let dir = unique_temp_path("planetkit_test_modified_chunk_survives_unload");
+    let _ = fs::remove_dir_all(&dir);
+
+    let mut globe = Globe::new_example();
+    let chunk_resolution = globe.spec().chunk_resolution;
+    globe.set_chunk_store(Box::new(
+        RegionFileChunkStore::new(dir.clone(), chunk_resolution).unwrap(),
+    ));
+    let chunk_origin = example_chunk_origin(&globe);
+    let pos = chunk_origin.pos().with_x(20).with_y(35);
+    globe.ensure_chunk_present(chunk_origin).unwrap();
+    let new_material = flip_cell(&mut globe, pos);
+
+    // Unloading should save the chunk, and loading it again should get it back
+    // from the store rather than generating it fresh.
+    assert!(globe.unload_chunk(chunk_origin).unwrap());
+    assert!(globe.chunk_at(chunk_origin).is_none());
+    globe.ensure_chunk_present(chunk_origin).unwrap();
+    assert!(new_material == globe.maybe_non_authoritative_cell(pos).material);
+
+    // Modify it again, and save everything before dropping the whole globe.
+    let new_material = flip_cell(&mut globe, pos);
+    globe.save_all_changed_chunks().unwrap();
+    drop(globe);
+
+    let mut globe = Globe::new_example();
+    globe.set_chunk_store(Box::new(
+        RegionFileChunkStore::new(dir.clone(), chunk_resolution).unwrap(),
+    ));
+    globe.ensure_chunk_present(chunk_origin).unwrap();
+    assert!(new_material == globe.maybe_non_authoritative_cell(pos).material);
+
+    let _ = fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn generated_chunk_does_not_replace_saved_chunk() {
+    use std::fs;
+    use super::globe::generate_chunk;
+
+    let dir = unique_temp_path("planetkit_test_generated_chunk_does_not_replace_saved");
+    let _ = fs::remove_dir_all(&dir);
+
+    let mut globe = Globe::new_example();
+    let spec = globe.spec();
+    let chunk_store = RegionFileChunkStore::new(dir.clone(), spec.chunk_resolution).unwrap();
+    globe.set_chunk_store(Box::new(chunk_store));
+    let chunk_origin = example_chunk_origin(&globe);
+    let pos = chunk_origin.pos().with_x(20).with_y(35);
+
+    // Pretend the chunk was being generated in the background...
+    let generated_chunk = generate_chunk(chunk_origin, spec, globe.gen());
+    assert!(globe.maybe_non_authoritative_cell_if_ready(pos).is_err());
+
+    // ...while somebody else loaded it, changed it, and unloaded it.
+    globe.ensure_chunk_present(chunk_origin).unwrap();
+    let new_material = flip_cell(&mut globe, pos);
+    assert!(globe.unload_chunk(chunk_origin).unwrap());
+
+    // The saved changes should win.
+    globe.add_generated_chunk(generated_chunk, None).unwrap();
+    assert_eq!(
+        Ok(new_material),
+        globe.maybe_non_authoritative_cell_if_ready(pos).map(|cell| cell.material)
+    );
+
+    let _ = fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn chunk_being_saved_can_be_loaded_again() {
+    use std::fs;
+    use super::chunk_format::encode_chunk_delta;
+
+    let dir = unique_temp_path("planetkit_test_chunk_being_saved_can_be_loaded_again");
+    let _ = fs::remove_dir_all(&dir);
+
+    let mut globe = Globe::new_example();
+    let spec = globe.spec();
+    let chunk_store = RegionFileChunkStore::new(dir.clone(), spec.chunk_resolution).unwrap();
+    globe.set_chunk_store(Box::new(chunk_store));
+    let chunk_origin = example_chunk_origin(&globe);
+    let pos = chunk_origin.pos().with_x(20).with_y(35);
+    globe.ensure_chunk_present(chunk_origin).unwrap();
+    let new_material = flip_cell(&mut globe, pos);
+
+    // Load it again while its changes are still being encoded.
+    let chunk = globe.unload_chunk_to_save_later(chunk_origin);
+    assert!(globe.chunk_at(chunk_origin).is_none());
+    globe.ensure_chunk_present(chunk_origin).unwrap();
+    assert!(new_material == globe.maybe_non_authoritative_cell(pos).material);
+
+    // Finishing that save shouldn't touch the chunk that's loaded now.
+    let mut data = Vec::new();
+    encode_chunk_delta(&chunk, spec, globe.gen(), &mut data);
+    globe.finish_saving_chunk(chunk, &data).unwrap();
+    assert!(globe.chunk_at(chunk_origin).unwrap().has_unsaved_changes);
+
+    // Once it has finished being saved, it should come back from the store.
+    let chunk = globe.unload_chunk_to_save_later(chunk_origin);
+    let mut data = Vec::new();
+    encode_chunk_delta(&chunk, spec, globe.gen(), &mut data);
+    globe.finish_saving_chunk(chunk, &data).unwrap();
+    assert_eq!(Some(data), globe.load_saved_chunk_data(chunk_origin).unwrap());
+    globe.ensure_chunk_present(chunk_origin).unwrap();
+    assert!(new_material == globe.maybe_non_authoritative_cell(pos).material);
+
+    let _ = fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn modified_chunk_without_store_stays_loaded() {
+    let mut globe = Globe::new_example();
+    let chunk_origin = example_chunk_origin(&globe);
+    let pos = chunk_origin.pos().with_x(20).with_y(35);
+    globe.ensure_chunk_present(chunk_origin).unwrap();
+    flip_cell(&mut globe, pos);
+
+    assert!(!globe.unload_chunk(chunk_origin).unwrap());
+    assert!(globe.chunk_at(chunk_origin).is_some());
+}
+
+#[test]
+fn corrupt_saved_chunk_is_reported_rather_than_loaded() {
+    use std::io;
+    use super::globe::generate_chunk;
+
+    struct CorruptChunkStore;
+
+    impl ChunkStore for CorruptChunkStore {
+        fn load_chunk(&mut self, _origin: ChunkOrigin) -> io::Result<Option<Vec<u8>>> {
+            Ok(Some(vec![0xff; 3]))
+        }
+
+        fn save_chunk(&mut self, _origin: ChunkOrigin, _data: &[u8]) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    let mut globe = Globe::new_example();
+    let spec = globe.spec();
+    globe.set_chunk_store(Box::new(CorruptChunkStore));
+    let chunk_origin = example_chunk_origin(&globe);
+
+    let err = globe.ensure_chunk_present(chunk_origin).unwrap_err();
+    assert_eq!(io::ErrorKind::InvalidData, err.kind());
+    assert!(globe.chunk_at(chunk_origin).is_none());
+
+    // A chunk generated in the background is used instead.
+    let generated_chunk = generate_chunk(chunk_origin, spec, globe.gen());
+    assert!(globe.add_generated_chunk(generated_chunk, None).is_err());
+    assert!(globe.chunk_at(chunk_origin).is_some());
+}
+
 #[cfg(feature = "nightly")]
 pub mod benches {
     use test::Bencher;
@@ -86,8 +333,10 @@ pub mod benches {
             block_height: 0.02,
             root_resolution: ROOT_RESOLUTION,
             chunk_resolution: CHUNK_RESOLUTION,
+            terrain: TerrainParams::default(),
+            caves: CaveParams::default(),
         };
-        let globe = Globe::new(spec);
+        let globe = Globe::new(spec, Box::new(Gen::new(spec)));
         let spec = globe.spec();
         let globe_view = View::new(spec, &log);
         let mut vertex_data: Vec<Vertex> = Vec::new();
@@ -111,3 +360,1155 @@ pub mod benches {
         });
     }
 }
+
+// Sample a coarse grid of columns across every root.
+fn sample_columns(spec: Spec) -> Vec<GridPoint2> {
+    let mut columns = Vec::new();
+    for root in 0..5 {
+        for x in (0..spec.root_resolution[0]).filter(|x| x % 8 == 0) {
+            for y in (0..spec.root_resolution[1]).filter(|y| y % 8 == 0) {
+                columns.push(GridPoint2::new(root.into(), x, y));
+            }
+        }
+    }
+    columns
+}
+
+#[test]
+fn biomes_vary_across_globe() {
+    use std::collections::HashSet;
+
+    let spec = Globe::new_example().spec();
+    let gen = Gen::new(spec);
+    let biomes: HashSet<Biome> = sample_columns(spec)
+        .into_iter()
+        .map(|column| gen.biome(column))
+        .collect();
+    assert!(biomes.len() >= 4);
+}
+
+#[test]
+fn poles_are_colder_than_equator() {
+    let spec = Globe::new_example().spec();
+    let gen = Gen::new(spec);
+    let north_pole = GridPoint2::new(0.into(), 0, 0);
+    let equator = GridPoint2::new(0.into(), 32, 64);
+    let north = icosahedron::VERTICES[0];
+    let equator_pt3 = spec.cell_center_on_unit_sphere(equator);
+    let equator_dot_north =
+        equator_pt3.x * north[0] + equator_pt3.y * north[1] + equator_pt3.z * north[2];
+    assert!(equator_dot_north.abs() < 0.2);
+    assert!(gen.climate(north_pole).temperature < gen.climate(equator).temperature);
+}
+
+#[test]
+fn surface_material_depends_on_biome() {
+    // Caves could take a bite out of the surface.
+    let spec = Spec {
+        caves: CaveParams::none(),
+        ..Globe::new_example().spec()
+    };
+    let gen = Gen::new(spec);
+    for column in sample_columns(spec) {
+        let biome = gen.biome(column);
+        if biome == Biome::Ocean {
+            continue;
+        }
+        // Find the top solid cell.
+        let mut z = spec.approx_cell_z_from_radius(gen.land_height(column)) + 5;
+        loop {
+            let material = gen.cell_at(column.with_z(z)).material;
+            if material != MaterialId::AIR && material != MaterialId::WATER {
+                assert_eq!(biome.surface_material(), material);
+                break;
+            }
+            z -= 1;
+        }
+    }
+}
+
+// Count cells below the surface of the land that have been carved out,
+// and the total number of cells below the surface, over a sample of columns.
+fn count_cave_cells(gen: &Gen, spec: Spec) -> (usize, usize) {
+    let mut cave_cells = 0;
+    let mut underground_cells = 0;
+    for column in sample_columns(spec) {
+        let surface_z = spec.approx_cell_z_from_radius(gen.land_height(column));
+        for z in 0..surface_z {
+            underground_cells += 1;
+            if gen.cell_at(column.with_z(z)).material == MaterialId::AIR {
+                cave_cells += 1;
+            }
+        }
+    }
+    (cave_cells, underground_cells)
+}
+
+#[test]
+fn caves_carve_underground_voids() {
+    let spec = Globe::new_example().spec();
+    let (cave_cells, underground_cells) = count_cave_cells(&Gen::new(spec), spec);
+    assert!(cave_cells > 0);
+    // But most of the ground should still be there!
+    assert!(cave_cells < underground_cells / 4);
+
+    let spec = Spec {
+        caves: CaveParams::none(),
+        ..spec
+    };
+    assert_eq!((0, underground_cells), count_cave_cells(&Gen::new(spec), spec));
+}
+
+#[test]
+fn caves_are_deterministic() {
+    let spec = Globe::new_example().spec();
+    let gen_a = Gen::new(spec);
+    let gen_b = Gen::new(spec);
+    for column in sample_columns(spec) {
+        for z in 0..100 {
+            let pos = column.with_z(z);
+            assert_eq!(gen_a.cell_at(pos).material, gen_b.cell_at(pos).material);
+        }
+    }
+}
+
+#[test]
+fn rock_layers_get_deeper() {
+    let spec = Spec {
+        caves: CaveParams::none(),
+        ..Globe::new_example().spec()
+    };
+    let gen = Gen::new(spec);
+    let materials_by_depth = |column: GridPoint2| -> Vec<MaterialId> {
+        let surface_z = spec.approx_cell_z_from_radius(gen.land_height(column));
+        (0..surface_z)
+            .rev()
+            .map(|z| gen.cell_at(column.with_z(z)).material)
+            .collect()
+    };
+    for column in sample_columns(spec) {
+        let materials = materials_by_depth(column);
+        // Bedrock at the very bottom.
+        assert_eq!(&[MaterialId::BEDROCK, MaterialId::BEDROCK], &materials[materials.len() - 2..]);
+        // Slate always comes below stone.
+        let last_stone = materials.iter().rposition(|m| *m == MaterialId::STONE);
+        let first_slate = materials.iter().position(|m| *m == MaterialId::SLATE);
+        if let (Some(last_stone), Some(first_slate)) = (last_stone, first_slate) {
+            assert!(last_stone < first_slate);
+        }
+    }
+}
+
+#[test]
+fn ores_are_scattered_through_rock() {
+    use std::collections::HashMap;
+
+    let spec = Globe::new_example().spec();
+    let gen = Gen::new(spec);
+    let mut counts: HashMap<MaterialId, usize> = HashMap::new();
+    let mut underground_cells = 0;
+    for column in sample_columns(spec) {
+        let surface_z = spec.approx_cell_z_from_radius(gen.land_height(column));
+        for z in 0..surface_z {
+            underground_cells += 1;
+            *counts.entry(gen.cell_at(column.with_z(z)).material).or_insert(0) += 1;
+        }
+    }
+    for ore in &[MaterialId::COAL, MaterialId::IRON_ORE, MaterialId::GOLD_ORE] {
+        let count = counts.get(ore).cloned().unwrap_or(0);
+        assert!(count > 0);
+        // Ore should be a nice surprise, not the norm.
+        assert!(count < underground_cells / 20);
+    }
+}
+
+#[test]
+fn edit_syncs_shared_cells_and_views() {
+    use super::globe::GlobeGuts;
+
+    let mut globe = Globe::new_example();
+    let spec = globe.spec();
+    // A cell on the edge between two chunks, owned by the first.
+    let pos = GridPoint3::new(1.into(), 16, 35, 41);
+    let pos_in_owning_root = PosInOwningRoot::new(pos, spec.root_resolution);
+    let owning_chunk_origin = globe.origin_of_chunk_owning(pos_in_owning_root);
+    let neighbor_chunk_origin = ChunkOrigin::new(
+        GridPoint3::new(1.into(), 0, 32, 40),
+        spec.root_resolution,
+        spec.chunk_resolution,
+    );
+    assert!(owning_chunk_origin != neighbor_chunk_origin);
+    globe.ensure_chunk_present(owning_chunk_origin).unwrap();
+    globe.ensure_chunk_present(neighbor_chunk_origin).unwrap();
+    for chunk in globe.chunks_mut().values_mut() {
+        chunk.mark_view_as_clean();
+    }
+
+    let new_material = if globe.authoritative_cell(pos_in_owning_root).material == MaterialId::AIR {
+        MaterialId::DIRT
+    } else {
+        MaterialId::AIR
+    };
+    {
+        let mut edit = globe.edit();
+        edit.set_material(pos, new_material).unwrap();
+        // Edits to chunks that aren't loaded are refused.
+        let unloaded_pos = GridPoint3::new(3.into(), 20, 35, 41);
+        assert!(edit.set_material(unloaded_pos, new_material).is_err());
+        edit.commit();
+    }
+
+    // Both chunks should see the change, and need their views rebuilt.
+    for chunk_origin in &[owning_chunk_origin, neighbor_chunk_origin] {
+        let chunk = globe.chunk_at(*chunk_origin).unwrap();
+        assert_eq!(new_material, chunk.cell(pos).material);
+        assert!(chunk.is_view_dirty);
+    }
+    assert!(globe.chunk_at(owning_chunk_origin).unwrap().has_unsaved_changes);
+    assert!(!globe.chunk_at(neighbor_chunk_origin).unwrap().has_unsaved_changes);
+}
+
+#[test]
+fn undo_and_redo_edits() {
+    use super::globe::GlobeGuts;
+
+    let mut globe = Globe::new_example();
+    let spec = globe.spec();
+    let mut journal = EditJournal::new(10);
+    journal.enable_change_feed();
+    globe.set_edit_journal(journal);
+
+    // A cell on the edge between two chunks, owned by the first.
+    let pos = GridPoint3::new(1.into(), 16, 35, 41);
+    let pos_in_owning_root = PosInOwningRoot::new(pos, spec.root_resolution);
+    let owning_chunk_origin = globe.origin_of_chunk_owning(pos_in_owning_root);
+    let neighbor_chunk_origin = ChunkOrigin::new(
+        GridPoint3::new(1.into(), 0, 32, 40),
+        spec.root_resolution,
+        spec.chunk_resolution,
+    );
+    globe.ensure_chunk_present(owning_chunk_origin).unwrap();
+    globe.ensure_chunk_present(neighbor_chunk_origin).unwrap();
+    let old_material = globe.authoritative_cell(pos_in_owning_root).material;
+    let new_material = if old_material == MaterialId::AIR {
+        MaterialId::DIRT
+    } else {
+        MaterialId::AIR
+    };
+    let materials_seen = |globe: &Globe| -> Vec<MaterialId> {
+        [owning_chunk_origin, neighbor_chunk_origin]
+            .iter()
+            .map(|chunk_origin| globe.chunk_at(*chunk_origin).unwrap().cell(pos).material)
+            .collect()
+    };
+
+    // Nothing to undo yet.
+    assert_eq!(Ok(false), globe.undo());
+
+    globe.edit().set_material(pos, new_material).unwrap();
+    assert_eq!(vec![new_material, new_material], materials_seen(&globe));
+
+    // Undoing should restore the cell in both chunks, and dirty their views.
+    for chunk in globe.chunks_mut().values_mut() {
+        chunk.mark_view_as_clean();
+    }
+    assert_eq!(Ok(true), globe.undo());
+    assert_eq!(vec![old_material, old_material], materials_seen(&globe));
+    assert!(globe.chunk_at(owning_chunk_origin).unwrap().is_view_dirty);
+    assert!(globe.chunk_at(neighbor_chunk_origin).unwrap().is_view_dirty);
+    assert_eq!(Ok(false), globe.undo());
+
+    assert_eq!(Ok(true), globe.redo());
+    assert_eq!(vec![new_material, new_material], materials_seen(&globe));
+    assert_eq!(Ok(false), globe.redo());
+
+    // The feed should have seen every change, and replaying it
+    // on another globe should leave it the same as this one.
+    let change_feed = globe.edit_journal_mut().unwrap().take_change_feed();
+    assert_eq!(3, change_feed.len());
+    assert_eq!(old_material, change_feed[1].changes[0].new.material);
+    let mut other_globe = Globe::new_example();
+    other_globe.ensure_chunk_present(owning_chunk_origin).unwrap();
+    other_globe.ensure_chunk_present(neighbor_chunk_origin).unwrap();
+    for transaction in &change_feed {
+        other_globe.apply_transaction(transaction).unwrap();
+    }
+    assert_eq!(
+        new_material,
+        other_globe.authoritative_cell(pos_in_owning_root).material
+    );
+}
+
+#[test]
+fn edits_that_change_nothing_are_not_recorded() {
+    let mut globe = Globe::new_example();
+    globe.set_edit_journal(EditJournal::new(10));
+    let chunk_origin = example_chunk_origin(&globe);
+    globe.ensure_chunk_present(chunk_origin).unwrap();
+    let pos = chunk_origin.pos().with_x(20).with_y(35);
+    let material = globe.maybe_non_authoritative_cell(pos).material;
+    globe.edit().set_material(pos, material).unwrap();
+    assert!(!globe.edit_journal().unwrap().can_undo());
+}
+
+// Every column in every root, including those on the edges of roots,
+// where one cell has several equivalent positions, and the pentagons
+// at the corners of roots.
+fn all_columns(spec: &Spec) -> Vec<GridPoint3> {
+    let mut columns = Vec::new();
+    for root_index in 0..5 {
+        for x in 0..(spec.root_resolution[0] + 1) {
+            for y in 0..(spec.root_resolution[1] + 1) {
+                columns.push(GridPoint3::new(root_index.into(), x, y, 0));
+            }
+        }
+    }
+    columns
+}
+
+#[test]
+fn cell_containing_cell_centers() {
+    let spec = Globe::new_example().spec();
+    for column in all_columns(&spec) {
+        for &z in &[0, 10, 64] {
+            let pos = column.with_z(z);
+            let pos_in_owning_root: GridPoint3 =
+                PosInOwningRoot::new(pos, spec.root_resolution).into();
+            assert_eq!(
+                pos_in_owning_root,
+                spec.cell_containing(spec.cell_center_center(pos))
+            );
+        }
+    }
+}
+
+#[test]
+fn cell_containing_points_near_cell_vertices() {
+    use grid::cell_shape::DIR_OFFSETS;
+
+    let spec = Globe::new_example().spec();
+    // Leave a bit of room for floating point error.
+    let fraction_of_way_to_vertex = 0.95;
+    for column in all_columns(&spec) {
+        let pos = column.with_z(10);
+        let pos_in_owning_root: GridPoint3 =
+            PosInOwningRoot::new(pos, spec.root_resolution).into();
+        let center = spec.cell_center_center(pos);
+        let radius = center.coords.norm();
+        // Vertices are every second direction.
+        for vertex_index in 0..6 {
+            let offset = DIR_OFFSETS[vertex_index * 2 + 1];
+            // Parts of cells beyond the edge of this root are
+            // covered when we visit the same cell in the next root.
+            let vertex_x = pos.x * 6 + offset[0];
+            let vertex_y = pos.y * 6 + offset[1];
+            if vertex_x < 0 || vertex_x > spec.root_resolution[0] * 6 || vertex_y < 0 ||
+                vertex_y > spec.root_resolution[1] * 6
+            {
+                continue;
+            }
+            let vertex = spec.cell_vertex_on_unit_sphere(pos, offset) * radius;
+            let pt = center + (vertex - center) * fraction_of_way_to_vertex;
+            assert_eq!(pos_in_owning_root, spec.cell_containing(pt));
+        }
+    }
+}
+
+#[test]
+fn cell_containing_points_above_and_below() {
+    let spec = Globe::new_example().spec();
+    let pos = GridPoint3::new(2.into(), 20, 35, 10);
+    let center = spec.cell_center_center(pos);
+    let up = center.coords.normalize();
+    let nearly_half_block = spec.block_height * 0.49;
+    assert_eq!(pos, spec.cell_containing(center + up * nearly_half_block));
+    assert_eq!(pos, spec.cell_containing(center - up * nearly_half_block));
+    assert_eq!(
+        pos.with_z(11),
+        spec.cell_containing(center + up * spec.block_height)
+    );
+    // Below the floor of the globe isn't a real cell,
+    // but we still need to know that that's where we are.
+    assert_eq!(
+        pos.with_z(-1),
+        spec.cell_containing(spec.cell_center_center(pos.with_z(0)) - up * spec.block_height)
+    );
+}
+
+#[test]
+fn raycast_down_to_surface() {
+    let mut globe = Globe::new_example();
+    let spec = globe.spec();
+    let column = example_chunk_origin(&globe).pos().with_x(20).with_y(35);
+    let top_z = 100;
+    for z in 0..(top_z + 1) {
+        let chunk_origin = globe.origin_of_chunk_in_same_root_containing(column.with_z(z));
+        globe.ensure_chunk_present(chunk_origin).unwrap();
+    }
+    let is_solid = |cell: &chunk::Cell| cell.material != MaterialId::AIR;
+    let surface_z = (0..(top_z + 1))
+        .rev()
+        .find(|&z| is_solid(globe.maybe_non_authoritative_cell(column.with_z(z))))
+        .expect("Column should have something in it");
+    let surface_pos = column.with_z(surface_z);
+
+    // Straight down from high in the sky, we should land on top of the surface.
+    let origin = spec.cell_center_center(column.with_z(top_z));
+    let direction = -origin.coords;
+    let hit = globe
+        .raycast(origin, direction, 1000.0, &is_solid)
+        .unwrap()
+        .expect("Should have hit the surface");
+    assert_eq!(surface_pos, hit.pos);
+    assert_eq!(Some(CellFace::Top), hit.entry_face);
+    assert_eq!(Some(surface_pos.with_z(surface_z + 1)), hit.previous_pos);
+    let expected_distance = origin.coords.norm() -
+        (spec.floor_radius + spec.block_height * (surface_z + 1) as f64);
+    assert!((hit.distance - expected_distance).abs() < 0.001);
+
+    // Stopping short of the surface should find nothing.
+    assert_eq!(
+        Ok(None),
+        globe.raycast(origin, direction, expected_distance - 0.1, &is_solid)
+    );
+
+    // Starting inside the surface should hit it straight away.
+    let origin = spec.cell_center_center(surface_pos);
+    let hit = globe
+        .raycast(origin, direction, 1000.0, &is_solid)
+        .unwrap()
+        .expect("Should have hit the surface");
+    assert_eq!(surface_pos, hit.pos);
+    assert_eq!(0.0, hit.distance);
+    assert_eq!(None, hit.entry_face);
+}
+
+#[test]
+fn raycast_visits_every_cell_along_the_way() {
+    let mut globe = Globe::new_example();
+    let spec = globe.spec();
+    let column = example_chunk_origin(&globe).pos().with_x(20).with_y(35);
+    for x in 20..31 {
+        for y in 35..41 {
+            for z in 70..81 {
+                let pos = column.with_x(x).with_y(y).with_z(z);
+                let chunk_origin = globe.origin_of_chunk_in_same_root_containing(pos);
+                globe.ensure_chunk_present(chunk_origin).unwrap();
+            }
+        }
+    }
+
+    // Cut diagonally through lots of cells, and make sure
+    // each one we stop at is right next to the one before.
+    let origin = spec.cell_center_center(column.with_z(80));
+    let end = spec.cell_center_center(column.with_x(30).with_y(40).with_z(70));
+    let direction = end - origin;
+    let max_distance = direction.norm();
+    let mut pos = spec.cell_containing(origin);
+    for cells_to_pass in 1.. {
+        let mut cells_passed = 0;
+        let hit = globe
+            .raycast(origin, direction, max_distance, |_| {
+                cells_passed += 1;
+                cells_passed > cells_to_pass
+            })
+            .unwrap();
+        let hit = match hit {
+            Some(hit) => hit,
+            None => break,
+        };
+        assert_eq!(Some(pos), hit.previous_pos);
+        let root_resolution = spec.root_resolution;
+        let is_neighbor = Neighbors::new(hit.pos, root_resolution).any(|neighbor| {
+            PosInOwningRoot::new(neighbor, root_resolution) ==
+                PosInOwningRoot::new(pos, root_resolution)
+        });
+        assert!(is_neighbor, "{:?} isn't next to {:?}", hit.pos, pos);
+        pos = hit.pos;
+    }
+    assert_eq!(spec.cell_containing(end), pos);
+}
+
+#[test]
+fn raycast_into_unloaded_chunk() {
+    let globe = Globe::new_example();
+    let spec = globe.spec();
+    let pos = example_chunk_origin(&globe).pos().with_x(20).with_y(35);
+    let result = globe.raycast(spec.cell_center_center(pos), Vec3::x(), 1.0, |_| true);
+    assert_eq!(
+        Err(ChunkNotReady {
+            chunk_origin: globe.origin_of_chunk_in_same_root_containing(pos),
+        }),
+        result
+    );
+}
+
+#[test]
+fn flood_fill_finds_enclosed_pockets() {
+    let mut globe = flat_globe();
+    let spec = globe.spec();
+    let is_air = |cell: &chunk::Cell| cell.material == MaterialId::AIR;
+    let column = GridPoint3::new(1.into(), 20, 35, 0);
+    let other_column = column.with_x(30);
+
+    // Dig out two separate pockets of air, entirely underground.
+    let pocket = vec![
+        column.with_z(3),
+        column.with_z(4),
+        column.with_z(5),
+        column.with_x(21).with_z(4),
+    ];
+    let other_pocket = vec![other_column.with_z(5)];
+    for pos in pocket.iter().chain(other_pocket.iter()) {
+        let chunk_origin = globe.origin_of_chunk_in_same_root_containing(*pos);
+        globe.ensure_chunk_present(chunk_origin).unwrap();
+        globe.edit().set_material(*pos, MaterialId::AIR).unwrap();
+    }
+
+    let region = globe.flood_fill(column.with_z(4), 100, &is_air).unwrap();
+    assert!(!region.hit_limit);
+    let expected_cells: HashSet<PosInOwningRoot> = pocket
+        .iter()
+        .map(|pos| PosInOwningRoot::new(*pos, spec.root_resolution))
+        .collect();
+    assert_eq!(expected_cells, region.cells);
+    // The pocket straddles two layers of chunks.
+    assert_eq!(2, region.chunks.len());
+
+    // Starting in dirt finds nothing.
+    let region = globe.flood_fill(column.with_z(2), 100, &is_air).unwrap();
+    assert!(region.cells.is_empty());
+    assert!(!region.hit_limit);
+
+    let seeds = [
+        column.with_z(3),
+        other_column.with_z(5),
+        column.with_z(5),
+        column.with_z(1),
+    ];
+    let regions = globe.connected_regions(&seeds, 100, &is_air).unwrap();
+    assert_eq!(2, regions.len());
+    assert_eq!(4, regions[0].cells.len());
+    assert_eq!(1, regions[1].cells.len());
+
+    // Dig a shaft up to the sky, and the pocket is no longer enclosed.
+    for z in 6..10 {
+        let chunk_origin = globe.origin_of_chunk_in_same_root_containing(column.with_z(z));
+        globe.ensure_chunk_present(chunk_origin).unwrap();
+        globe.edit().set_material(column.with_z(z), MaterialId::AIR).unwrap();
+    }
+    let region = globe.flood_fill(column.with_z(4), 100, &is_air).unwrap();
+    assert!(region.hit_limit);
+    assert_eq!(100, region.cells.len());
+}
+
+#[test]
+fn water_flows_into_holes_and_settles() {
+    let mut globe = flat_globe();
+    let spec = globe.spec();
+    let column = GridPoint3::new(1.into(), 20, 35, 0);
+    let hole_column = column.with_x(21);
+    for z in 0..20 {
+        let chunk_origin = globe.origin_of_chunk_in_same_root_containing(column.with_z(z));
+        globe.ensure_chunk_present(chunk_origin).unwrap();
+    }
+
+    // Nothing is taking unsettled cells yet, so they shouldn't pile up.
+    globe.edit().set_material(column.with_z(15), MaterialId::DIRT).unwrap();
+    globe.edit().set_material(column.with_z(15), MaterialId::AIR).unwrap();
+    assert!(globe.take_unsettled_cells().is_empty());
+    globe.track_unsettled_cells(true);
+
+    // Stack up a column of water on the ground, and dig a hole next to it.
+    {
+        let mut edit = globe.edit();
+        for z in 10..14 {
+            edit.set_material(column.with_z(z), MaterialId::WATER).unwrap();
+        }
+        for z in 8..10 {
+            edit.set_material(hole_column.with_z(z), MaterialId::AIR).unwrap();
+        }
+    }
+    // The air just above the water we added was unsettled too.
+    let unsettled_cells = globe.take_unsettled_cells();
+    let above_water = PosInOwningRoot::new(column.with_z(14), spec.root_resolution);
+    assert!(unsettled_cells.contains(&above_water));
+    for pos in unsettled_cells {
+        globe.mark_cell_unsettled(pos);
+    }
+
+    let log = test_log();
+    let water_flow_system = WaterFlowSystem::new(&log, 1000);
+    let mut ticks = 0;
+    while water_flow_system.flow_water(&mut globe) > 0 {
+        ticks += 1;
+        assert!(ticks < 100, "Water should have settled by now");
+    }
+    // Make sure it stays settled.
+    assert_eq!(0, water_flow_system.flow_water(&mut globe));
+    assert!(globe.take_unsettled_cells().is_empty());
+
+    // The hole should be full, and we shouldn't have lost or gained any water.
+    for z in 8..10 {
+        assert_eq!(
+            MaterialId::WATER,
+            globe.maybe_non_authoritative_cell(hole_column.with_z(z)).material
+        );
+    }
+    let columns: Vec<GridPoint3> = Neighbors::new(column, spec.root_resolution)
+        .chain(Neighbors::new(hole_column, spec.root_resolution))
+        .filter(|pos| pos.z == 0)
+        .collect();
+    let mut water_cells = HashSet::new();
+    for pos in columns {
+        for z in 0..20 {
+            let pos = pos.with_z(z);
+            if globe.maybe_non_authoritative_cell(pos).material == MaterialId::WATER {
+                water_cells.insert(PosInOwningRoot::new(pos, spec.root_resolution));
+            }
+        }
+    }
+    assert_eq!(4, water_cells.len());
+}
+
+#[test]
+fn water_waits_for_chunks_to_load() {
+    let mut globe = flat_globe();
+    let spec = globe.spec();
+    // Just above the ground, but at the bottom of its chunk.
+    let pos = GridPoint3::new(1.into(), 20, 35, 12);
+    let below = pos.with_z(11);
+    let chunk_origin = globe.origin_of_chunk_in_same_root_containing(pos);
+    globe.ensure_chunk_present(chunk_origin).unwrap();
+    globe.track_unsettled_cells(true);
+    globe.edit().set_material(pos, MaterialId::WATER).unwrap();
+
+    // It can't tell whether it should fall yet.
+    let log = test_log();
+    let water_flow_system = WaterFlowSystem::new(&log, 1000);
+    assert_eq!(0, water_flow_system.flow_water(&mut globe));
+    assert_eq!(0, water_flow_system.flow_water(&mut globe));
+
+    // Once the chunk below it arrives, it should.
+    let chunk_origin = globe.origin_of_chunk_in_same_root_containing(below);
+    globe.ensure_chunk_present(chunk_origin).unwrap();
+    assert_eq!(1, water_flow_system.flow_water(&mut globe));
+    assert_eq!(MaterialId::WATER, globe.maybe_non_authoritative_cell(below).material);
+    assert_eq!(
+        MaterialId::AIR,
+        globe
+            .authoritative_cell(PosInOwningRoot::new(pos, spec.root_resolution))
+            .material
+    );
+}
+
+#[test]
+fn light_shines_into_caves_and_across_chunks() {
+    use std::sync::Arc;
+
+    let spec = Globe::new_example().spec();
+    let mut materials = MaterialRegistry::new();
+    let glowstone = materials.register(MaterialDef {
+        name: "glowstone".to_string(),
+        is_solid: true,
+        is_liquid: false,
+        is_transparent: false,
+        color: [1.0, 0.9, 0.6],
+        is_mineable: true,
+        light_emitted: 10,
+    });
+    let mut globe = Globe::new_with_materials(spec, flat_gen(spec), Arc::new(materials));
+    // On the edge between two chunks.
+    let column = GridPoint3::new(1.into(), 16, 35, 0);
+    // Chunk in the next column over that shares cells with the one containing `pos`.
+    let other_chunk_origin = |pos: GridPoint3| {
+        ChunkOrigin::new(
+            GridPoint3::new(1.into(), 0, 32, pos.z - pos.z % spec.chunk_resolution[2]),
+            spec.root_resolution,
+            spec.chunk_resolution,
+        )
+    };
+    for z in 0..16 {
+        let pos = column.with_z(z);
+        let chunk_origin = globe.origin_of_chunk_in_same_root_containing(pos);
+        globe.ensure_chunk_present(chunk_origin).unwrap();
+        globe.ensure_chunk_present(other_chunk_origin(pos)).unwrap();
+    }
+    let light_at = |globe: &Globe, pos: GridPoint3| {
+        let pos_in_owning_root = PosInOwningRoot::new(pos, spec.root_resolution);
+        let cell = *globe.authoritative_cell(pos_in_owning_root);
+        // The other chunk's copy should always agree.
+        let other_chunk = globe.chunk_at(other_chunk_origin(pos)).unwrap();
+        assert_eq!(cell, *other_chunk.cell(pos));
+        (cell.sky_light, cell.block_light)
+    };
+
+    // Sunlight reaches all the way down to the ground, but no further.
+    assert_eq!((MAX_LIGHT, 0), light_at(&globe, column.with_z(15)));
+    assert_eq!((MAX_LIGHT, 0), light_at(&globe, column.with_z(10)));
+    assert_eq!((0, 0), light_at(&globe, column.with_z(9)));
+
+    // Dig out a cave, entirely underground; it should be dark.
+    let cave = vec![
+        column.with_z(5),
+        column.with_z(6),
+        column.with_z(7),
+        column.with_z(8),
+        column.with_x(15).with_z(6),
+    ];
+    {
+        let mut edit = globe.edit();
+        for pos in &cave {
+            edit.set_material(*pos, MaterialId::AIR).unwrap();
+        }
+    }
+    for pos in &cave {
+        assert_eq!((0, 0), light_at(&globe, *pos));
+    }
+
+    // Open it up to the sky, and sunlight should shine straight down into it,
+    // and then spread out, getting dimmer.
+    globe.edit().set_material(column.with_z(9), MaterialId::AIR).unwrap();
+    assert_eq!((MAX_LIGHT, 0), light_at(&globe, column.with_z(5)));
+    assert_eq!((MAX_LIGHT - 1, 0), light_at(&globe, column.with_x(15).with_z(6)));
+
+    // Cover it up again, and it should go dark again.
+    globe.edit().set_material(column.with_z(9), MaterialId::DIRT).unwrap();
+    for pos in &cave {
+        assert_eq!((0, 0), light_at(&globe, *pos));
+    }
+
+    // Light it up from within.
+    globe.edit().set_material(column.with_z(5), glowstone).unwrap();
+    assert_eq!((0, 10), light_at(&globe, column.with_z(5)));
+    assert_eq!((0, 9), light_at(&globe, column.with_z(6)));
+    assert_eq!((0, 8), light_at(&globe, column.with_x(15).with_z(6)));
+    assert_eq!((0, 7), light_at(&globe, column.with_z(8)));
+    assert_eq!((0, 0), light_at(&globe, column.with_z(9)));
+
+    // And take it away.
+    globe.edit().set_material(column.with_z(5), MaterialId::AIR).unwrap();
+    for pos in &cave {
+        assert_eq!((0, 0), light_at(&globe, *pos));
+    }
+}
+
+#[test]
+fn chunks_lit_as_they_load_match_relighting_from_scratch() {
+    let mut globe = Globe::new_example();
+    let spec = globe.spec();
+    // A block of chunks around the surface, loaded top-down in some columns
+    // and bottom-up in others, so that light has to be fixed up both ways.
+    let column = GridPoint2::new(1.into(), 16, 48);
+    let surface_z = spec.approx_cell_z_from_radius(globe.gen().land_height(column));
+    let surface_layer = surface_z / spec.chunk_resolution[2];
+    let mut chunk_origins: Vec<ChunkOrigin> = Vec::new();
+    for &(x, y) in &[(0, 32), (16, 32), (0, 48), (16, 48)] {
+        let mut zs: Vec<i64> = ((surface_layer - 3)..(surface_layer + 4))
+            .map(|layer| layer * spec.chunk_resolution[2])
+            .collect();
+        if x == y % 32 {
+            zs.reverse();
+        }
+        for z in zs {
+            chunk_origins.push(ChunkOrigin::new(
+                GridPoint3::new(1.into(), x, y, z),
+                spec.root_resolution,
+                spec.chunk_resolution,
+            ));
+        }
+    }
+    let mut owned_cells: HashSet<PosInOwningRoot> = HashSet::new();
+    for chunk_origin in &chunk_origins {
+        globe.ensure_chunk_present(*chunk_origin).unwrap();
+        let origin = *chunk_origin.pos();
+        for z in origin.z..(origin.z + spec.chunk_resolution[2]) {
+            for y in origin.y..(origin.y + spec.chunk_resolution[1] + 1) {
+                for x in origin.x..(origin.x + spec.chunk_resolution[0] + 1) {
+                    let pos = GridPoint3::new(origin.root, x, y, z);
+                    let pos = PosInOwningRoot::new(pos, spec.root_resolution);
+                    if globe.origin_of_chunk_owning(pos) == *chunk_origin {
+                        owned_cells.insert(pos);
+                    }
+                }
+            }
+        }
+    }
+    let light_of = |globe: &Globe| -> HashMap<PosInOwningRoot, (u8, u8)> {
+        owned_cells
+            .iter()
+            .map(|&pos| {
+                let cell = globe.authoritative_cell(pos);
+                (pos, (cell.sky_light, cell.block_light))
+            })
+            .collect()
+    };
+
+    let lit_as_loaded = light_of(&globe);
+    // Make sure there's something to see: some cells lit by the sky, and some not.
+    assert!(lit_as_loaded.values().any(|&(sky, _)| sky == MAX_LIGHT));
+    assert!(lit_as_loaded.values().any(|&(sky, _)| sky == 0));
+
+    globe.update_light(owned_cells.iter().cloned());
+    assert!(lit_as_loaded == light_of(&globe));
+}
+
+#[test]
+fn lod_geometry_wraps_globe_just_below_surface() {
+    use super::lod_view::make_lod_geometry;
+
+    let globe = Globe::new_example();
+    let spec = globe.spec();
+    let mut vertex_data = Vec::new();
+    let mut index_data = Vec::new();
+    make_lod_geometry(&globe, 4, &mut vertex_data, &mut index_data);
+
+    // Each face of the icosahedron is split into 16 triangles.
+    assert_eq!(20 * 15, vertex_data.len());
+    assert_eq!(20 * 16 * 3, index_data.len());
+
+    let positions: Vec<Vec3> = vertex_data
+        .iter()
+        .map(|vertex| {
+            Vec3::new(
+                vertex.a_pos[0] as f64,
+                vertex.a_pos[1] as f64,
+                vertex.a_pos[2] as f64,
+            )
+        })
+        .collect();
+    for position in &positions {
+        let column = spec.cell_containing(Pt3::from_coordinates(*position)).rxy;
+        let surface_radius = globe.gen().land_height(column).max(spec.ocean_radius);
+        let radius = position.norm();
+        assert!(radius < surface_radius);
+        assert!(radius > surface_radius - spec.block_height * 2.0);
+    }
+
+    // Every triangle should face outwards, so it isn't culled.
+    for triangle in index_data.chunks(3) {
+        let a = positions[triangle[0] as usize];
+        let b = positions[triangle[1] as usize];
+        let c = positions[triangle[2] as usize];
+        assert!((b - a).cross(&(c - a)).dot(&(a + b + c)) > 0.0);
+    }
+}
+
+#[test]
+fn lod_view_is_hidden_when_camera_is_close() {
+    use na;
+    use specs;
+    use specs::Join;
+    use render::Visual;
+    use camera::DefaultCamera;
+    use Spatial;
+
+    let mut world = specs::World::new();
+    world.register::<Globe>();
+    world.register::<Spatial>();
+    world.register::<Visual>();
+    world.register::<LodView>();
+
+    let globe = Globe::new_example();
+    let ocean_radius = globe.spec().ocean_radius;
+    let globe_entity = world
+        .create_entity()
+        .with(globe)
+        .with(Spatial::new_root())
+        .build();
+    let far_away = Iso3::new(Vec3::new(0.0, 0.0, ocean_radius + 100.0), na::zero());
+    let camera_entity = world
+        .create_entity()
+        .with(Spatial::new(globe_entity, far_away))
+        .build();
+
+    let log = test_log();
+    let mut lod_view_system = LodViewSystem::new(&mut world, &log, 4, 30.0);
+    world.write_resource::<DefaultCamera>().camera_entity = Some(camera_entity);
+    specs::RunNow::run_now(&mut lod_view_system, &world.res);
+    world.maintain();
+
+    let is_lod_hidden = |world: &specs::World| {
+        let lod_views = world.read::<LodView>();
+        let visuals = world.read::<Visual>();
+        let lod_visuals: Vec<&Visual> = (&lod_views, &visuals)
+            .join()
+            .filter(|&(lod_view, _)| lod_view.globe_entity == globe_entity)
+            .map(|(_, visual)| visual)
+            .collect();
+        assert_eq!(1, lod_visuals.len());
+        assert!(lod_visuals[0].proto_mesh.is_some());
+        lod_visuals[0].is_hidden()
+    };
+    assert!(!is_lod_hidden(&world));
+
+    // Come in close enough for chunks to take over.
+    let close_up = Iso3::new(Vec3::new(0.0, 0.0, ocean_radius + 10.0), na::zero());
+    world
+        .write::<Spatial>()
+        .get_mut(camera_entity)
+        .unwrap()
+        .set_local_transform(close_up);
+    specs::RunNow::run_now(&mut lod_view_system, &world.res);
+    world.maintain();
+    assert!(is_lod_hidden(&world));
+
+    // Shouldn't make another view for the same globe.
+    world
+        .write::<Spatial>()
+        .get_mut(camera_entity)
+        .unwrap()
+        .set_local_transform(far_away);
+    specs::RunNow::run_now(&mut lod_view_system, &world.res);
+    world.maintain();
+    assert!(!is_lod_hidden(&world));
+
+    // Once the globe is gone, so is its view, along with the view's mesh.
+    world.delete_entity(globe_entity);
+    world.maintain();
+    specs::RunNow::run_now(&mut lod_view_system, &world.res);
+    world.maintain();
+    assert_eq!(0, world.read::<LodView>().join().count());
+    assert_eq!(0, world.read::<Visual>().join().count());
+}
+
+#[test]
+fn spec_builder_says_what_is_wrong() {
+    assert_eq!(Ok(Spec::new_example()), SpecBuilder::new().build());
+    assert_eq!(
+        Err(SpecError::ChunkResolutionDoesNotDivideRootResolution),
+        SpecBuilder::new().chunk_resolution([15, 16, 4]).build()
+    );
+    assert_eq!(
+        Err(SpecError::RootResolutionNotTwiceAsTallAsWide),
+        SpecBuilder::new().root_resolution([64, 64]).build()
+    );
+    assert_eq!(
+        Err(SpecError::NotPositive("block_height")),
+        SpecBuilder::new().block_height(0.0).build()
+    );
+    // Right in the middle of the tenth layer of cells.
+    assert_eq!(
+        Err(SpecError::OceanRadiusAtCellCenter),
+        SpecBuilder::new()
+            .floor_radius(25.0)
+            .block_height(0.5)
+            .ocean_radius(29.75)
+            .build()
+    );
+    let caves = CaveParams {
+        wavelength: 0.0,
+        ..CaveParams::default()
+    };
+    assert_eq!(
+        Err(SpecError::InvalidCaves),
+        SpecBuilder::new().caves(caves).build()
+    );
+}
+
+#[test]
+fn spec_loads_from_json() {
+    use std::fs;
+    use std::io::Write;
+    use serde_json;
+
+    let spec = Spec::new_earth_scale_example();
+    let json = serde_json::to_string(&spec).unwrap();
+    assert_eq!(spec, Spec::from_json(&json).unwrap());
+
+    // Terrain and cave parameters can be left out.
+    let json = r#"{
+        "seed": 3,
+        "floor_radius": 25.0,
+        "ocean_radius": 66.6,
+        "block_height": 0.65,
+        "root_resolution": [64, 128],
+        "chunk_resolution": [16, 16, 4]
+    }"#;
+    let path = unique_temp_path("planetkit_test_spec_loads_from_json").with_extension("json");
+    fs::File::create(&path)
+        .unwrap()
+        .write_all(json.as_bytes())
+        .unwrap();
+    let spec = Spec::load(&path).unwrap();
+    let _ = fs::remove_file(&path);
+    assert_eq!(3, spec.seed);
+    assert_eq!(TerrainParams::default(), spec.terrain);
+    assert_eq!(CaveParams::default(), spec.caves);
+
+    // It still has to be valid.
+    let json = json.replace("[64, 128]", "[64, 64]");
+    match Spec::from_json(&json) {
+        Err(SpecLoadError::Invalid(SpecError::RootResolutionNotTwiceAsTallAsWide)) => (),
+        other => panic!("Unexpected result: {:?}", other),
+    }
+}
+
+#[test]
+fn terrain_params_shape_the_land() {
+    let land_heights = |terrain: TerrainParams| -> Vec<f64> {
+        let spec = Spec {
+            terrain: terrain,
+            ..Globe::new_example().spec()
+        };
+        let gen = Gen::new(spec);
+        sample_columns(spec)
+            .into_iter()
+            .map(|column| gen.land_height(column))
+            .collect()
+    };
+    let ocean_radius = Globe::new_example().spec().ocean_radius;
+    let land_count = |sea_level_bias: f64| {
+        land_heights(TerrainParams {
+            sea_level_bias: sea_level_bias,
+            ..TerrainParams::default()
+        }).into_iter()
+            .filter(|&height| height > ocean_radius)
+            .count()
+    };
+    assert!(land_count(-0.5) < land_count(0.0));
+    assert!(land_count(0.0) < land_count(0.5));
+
+    let spread = |amplitude: f64| {
+        let heights = land_heights(TerrainParams {
+            amplitude: amplitude,
+            ..TerrainParams::default()
+        });
+        let lowest = heights.iter().cloned().fold(::std::f64::INFINITY, f64::min);
+        let highest = heights.iter().cloned().fold(::std::f64::NEG_INFINITY, f64::max);
+        highest - lowest
+    };
+    assert!(spread(0.1) < spread(0.45));
+
+    let terrain = TerrainParams {
+        octaves: 0,
+        ..TerrainParams::default()
+    };
+    assert_eq!(
+        Err(SpecError::InvalidTerrain),
+        SpecBuilder::new().terrain(terrain).build()
+    );
+}
+
+#[test]
+fn latitude_and_longitude_of_poles_and_prime_meridian() {
+    use std::f64::consts::FRAC_PI_2;
+
+    let spec = Globe::new_example().spec();
+    let (latitude, _) = spec.latitude_longitude(GridPoint2::new(0.into(), 0, 0));
+    assert_relative_eq!(FRAC_PI_2, latitude, epsilon = 1e-9);
+    let (latitude, _) = spec.latitude_longitude(GridPoint2::new(0.into(), 64, 128));
+    assert_relative_eq!(-FRAC_PI_2, latitude, epsilon = 1e-9);
+    // On the prime meridian.
+    let (latitude, longitude) = spec.latitude_longitude(GridPoint2::new(0.into(), 0, 8));
+    assert!(latitude > 0.0 && latitude < FRAC_PI_2);
+    assert_relative_eq!(0.0, longitude, epsilon = 1e-9);
+}
+
+#[test]
+fn heightmap_gen_follows_image() {
+    use std::f64::consts::PI;
+    use image::{DynamicImage, ImageBuffer, Luma};
+
+    let spec = Globe::new_example().spec();
+    // White in the east and black in the west, so bilinear
+    // filtering should make a smooth ramp between them both ways
+    // around the globe.
+    let heightmap = ImageBuffer::from_fn(2, 1, |x, _| Luma { data: [x as u8 * 255] });
+    let gen = HeightmapGen::new(spec, &DynamicImage::ImageLuma8(heightmap), -4.0, 6.0);
+    for column in sample_columns(spec) {
+        let (_, longitude) = spec.latitude_longitude(column);
+        // Pixel centers are a quarter of the way around the globe
+        // either side of the prime meridian.
+        let x = (longitude / PI + 1.0) - 0.5;
+        let x = (x + 2.0) % 2.0;
+        let value = if x < 1.0 { x } else { 2.0 - x };
+        assert_relative_eq!(
+            spec.ocean_radius - 4.0 + 10.0 * value,
+            gen.land_height(column),
+            epsilon = 1e-9
+        );
+    }
+
+    // Plugs into a globe just like `Gen`.
+    let mut globe = Globe::new(spec, Box::new(gen));
+    let chunk_origin = example_chunk_origin(&globe);
+    globe.ensure_chunk_present(chunk_origin).unwrap();
+    assert!(globe.chunk_at(chunk_origin).is_some());
+}
+
+#[test]
+fn generating_whole_columns_matches_generating_each_cell() {
+    use image::{DynamicImage, ImageBuffer, Luma};
+
+    let spec = Globe::new_example().spec();
+    let heightmap = ImageBuffer::from_fn(2, 1, |x, _| Luma { data: [x as u8 * 255] });
+    let gens: Vec<Box<WorldGen>> = vec![
+        Box::new(Gen::new(spec)),
+        Box::new(HeightmapGen::new(spec, &DynamicImage::ImageLuma8(heightmap), -4.0, 6.0)),
+    ];
+    for gen in &gens {
+        for column in sample_columns(spec).into_iter().take(20) {
+            let cells = gen.cells_in_column(column, 0..80);
+            assert_eq!(80, cells.len());
+            for (z, cell) in cells.iter().enumerate() {
+                assert_eq!(gen.cell_at(column.with_z(z as GridCoord)).material, cell.material);
+            }
+        }
+    }
+}
+
+#[test]
+fn heightmap_gen_uses_material_map() {
+    use std::f64::consts::PI;
+    use image::{DynamicImage, ImageBuffer, Luma, Rgb};
+
+    let spec = Globe::new_example().spec();
+    // Land a little above sea level everywhere.
+    let heightmap = ImageBuffer::from_pixel(8, 4, Luma { data: [128] });
+    let heightmap = DynamicImage::ImageLuma8(heightmap);
+    let check_surface = |gen: &HeightmapGen, expected: &Fn(f64) -> Option<MaterialId>| {
+        for column in sample_columns(spec) {
+            let (_, longitude) = spec.latitude_longitude(column);
+            let expected = match expected(longitude) {
+                Some(expected) => expected,
+                None => continue,
+            };
+            // Find the top of the land.
+            let top = (0..spec.root_resolution[1])
+                .map(|z| GridPoint3::new(column.root, column.x, column.y, z))
+                .take_while(|&pos| gen.cell_at(pos).material != MaterialId::AIR)
+                .last()
+                .expect("Should be some land");
+            assert_eq!(expected, gen.cell_at(top).material);
+        }
+    };
+
+    // Without a material map, it's all dirt.
+    let gen = HeightmapGen::new(spec, &heightmap, -2.0, 2.0);
+    check_surface(&gen, &|_| Some(MaterialId::DIRT));
+
+    // Snow in the east, and sand in the west; the colors are a bit off,
+    // but close enough to the palette.
+    let material_map = ImageBuffer::from_fn(16, 8, |x, _| if x < 8 {
+        Rgb { data: [250, 220, 120] }
+    } else {
+        Rgb { data: [240, 250, 250] }
+    });
+    let gen = gen.with_material_map(
+        &DynamicImage::ImageRgb8(material_map),
+        vec![
+            ([255, 255, 255], MaterialId::SNOW),
+            ([255, 220, 130], MaterialId::SAND),
+            ([0, 0, 0], MaterialId::STONE),
+        ],
+    );
+    // Skip the borders at the prime meridian and the antimeridian.
+    check_surface(&gen, &|longitude| if longitude.abs() < 0.1 || longitude.abs() > PI - 0.1 {
+        None
+    } else if longitude > 0.0 {
+        Some(MaterialId::SNOW)
+    } else {
+        Some(MaterialId::SAND)
+    });
+}
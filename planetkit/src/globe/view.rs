@@ -3,11 +3,14 @@ use slog::Logger;
 use grid::GridPoint3;
 use grid::cell_shape;
 use super::spec::Spec;
-use super::{Globe, Cursor, ChunkOrigin};
-use super::chunk::Material;
+use super::{Globe, Cursor, ChunkOrigin, MAX_LIGHT};
 use types::Pt3;
 use render;
 
+// How bright cells are with no light at all, so that
+// caves are dark, but not completely black.
+const MIN_BRIGHTNESS: f32 = 0.15;
+
 // TODO: between this and "draw" we now have some confusing names.
 // Shuffle this code into something that implies it's just about
 // generating geometry for other components/systems, e.g., drawing
@@ -83,28 +86,27 @@ impl View {
 
                     cursor.set_pos(grid_point);
 
-                    if self.cull_cell(&cursor) {
-                        continue;
-                    }
+                    let light = match self.visible_light(&cursor) {
+                        Some(light) => light,
+                        None => continue,
+                    };
 
                     let mut cell_color = {
                         // Eww... can I please have non-lexical borrow scopes? :)
                         let cell = cursor.cell().expect("We shouldn't be trying to build geometry for a chunk that isn't loaded.");
 
-                        // TEMP color dirt as green, ocean as blue.
-                        // TEMP: Randomly mutate cell color to make it easier to see edges.
-                        let mut inner_cell_color = if cell.material == Material::Dirt {
-                            // Grassy green
-                            [0.0, 0.4, 0.0]
-                        } else if cell.material == Material::Water {
-                            // Ocean blue
-                            [0.0, 0.1, 0.7]
-                        } else {
-                            // Don't draw air or anything else we don't understand.
-                            continue;
+                        // Don't draw air or anything else we can see straight through,
+                        // or anything we don't understand.
+                        let material = match globe.materials().get(cell.material) {
+                            Some(material) if !material.is_transparent => material,
+                            _ => continue,
                         };
+
+                        let brightness = MIN_BRIGHTNESS +
+                            (1.0 - MIN_BRIGHTNESS) * light as f32 / MAX_LIGHT as f32;
+                        let mut inner_cell_color = material.color;
                         for color_channel in &mut inner_cell_color {
-                            *color_channel *= 1.0 - 0.5 * cell.shade;
+                            *color_channel *= brightness;
                         }
 
                         inner_cell_color
@@ -204,7 +206,9 @@ impl View {
         }
     }
 
-    fn cull_cell(&self, cursor: &Cursor) -> bool {
+    // How brightly lit the visible faces of the cell are,
+    // or `None` if it can't be seen at all.
+    fn visible_light(&self, cursor: &Cursor) -> Option<u8> {
         use grid::Neighbors;
 
         let resolution = cursor.globe().spec().root_resolution;
@@ -212,21 +216,26 @@ impl View {
         let grid_point = cursor.pos();
         let mut neighbor_cursor = cursor.clone();
 
-        // If none of the neighboring cells contain air,
-        // then we won't render the cell at all.
+        // Faces can only be seen from neighboring cells that are
+        // transparent (e.g. air), and they're lit by the light in those cells.
+        // If there aren't any, then we won't render the cell at all.
+        let materials = cursor.globe().materials();
+        let mut light: Option<u8> = None;
         let neighbors = Neighbors::new(grid_point, resolution);
         for neighbor_pos in neighbors {
             neighbor_cursor.set_pos(neighbor_pos);
             if let Some(neighbor) = neighbor_cursor.cell() {
-                if neighbor.material == Material::Air {
-                    // This cell can be seen; we can't cull it.
-                    return false;
+                if materials.is_transparent(neighbor.material) {
+                    light = Some(light.map_or(neighbor.light(), |light| {
+                        light.max(neighbor.light())
+                    }));
                 }
             }
         }
 
-        // If there was no reason to save it,
-        // then assume we can cull it!
-        true
+        // Anything that glows is at least as bright as its own light.
+        neighbor_cursor.set_pos(grid_point);
+        let block_light = neighbor_cursor.cell().map_or(0, |cell| cell.block_light);
+        light.map(|light| light.max(block_light))
     }
 }
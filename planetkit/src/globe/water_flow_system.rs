@@ -0,0 +1,153 @@
+use std::collections::HashSet;
+
+use specs;
+use specs::WriteStorage;
+use slog::Logger;
+
+use grid::{GridCoord, GridPoint3, PosInOwningRoot, Neighbors, semi_arbitrary_compare};
+use super::{Globe, GlobeEdit, MaterialId, ChunkNotReady};
+
+/// Lets water flow into cells of air opened up next to it,
+/// e.g., by mining, until it settles.
+///
+/// Water is treated as a simple cellular automaton. Every tick, each unsettled
+/// cell of water (see `Globe::mark_cell_unsettled`) may move one cell:
+///
+/// - Down, if there's air below it.
+/// - Sideways, into air with more air below it, to pour over an edge.
+/// - Sideways, into air, if there's more water above it pushing it out.
+///
+/// Water is never created or destroyed, so this always settles eventually.
+///
+/// Only looks at cells in loaded chunks; water at the edge of what's
+/// loaded stays unsettled, and waits there until those chunks are loaded.
+///
+/// Globes only keep track of unsettled cells once this has run for them
+/// (see `Globe::track_unsettled_cells`), so water doesn't react to anything
+/// that changed before then.
+pub struct WaterFlowSystem {
+    log: Logger,
+    // Leave any more than this for the next tick,
+    // so a flood can't make us drop frames.
+    max_cells_per_tick: usize,
+}
+
+impl WaterFlowSystem {
+    pub fn new(parent_log: &Logger, max_cells_per_tick: usize) -> WaterFlowSystem {
+        WaterFlowSystem {
+            log: parent_log.new(o!()),
+            max_cells_per_tick: max_cells_per_tick,
+        }
+    }
+
+    /// Move each unsettled cell of water in `globe` at most once,
+    /// looking at no more than `max_cells_per_tick` cells.
+    ///
+    /// Returns how many cells of water moved, which will be zero
+    /// once all the water has settled.
+    pub fn flow_water(&self, globe: &mut Globe) -> usize {
+        globe.track_unsettled_cells(true);
+        let root_resolution = globe.spec().root_resolution;
+        let mut unsettled_cells: Vec<GridPoint3> = globe
+            .take_unsettled_cells()
+            .into_iter()
+            .map(|pos| pos.into())
+            .collect();
+        // Let water at the bottom move first to make room for the water above it,
+        // and otherwise go in a consistent order so that water flows the same way
+        // in every copy of the globe.
+        unsettled_cells.sort_by(|a, b| a.z.cmp(&b.z).then(semi_arbitrary_compare(a, b)));
+        if unsettled_cells.len() > self.max_cells_per_tick {
+            for pos in unsettled_cells.drain(self.max_cells_per_tick..) {
+                globe.mark_cell_unsettled(PosInOwningRoot::new(pos, root_resolution));
+            }
+        }
+
+        let mut cells_moved = 0;
+        // Water that needs to look at cells in chunks that aren't ready yet.
+        let mut waiting: Vec<PosInOwningRoot> = Vec::new();
+        {
+            let mut edit = globe.edit_without_recording();
+            // Don't let the same water move more than once per tick.
+            let mut arrived: HashSet<PosInOwningRoot> = HashSet::new();
+            for pos in unsettled_cells {
+                if arrived.contains(&PosInOwningRoot::new(pos, root_resolution)) {
+                    continue;
+                }
+                let new_pos = match where_water_flows(&edit, pos, root_resolution) {
+                    Ok(Some(new_pos)) => new_pos,
+                    // Either it's settled, or it's not water.
+                    Ok(None) => continue,
+                    Err(_) => {
+                        trace!(
+                            self.log,
+                            "Waiting for chunk to be ready before flowing";
+                            "pos" => format!("{:?}", pos)
+                        );
+                        waiting.push(PosInOwningRoot::new(pos, root_resolution));
+                        continue;
+                    }
+                };
+                // We already know that both chunks are loaded.
+                edit.set_material(pos, MaterialId::AIR).expect("Chunk disappeared");
+                edit.set_material(new_pos, MaterialId::WATER).expect("Chunk disappeared");
+                arrived.insert(PosInOwningRoot::new(new_pos, root_resolution));
+                cells_moved += 1;
+            }
+            edit.commit();
+        }
+        // Try again next tick; the chunks might be ready by then.
+        for pos in waiting {
+            globe.mark_cell_unsettled(pos);
+        }
+        cells_moved
+    }
+}
+
+// Where the cell at `pos` should move to, if it's water that can move anywhere.
+fn where_water_flows(
+    edit: &GlobeEdit,
+    pos: GridPoint3,
+    root_resolution: [GridCoord; 2],
+) -> Result<Option<GridPoint3>, ChunkNotReady> {
+    if edit.cell(pos)?.material != MaterialId::WATER {
+        return Ok(None);
+    }
+    let is_air = |pos: GridPoint3| -> Result<bool, ChunkNotReady> {
+        Ok(pos.z >= 0 && edit.cell(pos)?.material == MaterialId::AIR)
+    };
+
+    let below = pos.with_z(pos.z - 1);
+    if is_air(below)? {
+        return Ok(Some(below));
+    }
+    let sideways: Vec<GridPoint3> = Neighbors::new(pos, root_resolution)
+        .filter(|neighbor| neighbor.z == pos.z)
+        .collect();
+    for &neighbor in &sideways {
+        if is_air(neighbor)? && is_air(neighbor.with_z(pos.z - 1))? {
+            return Ok(Some(neighbor));
+        }
+    }
+    let above = pos.with_z(pos.z + 1);
+    if edit.cell(above)?.material != MaterialId::WATER {
+        return Ok(None);
+    }
+    for &neighbor in &sideways {
+        if is_air(neighbor)? {
+            return Ok(Some(neighbor));
+        }
+    }
+    Ok(None)
+}
+
+impl<'a> specs::System<'a> for WaterFlowSystem {
+    type SystemData = WriteStorage<'a, Globe>;
+
+    fn run(&mut self, mut globes: Self::SystemData) {
+        use specs::Join;
+        for globe in (&mut globes).join() {
+            self.flow_water(globe);
+        }
+    }
+}
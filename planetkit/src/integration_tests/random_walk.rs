@@ -28,12 +28,13 @@ impl Walker {
         world.register::<::cell_dweller::CellDweller>();
         world.register::<::Spatial>();
         world.register::<::globe::Globe>();
+        world.register::<::globe::ChunkLoadAnchor>();
 
         // Initialize common resources.
         world.add_resource(TimeDeltaResource(0.0));
 
         // Create systems.
-        let chunk_sys = globe::ChunkSystem::new(&root_log);
+        let chunk_sys = globe::ChunkSystem::new(&root_log, 2);
 
         let (movement_input_sender, movement_input_receiver) = mpsc::channel();
         let mut movement_sys =
@@ -58,21 +59,30 @@ impl Walker {
 
         // Use an Earth-scale globe to make it likely we're constantly
         // visiting new chunks.
-        let globe = globe::Globe::new_earth_scale_example();
+        //
+        // Leave out caves, because we start from the lowest air we can find,
+        // and we want that to be on the surface.
+        let spec = globe::Spec {
+            caves: globe::CaveParams::none(),
+            ..globe::Spec::new_earth_scale_example()
+        };
+        let globe = globe::Globe::new(spec, Box::new(globe::Gen::new(spec)));
         // First add the globe to the world so we can get a handle on its entity.
         let globe_spec = globe.spec();
         let globe_entity = world.create_entity().with(globe).build();
 
         // Find globe surface and put player character on it.
         use grid::{GridPoint3, Dir};
-        use globe::chunk::Material;
+        use globe::MaterialId;
         let mut guy_pos = GridPoint3::default();
         guy_pos = {
             let mut globes = world.write::<globe::Globe>();
             let globe = globes.get_mut(globe_entity).expect(
                 "Uh oh, where did our Globe go?",
             );
-            globe.find_lowest_cell_containing(guy_pos, Material::Air)
+            globe
+                .find_lowest_cell_containing(guy_pos, MaterialId::AIR)
+                .expect("Failed to load chunks")
         };
         let guy_entity = world
             .create_entity()
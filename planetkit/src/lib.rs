@@ -77,6 +77,7 @@ extern crate tokio_io;
 extern crate serde_derive;
 extern crate serde;
 extern crate serde_json;
+extern crate image;
 
 #[cfg(all(feature = "nightly", test))]
 extern crate test;
@@ -93,6 +94,9 @@ pub mod cell_dweller;
 pub mod movement;
 pub mod camera;
 pub mod net;
+pub mod pathfinding;
+pub mod day_night;
+pub mod orbit;
 
 mod spatial;
 pub use spatial::Spatial;
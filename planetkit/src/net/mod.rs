@@ -34,8 +34,9 @@ pub use self::server_resource::ServerResource;
 // peer is trusted.
 //
 // Exists primarily as a way to aggregate all the super-traits we expect,
-// especially around being able to serialize it.
-pub trait GameMessage : 'static + Serialize + DeserializeOwned + Debug + Eq + PartialEq + Send + Sync + Clone {}
+// especially around being able to serialize it. Doesn't require `Eq`,
+// so that messages can carry things like a globe's `Spec`.
+pub trait GameMessage : 'static + Serialize + DeserializeOwned + Debug + PartialEq + Send + Sync + Clone {}
 
 // TODO: identify self in every message. Make this a struct wrapping the enum,
 // or include your identity in Goodbye and a Game wrapper?
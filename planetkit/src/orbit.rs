@@ -0,0 +1,330 @@
+//! Moving globes, moons, and suns around each other.
+
+use std::f64::consts::PI;
+
+use na;
+use specs;
+use specs::{Fetch, FetchMut, ReadStorage, WriteStorage};
+use slog::Logger;
+
+use types::*;
+use Spatial;
+use AutoResource;
+
+// Solving Kepler's equation converges very quickly for any sensible
+// eccentricity; always doing the same number of iterations keeps
+// every peer's answer identical.
+const KEPLER_ITERATIONS: usize = 30;
+
+/// Seconds since the epoch at which every `Orbit` starts.
+///
+/// Orbits are worked out directly from this, rather than by accumulating
+/// small steps, so any peers that agree on the time will agree on where
+/// everything is.
+#[derive(Clone, Copy, Debug, PartialEq, Default)]
+pub struct OrbitalTime {
+    pub seconds: f64,
+}
+
+impl AutoResource for OrbitalTime {
+    fn new(_world: &mut specs::World) -> OrbitalTime {
+        OrbitalTime::default()
+    }
+}
+
+/// A Keplerian orbit around the parent of an entity's `Spatial`,
+/// and the entity's spin about its own axis.
+///
+/// The orbit is described in the parent's frame, with its XY plane
+/// as the reference plane, and the X axis as the reference direction.
+/// If the parent also has an `Orbit`, then its spin is taken back out
+/// of that frame, so that moons don't get swung around by the planets
+/// they orbit; the reference plane is then the parent's equator,
+/// which still follows the parent's axial tilt.
+///
+/// Angles are in radians, and times are in seconds.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Orbit {
+    pub semi_major_axis: f64,
+    /// 0 for a circular orbit; must be less than 1.
+    pub eccentricity: f64,
+    /// Tilt of the orbital plane from the reference plane.
+    pub inclination: f64,
+    /// Angle from the reference direction to where the orbit
+    /// passes up through the reference plane.
+    pub longitude_of_ascending_node: f64,
+    /// Angle from the ascending node to the closest approach to the parent.
+    pub argument_of_periapsis: f64,
+    /// Where the body is in its orbit at time zero, as a fraction of a
+    /// whole orbit since periapsis, expressed as an angle.
+    pub mean_anomaly_at_epoch: f64,
+    /// Time taken to go around the orbit once; must not be 0.
+    pub period: f64,
+    /// Axis the body spins about, in its own frame. For a `Globe`, this would
+    /// usually be the direction of its north pole.
+    pub spin_axis: Vec3,
+    /// Time taken to spin around once, or 0 to not spin at all.
+    pub spin_period: f64,
+    /// How far the body has spun about its axis at time zero.
+    pub spin_at_epoch: f64,
+    /// Tilt of the spin axis from the normal of the orbital plane.
+    pub axial_tilt: f64,
+}
+
+impl Orbit {
+    /// A circular orbit in the reference plane, without any spin.
+    pub fn new_circular(radius: f64, period: f64) -> Orbit {
+        Orbit {
+            semi_major_axis: radius,
+            eccentricity: 0.0,
+            inclination: 0.0,
+            longitude_of_ascending_node: 0.0,
+            argument_of_periapsis: 0.0,
+            mean_anomaly_at_epoch: 0.0,
+            period: period,
+            spin_axis: Vec3::z(),
+            spin_period: 0.0,
+            spin_at_epoch: 0.0,
+            axial_tilt: 0.0,
+        }
+    }
+
+    pub fn is_valid(&self) -> bool {
+        self.eccentricity >= 0.0 && self.eccentricity < 1.0 && self.period != 0.0 &&
+            self.period.is_finite()
+    }
+
+    /// Position relative to the parent at `time`, in the parent's frame.
+    pub fn position_at(&self, time: f64) -> Vec3 {
+        let e = self.eccentricity;
+        let mean_anomaly = self.mean_anomaly_at_epoch + 2.0 * PI * time / self.period;
+        // Solve Kepler's equation, `M = E - e sin(E)`, for the eccentric anomaly
+        // using Newton's method.
+        let mean_anomaly = mean_anomaly % (2.0 * PI);
+        let mut eccentric_anomaly = if e > 0.8 { PI } else { mean_anomaly };
+        for _ in 0..KEPLER_ITERATIONS {
+            eccentric_anomaly -= (eccentric_anomaly - e * eccentric_anomaly.sin() -
+                                      mean_anomaly) /
+                (1.0 - e * eccentric_anomaly.cos());
+        }
+        let true_anomaly = 2.0 *
+            ((1.0 + e).sqrt() * (eccentric_anomaly / 2.0).sin())
+                .atan2((1.0 - e).sqrt() * (eccentric_anomaly / 2.0).cos());
+        let distance = self.semi_major_axis * (1.0 - e * eccentric_anomaly.cos());
+        let in_orbital_plane = Rot3::from_axis_angle(
+            &Vec3::z_axis(),
+            self.argument_of_periapsis + true_anomaly,
+        ) * Vec3::x();
+        self.orbital_plane() * in_orbital_plane * distance
+    }
+
+    /// Orientation of the body at `time`, relative to the parent's frame.
+    pub fn orientation_at(&self, time: f64) -> Rot3 {
+        // Point the spin axis up out of the orbital plane, spin around it,
+        // and then tilt it.
+        self.orbital_plane() * Rot3::from_axis_angle(&Vec3::x_axis(), self.axial_tilt) *
+            self.spin_at(time)
+    }
+
+    /// Transform of the body relative to its parent at `time`.
+    pub fn local_transform_at(&self, time: f64) -> Iso3 {
+        Iso3::from_parts(
+            na::Translation3::from_vector(self.position_at(time)),
+            na::UnitQuaternion::from_rotation_matrix(&self.orientation_at(time)),
+        )
+    }
+
+    // Rotation that points the spin axis along Z, and then spins
+    // the body around it as far as it has spun by `time`.
+    fn spin_at(&self, time: f64) -> Rot3 {
+        let spin = if self.spin_period == 0.0 {
+            self.spin_at_epoch
+        } else {
+            self.spin_at_epoch + 2.0 * PI * (time / self.spin_period) % (2.0 * PI)
+        };
+        let align_axis = Rot3::rotation_between(&self.spin_axis, &Vec3::z())
+            .unwrap_or_else(|| Rot3::from_axis_angle(&Vec3::x_axis(), PI));
+        Rot3::from_axis_angle(&Vec3::z_axis(), spin) * align_axis
+    }
+
+    // Rotation from the reference plane to the orbital plane.
+    fn orbital_plane(&self) -> Rot3 {
+        Rot3::from_axis_angle(&Vec3::z_axis(), self.longitude_of_ascending_node) *
+            Rot3::from_axis_angle(&Vec3::x_axis(), self.inclination)
+    }
+}
+
+impl specs::Component for Orbit {
+    type Storage = specs::HashMapStorage<Orbit>;
+}
+
+/// Advances `OrbitalTime`, and then moves every `Spatial` that has an `Orbit`
+/// to where it should be at that time.
+///
+/// Panics on finding an `Orbit` that isn't valid; see `Orbit::is_valid`.
+pub struct OrbitSystem {
+    log: Logger,
+}
+
+impl OrbitSystem {
+    pub fn new(world: &mut specs::World, parent_log: &Logger) -> OrbitSystem {
+        OrbitalTime::ensure(world);
+        OrbitSystem { log: parent_log.new(o!("system" => "orbit")) }
+    }
+}
+
+impl<'a> specs::System<'a> for OrbitSystem {
+    type SystemData = (Fetch<'a, TimeDeltaResource>,
+     FetchMut<'a, OrbitalTime>,
+     ReadStorage<'a, Orbit>,
+     WriteStorage<'a, Spatial>);
+
+    fn run(&mut self, data: Self::SystemData) {
+        use specs::Join;
+
+        let (dt, mut orbital_time, orbits, mut spatials) = data;
+        orbital_time.seconds += dt.0;
+        let time = orbital_time.seconds;
+        for (orbit, spatial) in (&orbits, &mut spatials).join() {
+            if !orbit.is_valid() {
+                panic!("Invalid orbit: {:?}", orbit);
+            }
+            let mut local_transform = orbit.local_transform_at(time);
+            // Undo the parent's spin, but not its tilt.
+            let parent_orbit = spatial.parent_entity().and_then(|parent| orbits.get(parent));
+            if let Some(parent_orbit) = parent_orbit {
+                let parent_spin =
+                    na::UnitQuaternion::from_rotation_matrix(&parent_orbit.spin_at(time));
+                local_transform.append_rotation_mut(&parent_spin.inverse());
+            }
+            spatial.set_local_transform(local_transform);
+        }
+        trace!(self.log, "Moved orbiting bodies"; "time" => time);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use specs;
+
+    use spatial::SpatialStorage;
+    use super::*;
+
+    #[test]
+    fn circular_orbit() {
+        let orbit = Orbit::new_circular(10.0, 100.0);
+        assert_relative_eq!(Vec3::new(10.0, 0.0, 0.0), orbit.position_at(0.0), epsilon = 1e-9);
+        assert_relative_eq!(Vec3::new(0.0, 10.0, 0.0), orbit.position_at(25.0), epsilon = 1e-9);
+        assert_relative_eq!(Vec3::new(-10.0, 0.0, 0.0), orbit.position_at(50.0), epsilon = 1e-9);
+        // Back where it started.
+        assert_relative_eq!(orbit.position_at(0.0), orbit.position_at(300.0), epsilon = 1e-9);
+    }
+
+    #[test]
+    fn eccentric_inclined_orbit() {
+        let orbit = Orbit {
+            eccentricity: 0.5,
+            inclination: PI / 2.0,
+            ..Orbit::new_circular(10.0, 100.0)
+        };
+        // Closest at periapsis, and furthest at apoapsis.
+        assert_relative_eq!(Vec3::new(5.0, 0.0, 0.0), orbit.position_at(0.0), epsilon = 1e-9);
+        assert_relative_eq!(Vec3::new(-15.0, 0.0, 0.0), orbit.position_at(50.0), epsilon = 1e-9);
+        for i in 0..100 {
+            let distance = orbit.position_at(i as f64).norm();
+            assert!(distance >= 5.0 - 1e-9 && distance <= 15.0 + 1e-9);
+        }
+        // Tipped up onto the XZ plane, and faster near periapsis.
+        let quarter_of_the_way = orbit.position_at(25.0);
+        assert_relative_eq!(0.0, quarter_of_the_way.y, epsilon = 1e-9);
+        assert!(quarter_of_the_way.x < 0.0);
+        assert!(quarter_of_the_way.z > 0.0);
+    }
+
+    #[test]
+    fn tilted_spin() {
+        let orbit = Orbit {
+            spin_axis: Vec3::y(),
+            spin_period: 10.0,
+            axial_tilt: 0.4,
+            ..Orbit::new_circular(10.0, 100.0)
+        };
+        let spin_axis = orbit.orientation_at(0.0) * Vec3::y();
+        assert_relative_eq!(0.4_f64.cos(), spin_axis.z, epsilon = 1e-9);
+        for i in 0..20 {
+            let time = i as f64 * 1.3;
+            // Spin axis stays put while everything else turns around it.
+            assert_relative_eq!(spin_axis, orbit.orientation_at(time) * Vec3::y(), epsilon = 1e-9);
+        }
+        assert!((orbit.orientation_at(0.0) * Vec3::x() - orbit.orientation_at(5.0) * Vec3::x())
+            .norm() > 1.0);
+        assert_relative_eq!(
+            orbit.orientation_at(0.0) * Vec3::x(),
+            orbit.orientation_at(10.0) * Vec3::x(),
+            epsilon = 1e-9
+        );
+    }
+
+    #[test]
+    fn orbit_validity() {
+        assert!(Orbit::new_circular(10.0, 100.0).is_valid());
+        assert!(!Orbit::new_circular(10.0, 0.0).is_valid());
+        assert!(
+            !Orbit {
+                eccentricity: 1.0,
+                ..Orbit::new_circular(10.0, 100.0)
+            }.is_valid()
+        );
+    }
+
+    #[test]
+    fn moon_orbits_planet_orbits_star() {
+        use slog;
+
+        let mut world = specs::World::new();
+        world.register::<Spatial>();
+        world.register::<Orbit>();
+        world.add_resource(TimeDeltaResource(0.0));
+
+        let planet_orbit = Orbit {
+            spin_period: 7.0,
+            axial_tilt: 0.3,
+            ..Orbit::new_circular(1000.0, 400.0)
+        };
+        let moon_orbit = Orbit::new_circular(50.0, 40.0);
+        let star = world.create_entity().with(Spatial::new_root()).build();
+        let planet = world
+            .create_entity()
+            .with(Spatial::new(star, Iso3::identity()))
+            .with(planet_orbit)
+            .build();
+        let moon = world
+            .create_entity()
+            .with(Spatial::new(planet, Iso3::identity()))
+            .with(moon_orbit)
+            .build();
+
+        let drain = slog::Discard;
+        let log = slog::Logger::root(drain, o!("pk_version" => env!("CARGO_PKG_VERSION")));
+        let mut orbit_system = OrbitSystem::new(&mut world, &log);
+        for _ in 0..37 {
+            world.write_resource::<TimeDeltaResource>().0 = 0.5;
+            specs::RunNow::run_now(&mut orbit_system, &world.res);
+        }
+
+        let time = world.read_resource::<OrbitalTime>().seconds;
+        assert_relative_eq!(18.5, time);
+        let spatials = world.read::<Spatial>();
+        let planet_from_star = spatials.a_relative_to_b(planet, star).translation.vector;
+        let moon_from_star = spatials.a_relative_to_b(moon, star).translation.vector;
+        assert_relative_eq!(planet_orbit.position_at(time), planet_from_star, epsilon = 1e-6);
+        // The planet's spin doesn't drag the moon around with it,
+        // but the moon does orbit around the planet's tilted equator.
+        let planet_tilt = Rot3::from_axis_angle(&Vec3::x_axis(), 0.3);
+        assert_relative_eq!(
+            planet_orbit.position_at(time) + planet_tilt * moon_orbit.position_at(time),
+            moon_from_star,
+            epsilon = 1e-6
+        );
+    }
+}
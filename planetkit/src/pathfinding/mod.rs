@@ -0,0 +1,292 @@
+//! Finding paths across the surface of a `Globe` that a `CellDweller`
+//! could walk along.
+
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap};
+use std::error;
+use std::fmt;
+
+use grid::{GridCoord, GridPoint3, PosInOwningRoot, Neighbors};
+use globe::{Globe, ChunkOrigin, ChunkNotReady};
+
+#[cfg(test)]
+mod tests;
+
+// Neighboring cell centers are never more than this far apart,
+// in radians, divided by the x-resolution of root quads.
+//
+// Root quads are one edge of the icosahedron across, and the edges of
+// an icosahedron subtend about 1.107 radians, but cells in the middle
+// of each face get stretched a bit by projecting them onto the sphere.
+const MAX_STEP_ANGLE_TIMES_RESOLUTION: f64 = 1.5;
+
+/// Finds paths between cells using the same rules for stepping,
+/// climbing, and falling as `MovementSystem` and `PhysicsSystem`.
+///
+/// A `CellDweller` can stand in any cell that isn't solid,
+/// as long as the cell below it is solid. From there it can step
+/// into a neighboring column at the same height, or climb up to
+/// `max_step_height` cells if the way is blocked, after which
+/// it falls until it lands on something solid.
+///
+/// Chunks that the search needs but aren't loaded yet are loaded
+/// (or generated) on demand, up to `max_chunks_to_load` of them.
+/// Like any chunks loaded through `Globe::ensure_chunk_present`,
+/// `ChunkSystem` may unload them again soon after.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Pathfinder {
+    pub max_step_height: u8,
+    /// Give up after looking at this many cells.
+    pub max_cells_explored: usize,
+    /// Give up rather than load more than this many chunks.
+    pub max_chunks_to_load: usize,
+}
+
+/// Reasons why `Pathfinder::find_path` might fail to find a path.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum PathfindingError {
+    /// Every cell reachable from the start has been explored,
+    /// and none of them were the goal.
+    NoPath,
+    /// Explored `max_cells_explored` cells without finding the goal.
+    TooManyCellsExplored,
+    /// Needed to load the chunk at the given origin,
+    /// but already loaded `max_chunks_to_load` chunks.
+    ChunkBudgetExhausted(ChunkOrigin),
+    /// Needed to load the chunk at the given origin,
+    /// but it couldn't be read from the globe's chunk store.
+    ChunkLoadFailed(ChunkOrigin),
+}
+
+impl fmt::Display for PathfindingError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use self::PathfindingError::*;
+        match *self {
+            NoPath => write!(f, "there is no path to the goal"),
+            TooManyCellsExplored => write!(f, "explored too many cells looking for a path"),
+            ChunkBudgetExhausted(chunk_origin) => {
+                write!(
+                    f,
+                    "needed to load too many chunks looking for a path, including {:?}",
+                    chunk_origin.pos()
+                )
+            }
+            ChunkLoadFailed(chunk_origin) => {
+                write!(
+                    f,
+                    "couldn't load chunk {:?} looking for a path",
+                    chunk_origin.pos()
+                )
+            }
+        }
+    }
+}
+
+impl error::Error for PathfindingError {
+    fn description(&self) -> &str {
+        "couldn't find a path"
+    }
+}
+
+impl Pathfinder {
+    pub fn new(
+        max_step_height: u8,
+        max_cells_explored: usize,
+        max_chunks_to_load: usize,
+    ) -> Pathfinder {
+        Pathfinder {
+            max_step_height: max_step_height,
+            max_cells_explored: max_cells_explored,
+            max_chunks_to_load: max_chunks_to_load,
+        }
+    }
+
+    /// Find the shortest path from `start` to `goal`, counting the number of steps.
+    ///
+    /// If either `start` or `goal` is up in the air, then they're first
+    /// moved down to where a `CellDweller` would land if it fell from there.
+    ///
+    /// Returns the position a `CellDweller` following the path would end up at
+    /// after each step, including `goal`, but not `start`. Each one is in a column
+    /// next to the previous one, so a `CellDweller` can follow the path by turning
+    /// until it faces each step's column, and then stepping forward.
+    /// The positions are all expressed in their owning root, so when comparing
+    /// them with the position of a `CellDweller` be sure to use `PosInOwningRoot`.
+    pub fn find_path(
+        &self,
+        globe: &mut Globe,
+        start: GridPoint3,
+        goal: GridPoint3,
+    ) -> Result<Vec<GridPoint3>, PathfindingError> {
+        let root_resolution = globe.spec().root_resolution;
+        let max_step_angle = MAX_STEP_ANGLE_TIMES_RESOLUTION / root_resolution[0] as f64;
+        let mut search = Search {
+            globe: globe,
+            pathfinder: *self,
+            chunks_loaded: 0,
+        };
+        let start = PosInOwningRoot::new(search.land(start)?, root_resolution);
+        let goal = PosInOwningRoot::new(search.land(goal)?, root_resolution);
+        let goal_on_unit_sphere = search.globe.spec().cell_center_on_unit_sphere(goal.pos().rxy);
+        // Never overestimate the number of steps left,
+        // so that we'll always find the shortest path.
+        let heuristic = |globe: &Globe, pos: PosInOwningRoot| {
+            let pos_on_unit_sphere = globe.spec().cell_center_on_unit_sphere(pos.pos().rxy);
+            let cos_angle = pos_on_unit_sphere.coords.dot(&goal_on_unit_sphere.coords);
+            cos_angle.max(-1.0).min(1.0).acos() / max_step_angle
+        };
+
+        let mut open = BinaryHeap::new();
+        let mut steps_to: HashMap<PosInOwningRoot, usize> = HashMap::new();
+        let mut came_from: HashMap<PosInOwningRoot, PosInOwningRoot> = HashMap::new();
+        steps_to.insert(start, 0);
+        open.push(Candidate {
+            estimated_steps: heuristic(search.globe, start),
+            steps: 0,
+            pos: start,
+        });
+        let mut cells_explored = 0;
+        while let Some(candidate) = open.pop() {
+            if candidate.pos == goal {
+                return Ok(path_to(goal, &came_from));
+            }
+            if steps_to[&candidate.pos] < candidate.steps {
+                // We've already found a shorter way here.
+                continue;
+            }
+            cells_explored += 1;
+            if cells_explored > self.max_cells_explored {
+                return Err(PathfindingError::TooManyCellsExplored);
+            }
+
+            let steps = candidate.steps + 1;
+            for next_pos in search.steps_from(*candidate.pos())? {
+                let next_pos = PosInOwningRoot::new(next_pos, root_resolution);
+                if steps_to.get(&next_pos).map_or(false, |&known| known <= steps) {
+                    continue;
+                }
+                steps_to.insert(next_pos, steps);
+                came_from.insert(next_pos, candidate.pos);
+                open.push(Candidate {
+                    estimated_steps: steps as f64 + heuristic(search.globe, next_pos),
+                    steps: steps,
+                    pos: next_pos,
+                });
+            }
+        }
+        Err(PathfindingError::NoPath)
+    }
+}
+
+impl Default for Pathfinder {
+    fn default() -> Pathfinder {
+        // Same step height as `MovementSystem`.
+        Pathfinder::new(1, 10000, 64)
+    }
+}
+
+struct Search<'a> {
+    globe: &'a mut Globe,
+    pathfinder: Pathfinder,
+    chunks_loaded: usize,
+}
+
+impl<'a> Search<'a> {
+    fn is_solid(&mut self, pos: GridPoint3) -> Result<bool, PathfindingError> {
+        loop {
+            let chunk_origin = match self.globe.maybe_non_authoritative_cell_if_ready(pos) {
+                Ok(cell) => return Ok(self.globe.materials().is_solid(cell.material)),
+                Err(ChunkNotReady { chunk_origin }) => chunk_origin,
+            };
+            if self.chunks_loaded >= self.pathfinder.max_chunks_to_load {
+                return Err(PathfindingError::ChunkBudgetExhausted(chunk_origin));
+            }
+            if self.globe.ensure_chunk_present(chunk_origin).is_err() {
+                return Err(PathfindingError::ChunkLoadFailed(chunk_origin));
+            }
+            self.chunks_loaded += 1;
+        }
+    }
+
+    // Where a `CellDweller` at `pos` would end up after falling
+    // until it lands on something solid.
+    fn land(&mut self, mut pos: GridPoint3) -> Result<GridPoint3, PathfindingError> {
+        while pos.z > 0 && !self.is_solid(pos.with_z(pos.z - 1))? {
+            pos.z -= 1;
+        }
+        Ok(pos)
+    }
+
+    // Everywhere a `CellDweller` standing at `pos` could end up
+    // after a single step. See `MovementSystem::step_if_possible`.
+    fn steps_from(&mut self, pos: GridPoint3) -> Result<Vec<GridPoint3>, PathfindingError> {
+        let root_resolution = self.globe.spec().root_resolution;
+        let mut ends = Vec::new();
+        let columns = Neighbors::new(pos, root_resolution).filter(|neighbor| neighbor.z == pos.z);
+        for neighbor in columns {
+            let max_z = pos.z + self.pathfinder.max_step_height as GridCoord;
+            let mut z = pos.z;
+            while z <= max_z && self.is_solid(neighbor.with_z(z))? {
+                z += 1;
+            }
+            if z <= max_z {
+                ends.push(self.land(neighbor.with_z(z))?);
+            }
+        }
+        Ok(ends)
+    }
+}
+
+fn path_to(
+    goal: PosInOwningRoot,
+    came_from: &HashMap<PosInOwningRoot, PosInOwningRoot>,
+) -> Vec<GridPoint3> {
+    let mut path = Vec::new();
+    let mut pos = goal;
+    while let Some(&previous_pos) = came_from.get(&pos) {
+        path.push(pos.into());
+        pos = previous_pos;
+    }
+    path.reverse();
+    path
+}
+
+// Cell waiting to be explored, ordered so that `BinaryHeap`
+// gives us the most promising one first.
+struct Candidate {
+    estimated_steps: f64,
+    steps: usize,
+    pos: PosInOwningRoot,
+}
+
+impl Candidate {
+    fn pos(&self) -> &GridPoint3 {
+        self.pos.pos()
+    }
+}
+
+impl PartialEq for Candidate {
+    fn eq(&self, other: &Candidate) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Candidate {}
+
+impl PartialOrd for Candidate {
+    fn partial_cmp(&self, other: &Candidate) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Candidate {
+    fn cmp(&self, other: &Candidate) -> Ordering {
+        // Fewest estimated steps first, and then prefer those
+        // furthest along, which are probably closest to the goal.
+        other
+            .estimated_steps
+            .partial_cmp(&self.estimated_steps)
+            .unwrap_or(Ordering::Equal)
+            .then(self.steps.cmp(&other.steps))
+    }
+}
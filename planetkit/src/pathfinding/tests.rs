@@ -0,0 +1,187 @@
+use grid::{GridCoord, GridPoint2, GridPoint3, PosInOwningRoot, Neighbors, EquivalentPoints};
+use globe::{Globe, Spec, WorldGen, MaterialId};
+use globe::chunk::Cell;
+use super::*;
+
+// Flat ground everywhere, except where `ground_height` says otherwise.
+struct TestGen {
+    spec: Spec,
+    ground_height: fn(GridPoint3) -> GridCoord,
+}
+
+impl WorldGen for TestGen {
+    fn land_height(&self, column: GridPoint2) -> f64 {
+        let z = (self.ground_height)(GridPoint3::new(column.root, column.x, column.y, 0));
+        self.spec.floor_radius + self.spec.block_height * z as f64
+    }
+
+    fn cell_at(&self, grid_point: GridPoint3) -> Cell {
+        let material = if grid_point.z < (self.ground_height)(grid_point) {
+            MaterialId::DIRT
+        } else {
+            MaterialId::AIR
+        };
+        Cell {
+            material: material,
+            sky_light: 0,
+            block_light: 0,
+        }
+    }
+}
+
+const GROUND_HEIGHT: GridCoord = 5;
+
+fn flat(_pos: GridPoint3) -> GridCoord {
+    GROUND_HEIGHT
+}
+
+// Wall two cells high across the path between the start and goal used below.
+fn wall(pos: GridPoint3) -> GridCoord {
+    if pos.root.index == 1 && pos.x == 23 && pos.y >= 20 && pos.y < 50 {
+        GROUND_HEIGHT + 2
+    } else {
+        GROUND_HEIGHT
+    }
+}
+
+// Hole too deep to climb out of, right where we start.
+fn pit(pos: GridPoint3) -> GridCoord {
+    if pos.root.index == 1 && pos.x == 20 && pos.y == 35 {
+        GROUND_HEIGHT - 3
+    } else {
+        GROUND_HEIGHT
+    }
+}
+
+fn globe_with_ground(ground_height: fn(GridPoint3) -> GridCoord) -> Globe {
+    let spec = Globe::new_example().spec();
+    Globe::new(
+        spec,
+        Box::new(TestGen {
+            spec: spec,
+            ground_height: ground_height,
+        }),
+    )
+}
+
+fn start() -> GridPoint3 {
+    GridPoint3::new(1.into(), 20, 35, GROUND_HEIGHT)
+}
+
+fn goal() -> GridPoint3 {
+    GridPoint3::new(1.into(), 26, 35, GROUND_HEIGHT)
+}
+
+// Make sure each step is into a neighboring column, like a `CellDweller` would take.
+fn assert_path_is_walkable(globe: &Globe, start: GridPoint3, path: &[GridPoint3]) {
+    let root_resolution = globe.spec().root_resolution;
+    let mut pos = start;
+    for &next_pos in path {
+        let next_column = PosInOwningRoot::new(next_pos.with_z(0), root_resolution);
+        let is_neighbor = Neighbors::new(pos.with_z(0), root_resolution).any(|neighbor| {
+            PosInOwningRoot::new(neighbor, root_resolution) == next_column
+        });
+        assert!(is_neighbor, "{:?} isn't next to {:?}", next_pos, pos);
+        pos = next_pos;
+    }
+}
+
+#[test]
+fn straight_path_on_flat_ground() {
+    let mut globe = globe_with_ground(flat);
+    let path = Pathfinder::default()
+        .find_path(&mut globe, start(), goal())
+        .unwrap();
+    assert_eq!(6, path.len());
+    assert_eq!(Some(&goal()), path.last());
+    assert!(path.iter().all(|pos| pos.z == GROUND_HEIGHT));
+    assert_path_is_walkable(&globe, start(), &path);
+
+    // Starting from up in the air should fall to the ground first.
+    let path = Pathfinder::default()
+        .find_path(&mut globe, start().with_z(20), goal().with_z(20))
+        .unwrap();
+    assert_eq!(6, path.len());
+    assert_eq!(Some(&goal()), path.last());
+}
+
+#[test]
+fn path_across_root_seam() {
+    let mut globe = globe_with_ground(flat);
+    let root_resolution = globe.spec().root_resolution;
+    let start = GridPoint3::new(1.into(), 3, 40, GROUND_HEIGHT);
+
+    // Find a goal three cells into the next root across the x == 0 edge.
+    let edge = start.with_x(0);
+    let goal = EquivalentPoints::new(edge, root_resolution)
+        .find(|pos| pos.root != edge.root)
+        .map(|pos| if pos.y == 0 {
+            pos.with_y(3)
+        } else {
+            pos.with_x(pos.x - 3)
+        })
+        .expect("Edge should be shared with another root");
+
+    let path = Pathfinder::default()
+        .find_path(&mut globe, start, goal)
+        .unwrap();
+    assert_eq!(6, path.len());
+    assert_eq!(Some(&goal), path.last());
+    assert_path_is_walkable(&globe, start, &path);
+}
+
+#[test]
+fn climb_over_or_go_around_walls() {
+    let mut globe = globe_with_ground(wall);
+
+    // Too high to climb by default, so we have to go the long way around.
+    let path = Pathfinder::default()
+        .find_path(&mut globe, start(), goal())
+        .unwrap();
+    assert!(path.len() > 20);
+    assert_eq!(Some(&goal()), path.last());
+    assert!(path.iter().all(|pos| pos.z == GROUND_HEIGHT));
+    assert_path_is_walkable(&globe, start(), &path);
+
+    // If we can climb higher, then straight over the top is quicker.
+    let pathfinder = Pathfinder {
+        max_step_height: 2,
+        ..Pathfinder::default()
+    };
+    let path = pathfinder.find_path(&mut globe, start(), goal()).unwrap();
+    assert_eq!(6, path.len());
+    assert_eq!(Some(GROUND_HEIGHT + 2), path.iter().map(|pos| pos.z).max());
+    assert_path_is_walkable(&globe, start(), &path);
+}
+
+#[test]
+fn no_way_out_of_pit() {
+    let mut globe = globe_with_ground(pit);
+    assert_eq!(
+        Err(PathfindingError::NoPath),
+        Pathfinder::default().find_path(&mut globe, start(), goal())
+    );
+}
+
+#[test]
+fn give_up_when_over_budget() {
+    let mut globe = globe_with_ground(wall);
+    let pathfinder = Pathfinder {
+        max_cells_explored: 10,
+        ..Pathfinder::default()
+    };
+    assert_eq!(
+        Err(PathfindingError::TooManyCellsExplored),
+        pathfinder.find_path(&mut globe, start(), goal())
+    );
+
+    let mut globe = globe_with_ground(flat);
+    let pathfinder = Pathfinder {
+        max_chunks_to_load: 0,
+        ..Pathfinder::default()
+    };
+    match pathfinder.find_path(&mut globe, start(), goal()) {
+        Err(PathfindingError::ChunkBudgetExhausted(_)) => (),
+        other => panic!("Expected to run out of chunk budget, but got {:?}", other),
+    }
+}
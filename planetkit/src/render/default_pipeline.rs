@@ -34,6 +34,8 @@ gfx_pipeline!(
     pipe {
         vbuf: gfx::VertexBuffer<Vertex> = (),
         u_model_view_proj: gfx::Global<[[f32; 4]; 4]> = "u_model_view_proj",
+        u_model_to_globe: gfx::Global<[[f32; 4]; 4]> = "u_model_to_globe",
+        u_sun_dir: gfx::Global<[f32; 3]> = "u_sun_dir",
         t_color: gfx::TextureSampler<[f32; 4]> = "t_color",
         out_color: gfx::RenderTarget<gfx::format::Srgba8> = "o_color",
         out_depth: gfx::DepthTarget<gfx::format::DepthStencil> =
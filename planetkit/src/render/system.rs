@@ -16,6 +16,8 @@ use super::Visual;
 use super::MeshRepository;
 use Spatial;
 use camera::DefaultCamera;
+use globe::Globe;
+use day_night::TimeOfDay;
 
 // System to render all visible entities. This is back-end agnostic;
 // i.e. nothing in it should be tied to OpenGL, Vulkan, etc.
@@ -47,8 +49,9 @@ impl<R: gfx::Resources, C: gfx::CommandBuffer<R>> System<R, C> {
         let log = parent_log.new(o!("system" => "render"));
         debug!(log, "Initialising");
 
-        // Ensure DefaultCamera resource is present.
+        // Ensure DefaultCamera and TimeOfDay resources are present.
         DefaultCamera::ensure(world);
+        TimeOfDay::ensure(world);
 
         // Create pipeline state object.
         use gfx::traits::FactoryExt;
@@ -81,6 +84,8 @@ impl<R: gfx::Resources, C: gfx::CommandBuffer<R>> System<R, C> {
         entities: &specs::Entities<'a>,
         visuals: &specs::ReadStorage<'a, Visual>,
         spatials: &specs::ReadStorage<'a, Spatial>,
+        globes: &specs::ReadStorage<'a, Globe>,
+        time_of_day: &TimeOfDay,
         camera: specs::Entity,
     ) {
         // TODO: Systems are currently run on the main thread,
@@ -103,6 +108,12 @@ impl<R: gfx::Resources, C: gfx::CommandBuffer<R>> System<R, C> {
 
         let projection = self.projection.lock().unwrap();
         let mut mesh_repo = self.mesh_repo.lock().unwrap();
+        let sun_direction = time_of_day.sun_direction();
+        let sun_direction = [
+            sun_direction.x as f32,
+            sun_direction.y as f32,
+            sun_direction.z as f32,
+        ];
 
         // Try to draw all visuals.
         use specs::Join;
@@ -115,6 +126,10 @@ impl<R: gfx::Resources, C: gfx::CommandBuffer<R>> System<R, C> {
                 continue;
             }
 
+            if visual.is_hidden() {
+                continue;
+            }
+
             // Visual might not have its mesh created yet.
             let mesh_pointer = match visual.mesh_pointer() {
                 Some(mesh_pointer) => mesh_pointer,
@@ -160,8 +175,20 @@ impl<R: gfx::Resources, C: gfx::CommandBuffer<R>> System<R, C> {
                 *projection,
             );
 
+            // Shade things on a globe according to where the sun is over it.
+            let (model_to_globe, sun_dir) = match globe_containing(entity, spatials, globes) {
+                Some(globe_entity) => {
+                    let model_to_globe: Isometry3<f32> =
+                        na::convert(spatials.a_relative_to_ancestor_b(entity, globe_entity));
+                    (model_to_globe.to_homogeneous().into(), sun_direction)
+                }
+                None => (vecmath::mat4_id(), [0.0; 3]),
+            };
+
             let mesh = mesh_repo.get_mut(mesh_pointer);
             mesh.data_mut().u_model_view_proj = model_view_projection;
+            mesh.data_mut().u_model_to_globe = model_to_globe;
+            mesh.data_mut().u_sun_dir = sun_dir;
             encoder.draw(mesh.slice(), &self.pso, mesh.data());
         }
 
@@ -176,17 +203,26 @@ where
 {
     type SystemData = (Entities<'a>,
      Fetch<'a, DefaultCamera>,
+     Fetch<'a, TimeOfDay>,
      ReadStorage<'a, Visual>,
-     ReadStorage<'a, Spatial>);
+     ReadStorage<'a, Spatial>,
+     ReadStorage<'a, Globe>);
 
     fn run(&mut self, data: Self::SystemData) {
-        let (entities, default_camera, visuals, spatials) = data;
+        let (entities, default_camera, time_of_day, visuals, spatials, globes) = data;
 
         if let Some(camera_entity) = default_camera.camera_entity {
             // Camera must have been realized.
             // TODO: there's got to be a better pattern than this...
             if spatials.get(camera_entity).is_some() {
-                self.draw(&entities, &visuals, &spatials, camera_entity);
+                self.draw(
+                    &entities,
+                    &visuals,
+                    &spatials,
+                    &globes,
+                    &time_of_day,
+                    camera_entity,
+                );
             }
         }
 
@@ -195,3 +231,19 @@ where
         // See https://github.com/PistonDevelopers/piston/issues/193
     }
 }
+
+// The nearest of `entity` and its ancestors that is a `Globe`, if any.
+fn globe_containing<'a>(
+    entity: specs::Entity,
+    spatials: &specs::ReadStorage<'a, Spatial>,
+    globes: &specs::ReadStorage<'a, Globe>,
+) -> Option<specs::Entity> {
+    let mut maybe_entity = Some(entity);
+    while let Some(entity) = maybe_entity {
+        if globes.get(entity).is_some() {
+            return Some(entity);
+        }
+        maybe_entity = spatials.get(entity).and_then(|spatial| spatial.parent_entity());
+    }
+    None
+}
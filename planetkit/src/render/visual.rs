@@ -15,6 +15,9 @@ pub struct Visual {
     // actual mesh whenever this is present.
     // TODO: privacy
     pub proto_mesh: Option<ProtoMesh>,
+    // Whether to skip drawing it for now, e.g., because something
+    // more detailed is being drawn in its place.
+    hidden: bool,
 }
 
 impl Visual {
@@ -22,6 +25,7 @@ impl Visual {
         Visual {
             mesh_pointer: None,
             proto_mesh: None,
+            hidden: false,
         }
     }
 
@@ -32,6 +36,14 @@ impl Visual {
     pub fn set_mesh_pointer(&mut self, new_mesh_pointer: froggy::Pointer<MeshWrapper>) {
         self.mesh_pointer = new_mesh_pointer.into();
     }
+
+    pub fn is_hidden(&self) -> bool {
+        self.hidden
+    }
+
+    pub fn set_hidden(&mut self, hidden: bool) {
+        self.hidden = hidden;
+    }
 }
 
 impl specs::Component for Visual {
@@ -12,6 +12,8 @@ use types::*;
 use window;
 use app;
 use globe;
+use day_night;
+use orbit;
 use cell_dweller;
 use render;
 use super::LogResource;
@@ -76,7 +78,10 @@ pub fn new_empty<F: CreateSystemsFn<'static, 'static>>(
     world.register::<::render::Visual>();
     world.register::<::Spatial>();
     world.register::<::globe::Globe>();
+    world.register::<::globe::ChunkLoadAnchor>();
     world.register::<::globe::ChunkView>();
+    world.register::<::globe::LodView>();
+    world.register::<::orbit::Orbit>();
 
     // Initialize common resources.
     // These should be impossible to create from
@@ -102,13 +107,32 @@ pub fn new_empty<F: CreateSystemsFn<'static, 'static>>(
     );
 
     use globe;
-    let chunk_sys = globe::ChunkSystem::new(&log);
+    let chunk_sys = globe::ChunkSystem::new(
+        &log,
+        3, // Threads to generate chunks on
+    );
+
+    let water_flow_sys = globe::WaterFlowSystem::new(
+        &log,
+        1000, // Max cells to look at per tick
+    );
+
+    let day_night_sys = day_night::DayNightSystem::new(&mut world, &log);
+
+    let orbit_sys = orbit::OrbitSystem::new(&mut world, &log);
 
     let chunk_view_sys = globe::ChunkViewSystem::new(
         &log,
         0.05, // Seconds between geometry creation
     );
 
+    let lod_view_sys = globe::LodViewSystem::new(
+        &mut world,
+        &log,
+        16, // Subdivisions of each icosahedron edge
+        30.0, // Altitude above which to draw globes as a whole
+    );
+
     // TODO: export some default names and priorities for these...
     let dispatcher_builder = specs::DispatcherBuilder::new()
         // Try to get stuff most directly linked to input done first
@@ -118,9 +142,13 @@ pub fn new_empty<F: CreateSystemsFn<'static, 'static>>(
         .add_barrier()
         .add(physics_sys, "physics", &[])
         .add(chunk_sys, "chunk", &[])
+        .add(water_flow_sys, "water_flow", &[])
+        .add(day_night_sys, "day_night", &[])
+        .add(orbit_sys, "orbit", &[])
         // Don't depend on chunk system; chunk view can lag happily, so we'd prefer
         // to be able to run it in parallel.
-        .add(chunk_view_sys, "chunk_view", &[]);
+        .add(chunk_view_sys, "chunk_view", &[])
+        .add(lod_view_sys, "lod_view", &[]);
 
     // Run any user-provided system creation code.
     let dispatcher_builder = create_systems(&log, &mut world, dispatcher_builder);